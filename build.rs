@@ -4,7 +4,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::io::{Error, ErrorKind, Result};
+use std::io::{Error, Result};
 use std::path::Path;
 use std::process::Command;
 
@@ -29,7 +29,7 @@ fn build_manpage<P: AsRef<Path>>(out_dir: P) -> Result<()> {
 
     let mut command = Command::new("asciidoctor");
     command
-        .args(&["-b", "manpage", "-a", "reproducible"])
+        .args(["-b", "manpage", "-a", "reproducible"])
         .arg("-o")
         .arg(target_file)
         .arg("mdcat.1.adoc");
@@ -38,10 +38,11 @@ fn build_manpage<P: AsRef<Path>>(out_dir: P) -> Result<()> {
     if result.success() {
         Ok(())
     } else {
-        Err(Error::new(
-            ErrorKind::Other,
-            format!("{:?} failed with exit code: {:?}", command, result.code()),
-        ))
+        Err(Error::other(format!(
+            "{:?} failed with exit code: {:?}",
+            command,
+            result.code()
+        )))
     }
 }
 
@@ -0,0 +1,61 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Fuzz the rendering state machine.
+//!
+//! Render arbitrary Markdown, and arbitrary unbalanced event streams derived
+//! from it, and check that mdcat never panics, and always renders the whole
+//! document if it degrades gracefully.
+//!
+//! Run with `cargo fuzz run write_event` from the top-level directory.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mdcat::{
+    Environment, ErrorRecovery, ResourcePolicy, Settings, Stylesheet, TerminalCapabilities,
+    TerminalSize,
+};
+use pulldown_cmark::{Event, Options, Parser};
+use syntect::highlighting::Theme;
+use syntect::parsing::SyntaxSet;
+
+fn render(events: &[Event], error_recovery: ErrorRecovery) -> Result<(), mdcat::Error> {
+    let settings = Settings {
+        terminal_capabilities: TerminalCapabilities::vte50(),
+        terminal_size: TerminalSize::default(),
+        resource_policy: ResourcePolicy::local_only(),
+        cache: None,
+        syntax_set: SyntaxSet::default(),
+        theme: Theme::default(),
+        stylesheet: Stylesheet::default(),
+        error_recovery,
+        diagnostics: None,
+    };
+    let env = Environment::for_local_directory(&std::env::temp_dir())?;
+    mdcat::push_tty(&settings, &env, &mut std::io::sink(), events.iter().cloned())
+}
+
+fuzz_target!(|data: &[u8]| {
+    // Markdown up to the first NUL byte, and a mask of events to drop after.
+    let (markdown, mask) = match data.iter().position(|b| *b == 0) {
+        Some(index) => (&data[..index], &data[index + 1..]),
+        None => (data, &[][..]),
+    };
+    if let Ok(markdown) = std::str::from_utf8(markdown) {
+        let events: Vec<Event> = Parser::new_ext(markdown, Options::all())
+            .enumerate()
+            .filter(|(index, _)| {
+                let bit = mask.get(index / 8).map_or(0, |byte| byte & (1 << (index % 8)));
+                bit == 0
+            })
+            .map(|(_, event)| event)
+            .collect();
+        // Failing is fine, as long as we do not panic
+        let _ = render(&events, ErrorRecovery::Fail);
+        render(&events, ErrorRecovery::Degrade).expect("Failed to degrade gracefully");
+    }
+});
@@ -36,9 +36,37 @@ Report issues to <https://github.com/lunaryorn/mdcat>.",
         .arg(
             Arg::with_name("filenames")
                 .multiple(true)
-                .help("The file to read.  If - read from standard input instead")
+                .help("The file or HTTP(S) URL to read.  If - read from standard input instead")
                 .default_value("-"),
         )
+        .arg(
+            Arg::with_name("follow")
+                .long("follow")
+                .help("Also render local Markdown files linked from FILE, like a book"),
+        )
+        .arg(
+            Arg::with_name("section")
+                .long("section")
+                .value_name("HEADING")
+                .help("Only render the section under the heading which best matches HEADING")
+                .conflicts_with("follow"),
+        )
+        .arg(
+            Arg::with_name("toc")
+                .long("toc")
+                .help("Print a table of contents before each document"),
+        )
+        .arg(
+            Arg::with_name("toc_only")
+                .long("toc-only")
+                .help("Only print a table of contents of each document")
+                .overrides_with("toc"),
+        )
+        .arg(
+            Arg::with_name("html")
+                .long("html")
+                .help("Write a HTML document which looks like the output in a terminal"),
+        )
         .arg(
             Arg::with_name("no_colour")
                 .short("c")
@@ -52,12 +80,118 @@ Report issues to <https://github.com/lunaryorn/mdcat>.",
                 .help("Maximum number of columns to use for output")
                 .default_value(default_columns),
         )
+        .arg(
+            Arg::with_name("theme")
+                .long("theme")
+                .value_name("THEME")
+                .help("The theme for syntax highlighting of code blocks; see --list-themes")
+                .default_value("Solarized (dark)"),
+        )
+        .arg(
+            Arg::with_name("list_themes")
+                .long("list-themes")
+                .help("List all available themes for syntax highlighting and exit"),
+        )
+        .arg(
+            Arg::with_name("stylesheet")
+                .long("stylesheet")
+                .value_name("FILE")
+                .help("Load the style of Markdown elements from the given TOML file"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("FILE")
+                .help("Read configuration from the given TOML file"),
+        )
+        .arg(
+            Arg::with_name("print_config")
+                .long("print-config")
+                .help("Print the effective configuration and exit"),
+        )
+        .arg(
+            Arg::with_name("image_protocol")
+                .long("image-protocol")
+                .value_name("PROTOCOL")
+                .possible_values(&[
+                    "auto",
+                    "none",
+                    "sixel",
+                    "kitty",
+                    "iterm2",
+                    "terminology",
+                    "half-blocks",
+                ])
+                .help("The protocol for inline images; detect the protocol by default"),
+        )
         .arg(
             Arg::with_name("local_only")
                 .short("l")
                 .long("local")
                 .help("Do not load remote resources like images"),
         )
+        .arg(
+            Arg::with_name("schemes")
+                .long("schemes")
+                .value_name("SCHEMES")
+                .use_delimiter(true)
+                .possible_values(&["file", "http", "https"])
+                .help("Comma-separated URL schemes to load resources from"),
+        )
+        .arg(
+            Arg::with_name("allow_host")
+                .long("allow-host")
+                .value_name("HOST")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only load remote resources from HOST and its subdomains"),
+        )
+        .arg(
+            Arg::with_name("deny_host")
+                .long("deny-host")
+                .value_name("HOST")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Do not load remote resources from HOST and its subdomains"),
+        )
+        .arg(
+            Arg::with_name("local_root")
+                .long("local-root")
+                .value_name("DIR")
+                .help(
+                    "Only load local resources from DIR; the directory of the document by default",
+                ),
+        )
+        .arg(
+            Arg::with_name("size_limit")
+                .long("size-limit")
+                .value_name("SIZE")
+                .help(
+                    "Do not load resources larger than SIZE bytes, with optional K, M or G suffix",
+                ),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .value_name("SECONDS")
+                .help("Give up loading remote resources after SECONDS"),
+        )
+        .arg(
+            Arg::with_name("retries")
+                .long("retries")
+                .value_name("N")
+                .help("Retry failed requests for remote resources N times"),
+        )
+        .arg(
+            Arg::with_name("no_cache")
+                .long("no-cache")
+                .help("Do not cache remote resources and rendered images"),
+        )
+        .arg(
+            Arg::with_name("clear_cache")
+                .long("clear-cache")
+                .help("Remove all cached resources and images and exit"),
+        )
         .arg(
             Arg::with_name("dump_events")
                 .long("dump-events")
@@ -69,6 +203,12 @@ Report issues to <https://github.com/lunaryorn/mdcat>.",
                 .long("fail")
                 .help("Exit immediately if any error occurs processing an input file"),
         )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .help("Print warnings, e.g. about images which mdcat cannot show"),
+        )
         .arg(
             Arg::with_name("detect_only")
                 .long("detect-only")
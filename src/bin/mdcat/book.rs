@@ -0,0 +1,252 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Render local documents linked from an index document as a book.
+
+use mdcat::{ResourcePolicy, UrlBase};
+use pulldown_cmark::{Event, Parser, Tag};
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use crate::markdown_options;
+
+/// A document in a book.
+pub struct Chapter {
+    /// The absolute path of the document.
+    pub path: PathBuf,
+    /// The anchor to refer to this document with.
+    pub anchor: String,
+    /// The title of this document in the book.
+    pub title: String,
+    /// The contents of the document.
+    pub input: String,
+}
+
+/// A book of documents.
+pub struct Book {
+    /// The documents of the book, in link order.
+    pub chapters: Vec<Chapter>,
+    /// The anchors of all chapters by every URL which refers to them.
+    pub anchors: HashMap<Url, String>,
+}
+
+/// Whether `url` refers to a Markdown file.
+fn is_markdown(url: &Url) -> bool {
+    let path = url.path().to_ascii_lowercase();
+    path.ends_with(".md") || path.ends_with(".markdown")
+}
+
+/// Get all local Markdown files `input` links to.
+///
+/// Resolve links against the directory of `path`, and return only links which
+/// `policy` permits, relative to `root_url`.
+fn linked_documents(input: &str, path: &Path, policy: &ResourcePolicy, root_url: &Url) -> Vec<Url> {
+    let base_url = match path
+        .parent()
+        .and_then(|dir| Url::from_directory_path(dir).ok())
+    {
+        Some(url) => url,
+        None => return Vec::new(),
+    };
+    Parser::new_ext(input, markdown_options())
+        .filter_map(|event| match event {
+            Event::Start(Tag::Link(_, target, _)) => base_url.resolve_reference(&target),
+            _ => None,
+        })
+        .map(|mut url| {
+            url.set_fragment(None);
+            url
+        })
+        .filter(|url| url.scheme() == "file" && is_markdown(url) && policy.permits(url, root_url))
+        .collect()
+}
+
+/// The visible title of the document at `path` within a book rooted at `root`.
+fn title(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+impl Book {
+    /// Collect the book which starts at the index document at `path`.
+    ///
+    /// Walk links to local Markdown files depth first, and add every document
+    /// once, in the order of links.  Only follow links `policy` permits for the
+    /// index document.
+    pub fn collect(path: &Path, policy: &ResourcePolicy) -> Result<Book> {
+        let index = path.canonicalize()?;
+        let root = index.parent().unwrap_or_else(|| Path::new("/"));
+        let root_url = Url::from_directory_path(root).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid directory {}", root.display()),
+            )
+        })?;
+        let mut book = Book {
+            chapters: Vec::new(),
+            anchors: HashMap::new(),
+        };
+        book.add_all(index.clone(), &root_url, root, policy)?;
+        Ok(book)
+    }
+
+    /// Add the document at `index` and all documents it links to.
+    ///
+    /// Keep a stack of documents still to add instead of recursing, so that
+    /// deeply nested books cannot overflow the stack.
+    fn add_all(
+        &mut self,
+        index: PathBuf,
+        root_url: &Url,
+        root: &Path,
+        policy: &ResourcePolicy,
+    ) -> Result<()> {
+        let mut seen = HashSet::new();
+        let mut links_to_targets = Vec::new();
+        let mut pending = vec![index];
+        while let Some(path) = pending.pop() {
+            if !seen.insert(path.clone()) {
+                continue;
+            }
+            let input = std::fs::read_to_string(&path).map_err(|error| {
+                Error::new(error.kind(), format!("{}: {}", path.display(), error))
+            })?;
+            let anchor = format!("§{}", self.chapters.len() + 1);
+            if let Ok(url) = Url::from_file_path(&path) {
+                self.anchors.insert(url, anchor.clone());
+            }
+            let links = linked_documents(&input, &path, policy, root_url);
+            self.chapters.push(Chapter {
+                title: title(&path, root),
+                path,
+                anchor,
+                input,
+            });
+            let mut targets = Vec::new();
+            for url in links {
+                let target = url.to_file_path().ok().and_then(|p| p.canonicalize().ok());
+                if let Some(target) = target.filter(|target| target.is_file()) {
+                    targets.push(target.clone());
+                    links_to_targets.push((url, target));
+                }
+            }
+            // Push in reverse to add the first link next, depth first
+            pending.extend(targets.into_iter().rev());
+        }
+        // Also refer to documents by the URLs of links, which differ from the
+        // canonical URL if a link goes through a symlink.
+        for (url, target) in links_to_targets {
+            let anchor = Url::from_file_path(&target)
+                .ok()
+                .and_then(|target_url| self.anchors.get(&target_url).cloned());
+            if let Some(anchor) = anchor {
+                self.anchors.insert(url, anchor);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// Write `files` to a fresh directory for the test `name`.
+    fn write_book(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("mdcat-book-{}-{}", name, std::process::id()));
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+        for (file, contents) in files {
+            let path = directory.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        directory.canonicalize().unwrap()
+    }
+
+    fn titles(book: &Book) -> Vec<&str> {
+        book.chapters.iter().map(|c| c.title.as_str()).collect()
+    }
+
+    #[test]
+    fn collect_linked_documents_depth_first() {
+        let directory = write_book(
+            "collect",
+            &[
+                ("index.md", "[A](a.md) and [B](sub/b.markdown#section)"),
+                ("a.md", "[C](c.md)"),
+                ("c.md", "The end"),
+                ("sub/b.markdown", "[Up](../c.md)"),
+            ],
+        );
+        let book = Book::collect(&directory.join("index.md"), &ResourcePolicy::default()).unwrap();
+        assert_eq!(
+            titles(&book),
+            vec!["index.md", "a.md", "c.md", "sub/b.markdown"]
+        );
+        let anchors: Vec<&str> = book.chapters.iter().map(|c| c.anchor.as_str()).collect();
+        assert_eq!(anchors, vec!["§1", "§2", "§3", "§4"]);
+        assert_eq!(book.chapters[2].input, "The end");
+        let c = Url::from_file_path(directory.join("c.md")).unwrap();
+        assert_eq!(book.anchors.get(&c).map(String::as_str), Some("§3"));
+    }
+
+    #[test]
+    fn add_each_document_once() {
+        let directory = write_book(
+            "cycle",
+            &[
+                ("index.md", "[A](a.md) [B](b.md) [A again](a.md)"),
+                ("a.md", "[B](b.md) [Index](index.md)"),
+                ("b.md", "[A](a.md)"),
+            ],
+        );
+        let book = Book::collect(&directory.join("index.md"), &ResourcePolicy::default()).unwrap();
+        assert_eq!(titles(&book), vec!["index.md", "a.md", "b.md"]);
+    }
+
+    #[test]
+    fn skip_remote_missing_and_other_links() {
+        let directory = write_book(
+            "skip",
+            &[
+                (
+                    "index.md",
+                    "[Remote](https://example.com/remote.md) [Text](notes.txt) \
+                     [Image](logo.png) [Missing](missing.md) [Anchor](#heading) [A](a.md)",
+                ),
+                ("notes.txt", "[A](a.md)"),
+                ("a.md", "A"),
+            ],
+        );
+        let book = Book::collect(&directory.join("index.md"), &ResourcePolicy::default()).unwrap();
+        assert_eq!(titles(&book), vec!["index.md", "a.md"]);
+    }
+
+    #[test]
+    fn skip_documents_outside_of_the_book() {
+        let directory = write_book(
+            "outside",
+            &[
+                ("outside.md", "Outside"),
+                ("book/index.md", "[Outside](../outside.md)"),
+            ],
+        );
+        let book = Book::collect(
+            &directory.join("book").join("index.md"),
+            &ResourcePolicy::default(),
+        )
+        .unwrap();
+        assert_eq!(titles(&book), vec!["index.md"]);
+    }
+}
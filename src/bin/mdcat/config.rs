@@ -0,0 +1,389 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The configuration file of mdcat.
+
+use anyhow::anyhow;
+use anyhow::{Context, Result};
+use mdcat::{ColourLevel, HttpSettings, ImageCapability, ResourcePolicy, TerminalCapabilities};
+use serde::{Deserialize, Serialize};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::dirs::config_dir;
+
+/// Which capabilities of the terminal to use.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capabilities {
+    /// Detect the capabilities of the terminal.
+    Auto,
+    /// Only use standard ANSI formatting.
+    Ansi,
+    /// Use no formatting at all.
+    None,
+}
+
+/// Which colours to use.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Colours {
+    /// Detect the colours the terminal supports.
+    Auto,
+    /// Use the 16 basic ANSI colours.
+    Ansi16,
+    /// Use the 256 colours of the xterm palette.
+    Ansi256,
+    /// Use 24 bit colours.
+    Truecolour,
+}
+
+/// How to show inline images.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageProtocol {
+    /// Detect the image protocol of the terminal.
+    Auto,
+    /// Do not show inline images.
+    None,
+    /// Show images with Sixel graphics.
+    Sixel,
+    /// Show images with the Kitty protocol.
+    Kitty,
+    /// Show images with the iTerm2 protocol.
+    Iterm2,
+    /// Show images with the Terminology protocol.
+    Terminology,
+    /// Draw images with Unicode half blocks.
+    HalfBlocks,
+}
+
+impl ImageProtocol {
+    /// Parse an image protocol from its `name`.
+    pub fn from_name(name: &str) -> Option<ImageProtocol> {
+        match name {
+            "auto" => Some(ImageProtocol::Auto),
+            "none" => Some(ImageProtocol::None),
+            "sixel" => Some(ImageProtocol::Sixel),
+            "kitty" => Some(ImageProtocol::Kitty),
+            "iterm2" => Some(ImageProtocol::Iterm2),
+            "terminology" => Some(ImageProtocol::Terminology),
+            "half-blocks" => Some(ImageProtocol::HalfBlocks),
+            _ => None,
+        }
+    }
+}
+
+/// The configuration of mdcat.
+///
+/// Every setting is optional, to merge configuration from the command line,
+/// the environment and the configuration file in order of precedence.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// Maximum number of columns to use for output.
+    pub columns: Option<usize>,
+    /// Whether to only access local resources.
+    pub local: Option<bool>,
+    /// The URL schemes to read resources from.
+    pub schemes: Option<Vec<String>>,
+    /// The hosts to load remote resources from; empty to permit all hosts.
+    pub allow_hosts: Option<Vec<String>>,
+    /// The hosts not to load remote resources from.
+    pub deny_hosts: Option<Vec<String>>,
+    /// The directory local resources must be in.
+    ///
+    /// If absent use the directory of the document.
+    pub local_root: Option<PathBuf>,
+    /// The maximum size of a resource in bytes.
+    pub size_limit: Option<u64>,
+    /// The timeout for remote resources in seconds.
+    pub timeout: Option<u64>,
+    /// How often to retry failed requests for remote resources.
+    pub retries: Option<u32>,
+    /// Whether to cache remote resources and rendered images.
+    pub cache: Option<bool>,
+    /// Whether to paginate output.
+    pub paginate: Option<bool>,
+    /// The pager command, as a shell command line.
+    pub pager: Option<String>,
+    /// Whether to exit immediately if an input file fails.
+    pub fail: Option<bool>,
+    /// The theme for syntax highlighting.
+    pub theme: Option<String>,
+    /// The path to a stylesheet.
+    pub stylesheet: Option<PathBuf>,
+    /// Which terminal capabilities to use.
+    pub capabilities: Option<Capabilities>,
+    /// Which colours to use.
+    pub colours: Option<Colours>,
+    /// How to show inline images.
+    pub image_protocol: Option<ImageProtocol>,
+}
+
+impl Config {
+    /// Load configuration from the TOML file at `path`.
+    ///
+    /// Resolve relative stylesheet and local root paths against the directory
+    /// of `path`.
+    pub fn load(path: &Path) -> Result<Config> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read configuration from {}", path.display()))?;
+        let config: Config = toml::from_str(&contents)
+            .with_context(|| format!("Invalid configuration in {}", path.display()))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Ok(Config {
+            stylesheet: config.stylesheet.map(|file| base_dir.join(file)),
+            local_root: config.local_root.map(|dir| base_dir.join(dir)),
+            ..config
+        })
+    }
+
+    /// Load the configuration file.
+    ///
+    /// Load `path` if given, otherwise load `config.toml` from the
+    /// configuration directory of mdcat, if it exists.
+    pub fn load_file(path: Option<&Path>) -> Result<Config> {
+        match path {
+            Some(path) => Self::load(path),
+            None => match default_config_file().filter(|file| file.is_file()) {
+                Some(file) => Self::load(&file),
+                None => Ok(Config::default()),
+            },
+        }
+    }
+
+    /// Default configuration for a terminal with the given number of `columns`.
+    pub fn defaults(columns: usize) -> Config {
+        let policy = ResourcePolicy::default();
+        Config {
+            columns: Some(columns),
+            local: Some(false),
+            schemes: Some(policy.schemes),
+            allow_hosts: Some(Vec::new()),
+            deny_hosts: Some(Vec::new()),
+            local_root: None,
+            size_limit: Some(policy.size_limit),
+            timeout: Some(policy.timeout.as_secs()),
+            retries: Some(HttpSettings::default().retries),
+            cache: Some(true),
+            paginate: Some(false),
+            pager: Some("less -R".to_string()),
+            fail: Some(false),
+            theme: Some("Solarized (dark)".to_string()),
+            stylesheet: None,
+            capabilities: Some(Capabilities::Auto),
+            colours: Some(Colours::Auto),
+            image_protocol: Some(ImageProtocol::Auto),
+        }
+    }
+
+    /// Fill settings missing in this configuration from `other`.
+    pub fn or(self, other: Config) -> Config {
+        Config {
+            columns: self.columns.or(other.columns),
+            local: self.local.or(other.local),
+            schemes: self.schemes.or(other.schemes),
+            allow_hosts: self.allow_hosts.or(other.allow_hosts),
+            deny_hosts: self.deny_hosts.or(other.deny_hosts),
+            local_root: self.local_root.or(other.local_root),
+            size_limit: self.size_limit.or(other.size_limit),
+            timeout: self.timeout.or(other.timeout),
+            retries: self.retries.or(other.retries),
+            cache: self.cache.or(other.cache),
+            paginate: self.paginate.or(other.paginate),
+            pager: self.pager.or(other.pager),
+            fail: self.fail.or(other.fail),
+            theme: self.theme.or(other.theme),
+            stylesheet: self.stylesheet.or(other.stylesheet),
+            capabilities: self.capabilities.or(other.capabilities),
+            colours: self.colours.or(other.colours),
+            image_protocol: self.image_protocol.or(other.image_protocol),
+        }
+    }
+
+    /// The terminal capabilities to use according to this configuration.
+    pub fn terminal_capabilities(&self) -> TerminalCapabilities {
+        let paginate = self.paginate.unwrap_or(false);
+        let capabilities = match self.capabilities.unwrap_or(Capabilities::Auto) {
+            Capabilities::None => TerminalCapabilities::none(),
+            Capabilities::Ansi => TerminalCapabilities::ansi(),
+            // A pager won't support any terminal-specific features, but passes
+            // colours through
+            Capabilities::Auto if paginate => TerminalCapabilities::ansi()
+                .with_colours(ColourLevel::detect().unwrap_or(ColourLevel::Ansi16)),
+            // Only ask the terminal about Sixel graphics if we talk to the
+            // terminal directly, and nobody chose an image protocol already
+            Capabilities::Auto
+                if self.image_protocol.unwrap_or(ImageProtocol::Auto) == ImageProtocol::Auto
+                    && std::io::stdin().is_terminal()
+                    && std::io::stdout().is_terminal() =>
+            {
+                TerminalCapabilities::detect().detect_sixel()
+            }
+            Capabilities::Auto => TerminalCapabilities::detect(),
+        };
+        let capabilities = match self.colours.unwrap_or(Colours::Auto) {
+            Colours::Auto => capabilities,
+            Colours::Ansi16 => capabilities.with_colours(ColourLevel::Ansi16),
+            Colours::Ansi256 => capabilities.with_colours(ColourLevel::Ansi256),
+            Colours::Truecolour => capabilities.with_colours(ColourLevel::TrueColour),
+        };
+        match self.image_protocol.unwrap_or(ImageProtocol::Auto) {
+            ImageProtocol::Auto => capabilities,
+            ImageProtocol::None => capabilities.with_image(None),
+            ImageProtocol::Sixel => capabilities.with_image(Some(ImageCapability::sixel())),
+            ImageProtocol::Kitty => capabilities.with_image(Some(ImageCapability::kitty())),
+            ImageProtocol::Iterm2 => capabilities.with_image(Some(ImageCapability::iterm2())),
+            ImageProtocol::Terminology => {
+                capabilities.with_image(Some(ImageCapability::terminology()))
+            }
+            ImageProtocol::HalfBlocks => {
+                capabilities.with_image(Some(ImageCapability::half_blocks()))
+            }
+        }
+    }
+
+    /// The resource policy according to this configuration.
+    pub fn resource_policy(&self) -> ResourcePolicy {
+        let defaults = ResourcePolicy::default();
+        let schemes = match self.local {
+            Some(true) => vec!["file".to_string()],
+            _ => self.schemes.clone().unwrap_or(defaults.schemes),
+        };
+        ResourcePolicy {
+            schemes,
+            allowed_hosts: self.allow_hosts.clone().unwrap_or_default(),
+            denied_hosts: self.deny_hosts.clone().unwrap_or_default(),
+            local_root: self.local_root.clone(),
+            size_limit: self.size_limit.unwrap_or(defaults.size_limit),
+            timeout: self.timeout.map_or(defaults.timeout, Duration::from_secs),
+        }
+    }
+
+    /// The HTTP settings according to this configuration.
+    pub fn http_settings(&self) -> HttpSettings {
+        let defaults = HttpSettings::default();
+        HttpSettings {
+            retries: self.retries.unwrap_or(defaults.retries),
+            ..defaults
+        }
+    }
+
+    /// Format this configuration as TOML.
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).context("Failed to format configuration")
+    }
+}
+
+/// Parse a size in bytes, with an optional binary unit suffix.
+///
+/// Understand `K`, `M`, and `G` for KiB, MiB and GiB respectively.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let (number, factor) = match size.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&size[..size.len() - 1], 1 << 10),
+        Some('M') => (&size[..size.len() - 1], 1 << 20),
+        Some('G') => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(factor))
+        .ok_or_else(|| anyhow!("Invalid size: {}", size))
+}
+
+/// The default configuration file.
+///
+/// This is `config.toml` in the configuration directory of mdcat.
+pub fn default_config_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// Write `contents` to a configuration file for the test `name`.
+    fn write_config(name: &str, contents: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("mdcat-config-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("config.toml");
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn command_line_takes_precedence_over_file() {
+        let command_line = Config {
+            columns: Some(40),
+            theme: Some("Monokai".to_string()),
+            ..Config::default()
+        };
+        let file = Config {
+            columns: Some(100),
+            local: Some(true),
+            ..Config::default()
+        };
+        let config = command_line.or(file).or(Config::defaults(80));
+        assert_eq!(config.columns, Some(40));
+        assert_eq!(config.theme.as_deref(), Some("Monokai"));
+        assert_eq!(config.local, Some(true));
+        assert_eq!(config.pager.as_deref(), Some("less -R"));
+    }
+
+    #[test]
+    fn resolve_relative_paths_against_config_file() {
+        let path = write_config(
+            "paths",
+            "stylesheet = \"style.toml\"\nlocal-root = \"docs\"\ncolumns = 60\n",
+        );
+        let directory = path.parent().unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.stylesheet, Some(directory.join("style.toml")));
+        assert_eq!(config.local_root, Some(directory.join("docs")));
+        assert_eq!(config.columns, Some(60));
+    }
+
+    #[test]
+    fn keep_absolute_paths_in_config_file() {
+        let path = write_config("absolute", "local-root = \"/srv/docs\"\n");
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.local_root, Some(PathBuf::from("/srv/docs")));
+    }
+
+    #[test]
+    fn reject_unknown_keys() {
+        let path = write_config("unknown", "colums = 60\n");
+        let error = Config::load(&path).unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("unknown field `colums`"),
+            "{:#}",
+            error
+        );
+    }
+
+    #[test]
+    fn parse_sizes_with_suffixes() {
+        assert_eq!(parse_size("1024").unwrap(), 1024);
+        assert_eq!(parse_size("2K").unwrap(), 2048);
+        assert_eq!(parse_size("2k").unwrap(), 2048);
+        assert_eq!(parse_size(" 3 M ").unwrap(), 3 << 20);
+        assert_eq!(parse_size("1G").unwrap(), 1 << 30);
+    }
+
+    #[test]
+    fn reject_invalid_sizes() {
+        for size in &["", "K", "-1", "1.5M", "1T", "ten", "99999999999999999999G"] {
+            assert!(parse_size(size).is_err(), "{}", size);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Standard directories for mdcat.
+
+use std::path::PathBuf;
+
+/// Get an absolute path from the environment variable `name`.
+fn absolute_path_from_env(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+/// The directory for the configuration of mdcat.
+///
+/// On Windows this is `%APPDATA%\mdcat`.  On other systems we follow the XDG
+/// basedir spec and use `$XDG_CONFIG_HOME/mdcat`, falling back to
+/// `~/.config/mdcat`.
+pub fn config_dir() -> Option<PathBuf> {
+    let base_dir = if cfg!(windows) {
+        absolute_path_from_env("APPDATA")
+    } else {
+        absolute_path_from_env("XDG_CONFIG_HOME")
+            .or_else(|| absolute_path_from_env("HOME").map(|home| home.join(".config")))
+    };
+    base_dir.map(|dir| dir.join("mdcat"))
+}
+
+/// The directory for the cache of mdcat.
+///
+/// On Windows this is `%LOCALAPPDATA%\mdcat`.  On other systems we follow the
+/// XDG basedir spec and use `$XDG_CACHE_HOME/mdcat`, falling back to
+/// `~/.cache/mdcat`.
+pub fn cache_dir() -> Option<PathBuf> {
+    let base_dir = if cfg!(windows) {
+        absolute_path_from_env("LOCALAPPDATA")
+    } else {
+        absolute_path_from_env("XDG_CACHE_HOME")
+            .or_else(|| absolute_path_from_env("HOME").map(|home| home.join(".cache")))
+    };
+    base_dir.map(|dir| dir.join("mdcat"))
+}
@@ -10,20 +10,30 @@
 
 use clap::{value_t, values_t};
 use fehler::throws;
-use mdcat::{Environment, Settings};
-use pulldown_cmark::{Options, Parser};
+use mdcat::{
+    Cache, Diagnostics, Environment, ErrorRecovery, HttpClient, Outline, Settings, Stylesheet,
+    TerminalCapabilities,
+};
+use pulldown_cmark::{Event, Options, Parser, Tag};
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::stdin;
-use std::io::{Error, Result};
-use std::path::PathBuf;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
 use syntect::parsing::SyntaxSet;
+use url::Url;
 
-use crate::output::Output;
-use mdcat::{ResourceAccess, TerminalCapabilities, TerminalSize};
+use crate::book::Book;
+use crate::config::{parse_size, Capabilities, Config, ImageProtocol};
+use crate::output::{env_var, Output};
+use mdcat::TerminalSize;
 
 mod args;
+mod book;
+mod config;
+mod dirs;
 mod output;
+mod themes;
 
 /// Read input for `filename`.
 ///
@@ -49,25 +59,44 @@ fn read_input<T: AsRef<str>>(filename: T) -> (PathBuf, String) {
     }
 }
 
-fn process_file(
-    filename: &str,
-    settings: &Settings,
-    dump_events: bool,
-    output: &mut Output,
-) -> Result<()> {
-    let (base_dir, input) = read_input(filename)?;
-    let parser = Parser::new_ext(
-        &input,
-        Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH,
-    );
-    let env = Environment::for_local_directory(&base_dir)?;
-
-    if dump_events {
-        mdcat::dump_states(settings, &env, &mut output.writer(), parser)
-    } else {
-        mdcat::push_tty(settings, &env, &mut output.writer(), parser)
-    }
-    .or_else(|error| {
+/// Parse `filename` as remote URL.
+///
+/// Return `None` if `filename` is not a HTTP(S) URL.
+fn remote_url(filename: &str) -> Option<Url> {
+    Url::parse(filename)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Read the document at the remote `url`.
+///
+/// Fetch the document according to the resource policy and with the cache of
+/// `settings`, and with the HTTP client of `env`.
+#[throws]
+fn read_remote_input(url: &Url, settings: &Settings, env: &Environment) -> String {
+    let resource = mdcat::read_url(url, env, &settings.resource_policy, settings.cache.as_ref())
+        .map_err(|error| Error::other(format!("{:#}", error)))?;
+    String::from_utf8(resource.contents).map_err(|error| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Document at {} is not valid UTF-8: {}", url, error),
+        )
+    })?
+}
+
+/// The Markdown extensions mdcat enables.
+fn markdown_options() -> Options {
+    Options::ENABLE_TASKLISTS
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+}
+
+/// Ignore broken pipes in the `result` of writing output.
+///
+/// The pager or another consumer of our output may exit early.
+fn ignore_broken_pipe(result: Result<()>) -> Result<()> {
+    result.or_else(|error| {
         if error.kind() == std::io::ErrorKind::BrokenPipe {
             Ok(())
         } else {
@@ -76,16 +105,242 @@ fn process_file(
     })
 }
 
+/// Print warnings to standard error.
+#[derive(Debug)]
+struct PrintWarnings;
+
+impl Diagnostics for PrintWarnings {
+    fn warn(&self, warning: &mdcat::Error) {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+/// Whether to print a table of contents for documents.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Toc {
+    /// Print no table of contents.
+    None,
+    /// Print a table of contents before every document.
+    Before,
+    /// Print only the table of contents of every document.
+    Only,
+}
+
+/// The format to write rendered documents in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    /// Write to a terminal.
+    Terminal,
+    /// Write a HTML document which looks like the terminal output.
+    Html,
+    /// Dump parser events and rendering states, for debugging.
+    Events,
+}
+
+impl Format {
+    /// Write what this format needs before all documents to `writer`.
+    ///
+    /// `title` is the title of the output.
+    fn start(self, writer: &mut dyn Write, title: &str) -> Result<()> {
+        match self {
+            Format::Terminal | Format::Events => Ok(()),
+            Format::Html => mdcat::write_html_start(writer, title),
+        }
+    }
+
+    /// Write what this format needs after all documents to `writer`.
+    fn end(self, writer: &mut dyn Write) -> Result<()> {
+        match self {
+            Format::Terminal | Format::Events => Ok(()),
+            Format::Html => mdcat::write_html_end(writer),
+        }
+    }
+
+    /// Render the Markdown `events` to `writer` in this format.
+    fn push<'e, W: Write, I: Iterator<Item = Event<'e>>>(
+        self,
+        settings: &Settings,
+        env: &Environment,
+        writer: &mut W,
+        events: I,
+    ) -> Result<()> {
+        match self {
+            Format::Terminal => mdcat::push_tty(settings, env, writer, events),
+            Format::Html => mdcat::push_html(settings, env, writer, events),
+            Format::Events => mdcat::dump_states(settings, env, writer, events),
+        }
+        .map_err(Into::into)
+    }
+}
+
+/// Parse the Markdown `input`.
+///
+/// If `section` is given, return only the events of the section under the
+/// heading which best matches `section`, and fail if no heading matches.
+fn parse_document<'a>(input: &'a str, section: Option<&str>) -> Result<Vec<Event<'a>>> {
+    let events: Vec<Event> = Parser::new_ext(input, markdown_options()).collect();
+    match section {
+        None => Ok(events),
+        Some(query) => {
+            let outline = Outline::from_events(&events);
+            match outline.find(query) {
+                Some(index) => Ok(mdcat::section(events, index).collect()),
+                None => {
+                    let available: Vec<String> = outline
+                        .headings()
+                        .iter()
+                        .map(|heading| {
+                            let indent = "  ".repeat(heading.level as usize);
+                            format!("{}{} ({})", indent, heading.text, heading.slug)
+                        })
+                        .collect();
+                    Err(Error::new(
+                        ErrorKind::NotFound,
+                        if available.is_empty() {
+                            format!("No section matches {:?}; document has no headings", query)
+                        } else {
+                            format!(
+                                "No section matches {:?}; available sections:\n{}",
+                                query,
+                                available.join("\n")
+                            )
+                        },
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Render the Markdown `events` to `writer` in the given `format`.
+///
+/// Print a table of contents of `events` as requested by `toc`.
+fn push_document<W: Write>(
+    settings: &Settings,
+    env: &Environment,
+    writer: &mut W,
+    events: Vec<Event>,
+    toc: Toc,
+    format: Format,
+) -> Result<()> {
+    if toc != Toc::None {
+        let outline = Outline::from_events(&events);
+        if !outline.is_empty() {
+            format.push(settings, env, writer, outline.to_events().into_iter())?;
+            if toc == Toc::Before {
+                writeln!(writer)?;
+            }
+        }
+    }
+    if toc == Toc::Only {
+        Ok(())
+    } else {
+        format.push(settings, env, writer, events.into_iter())
+    }
+}
+
+/// Render the local file `filename` and all local documents it links to.
+///
+/// Write a heading with the anchor and the path of each document before its
+/// contents, and refer to the anchors of documents in links between them.
+fn process_book(
+    filename: &str,
+    settings: &Settings,
+    http: &HttpClient,
+    toc: Toc,
+    format: Format,
+    output: &mut Output,
+) -> Result<()> {
+    let book = Book::collect(Path::new(filename), &settings.resource_policy)?;
+    let mut writer = output.writer();
+    ignore_broken_pipe(
+        book.chapters
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, chapter)| {
+                let base_dir = chapter.path.parent().unwrap_or_else(|| Path::new("/"));
+                let env = Environment {
+                    http: http.clone(),
+                    anchors: book.anchors.clone(),
+                    ..Environment::for_local_directory(&base_dir)?
+                };
+                if 0 < index {
+                    writeln!(writer)?;
+                }
+                let title = format!("{} {}", chapter.anchor, chapter.title);
+                let heading = vec![
+                    Event::Start(Tag::Heading(1)),
+                    Event::Text(title.into()),
+                    Event::End(Tag::Heading(1)),
+                ];
+                format.push(settings, &env, &mut writer, heading.into_iter())?;
+                writeln!(writer)?;
+                let events = parse_document(&chapter.input, None)?;
+                push_document(settings, &env, &mut writer, events, toc, format)
+            }),
+    )
+}
+
+fn process_file(
+    filename: &str,
+    settings: &Settings,
+    http: &HttpClient,
+    toc: Toc,
+    format: Format,
+    section: Option<&str>,
+    output: &mut Output,
+) -> Result<()> {
+    let (env, input) = match remote_url(filename) {
+        // Resolve references in remote documents against the document URL
+        Some(url) => {
+            let env = Environment {
+                http: http.clone(),
+                ..Environment::for_localhost(url.clone())?
+            };
+            let input = read_remote_input(&url, settings, &env)?;
+            (env, input)
+        }
+        None => {
+            let (base_dir, input) = read_input(filename)?;
+            let env = Environment {
+                http: http.clone(),
+                ..Environment::for_local_directory(&base_dir)?
+            };
+            (env, input)
+        }
+    };
+    let events = parse_document(&input, section)?;
+    // Dump events of the whole document, regardless of the table of contents
+    let toc = if format == Format::Events {
+        Toc::None
+    } else {
+        toc
+    };
+    ignore_broken_pipe(push_document(
+        settings,
+        &env,
+        &mut output.writer(),
+        events,
+        toc,
+        format,
+    ))
+}
+
 /// Represent command line arguments.
 struct Arguments {
     filenames: Vec<String>,
-    terminal_capabilities: TerminalCapabilities,
-    resource_access: ResourceAccess,
-    columns: usize,
-    dump_events: bool,
+    /// Configuration given on the command line.
+    config: Config,
+    config_file: Option<PathBuf>,
+    list_themes: bool,
+    print_config: bool,
+    clear_cache: bool,
+    follow: bool,
+    toc: Toc,
+    format: Format,
+    section: Option<String>,
+    verbose: bool,
     detect_only: bool,
-    fail_fast: bool,
-    paginate: bool,
 }
 
 fn is_mdless() -> bool {
@@ -105,42 +360,132 @@ impl Arguments {
         }
 
         let filenames = values_t!(matches, "filenames", String)?;
-        let dump_events = matches.is_present("dump_events");
         let detect_only = matches.is_present("detect_only");
-        let fail_fast = matches.is_present("fail_fast");
-        let paginate =
-            (is_mdless() || matches.is_present("paginate")) && !matches.is_present("no_pager");
-
-        let columns = value_t!(matches, "columns", usize)?;
-        let resource_access = if matches.is_present("local_only") {
-            ResourceAccess::LocalOnly
+        let list_themes = matches.is_present("list_themes");
+        let print_config = matches.is_present("print_config");
+        let clear_cache = matches.is_present("clear_cache");
+        let follow = matches.is_present("follow");
+        let toc = if matches.is_present("toc_only") {
+            Toc::Only
+        } else if matches.is_present("toc") {
+            Toc::Before
+        } else {
+            Toc::None
+        };
+        let format = if matches.is_present("dump_events") {
+            Format::Events
+        } else if matches.is_present("html") {
+            Format::Html
         } else {
-            ResourceAccess::RemoteAllowed
+            Format::Terminal
         };
+        let section = matches.value_of("section").map(ToString::to_string);
+        let verbose = matches.is_present("verbose");
+        let config_file = matches.value_of_os("config").map(PathBuf::from);
 
-        let terminal_capabilities = if matches.is_present("no_colour") {
+        // Only take options the user gave explicitly, to let the configuration
+        // file provide defaults for everything else.
+        let explicit = |name: &str| matches.occurrences_of(name) > 0;
+        let flag = |name: &str| Some(true).filter(|_| matches.is_present(name));
+
+        let paginate = if matches.is_present("no_pager") {
+            Some(false)
+        } else if is_mdless() || matches.is_present("paginate") {
+            Some(true)
+        } else {
+            None
+        };
+        let capabilities = if matches.is_present("no_colour") {
             // If the user disabled colours assume a dumb terminal
-            TerminalCapabilities::none()
-        } else if paginate || matches.is_present("ansi_only") {
-            // A pager won't support any terminal-specific features
-            TerminalCapabilities::ansi()
+            Some(Capabilities::None)
+        } else if matches.is_present("ansi_only") {
+            Some(Capabilities::Ansi)
         } else {
-            TerminalCapabilities::detect()
+            None
+        };
+
+        let config = Config {
+            columns: explicit("columns")
+                .then(|| value_t!(matches, "columns", usize))
+                .transpose()?,
+            local: flag("local_only"),
+            schemes: matches
+                .values_of("schemes")
+                .map(|schemes| schemes.map(ToString::to_string).collect()),
+            allow_hosts: matches
+                .values_of("allow_host")
+                .map(|hosts| hosts.map(ToString::to_string).collect()),
+            deny_hosts: matches
+                .values_of("deny_host")
+                .map(|hosts| hosts.map(ToString::to_string).collect()),
+            local_root: matches.value_of_os("local_root").map(PathBuf::from),
+            size_limit: matches
+                .value_of("size_limit")
+                .map(|size| {
+                    parse_size(size).map_err(|error| {
+                        clap::Error::value_validation_auto(format!("--size-limit: {}", error))
+                    })
+                })
+                .transpose()?,
+            timeout: explicit("timeout")
+                .then(|| value_t!(matches, "timeout", u64))
+                .transpose()?,
+            retries: explicit("retries")
+                .then(|| value_t!(matches, "retries", u32))
+                .transpose()?,
+            cache: Some(false).filter(|_| matches.is_present("no_cache")),
+            paginate,
+            pager: None,
+            fail: flag("fail_fast"),
+            theme: explicit("theme")
+                .then(|| value_t!(matches, "theme", String))
+                .transpose()?,
+            stylesheet: matches.value_of_os("stylesheet").map(PathBuf::from),
+            capabilities,
+            colours: None,
+            image_protocol: matches
+                .value_of("image_protocol")
+                .and_then(ImageProtocol::from_name),
         };
 
         Ok(Arguments {
             filenames,
-            terminal_capabilities,
-            resource_access,
-            columns,
-            dump_events,
+            config,
+            config_file,
+            list_themes,
+            print_config,
+            clear_cache,
+            follow,
+            toc,
+            format,
+            section,
+            verbose,
             detect_only,
-            fail_fast,
-            paginate,
         })
     }
 }
 
+/// Get the effective configuration.
+///
+/// Merge `arguments` from the command line, the pager from `$MDCAT_PAGER`, the
+/// configuration file, and the pager from `$PAGER`, in this order of
+/// precedence, and fill all remaining settings with defaults.
+fn effective_config(arguments: &Arguments, size: TerminalSize) -> anyhow::Result<Config> {
+    let pager_from_env = |name| {
+        env_var(name).map(|pager| Config {
+            pager,
+            ..Config::default()
+        })
+    };
+    Ok(arguments
+        .config
+        .clone()
+        .or(pager_from_env("MDCAT_PAGER")?)
+        .or(Config::load_file(arguments.config_file.as_deref())?)
+        .or(pager_from_env("PAGER")?)
+        .or(Config::defaults(size.columns)))
+}
+
 fn long_version() -> &'static str {
     concat!(
         clap::crate_version!(),
@@ -162,32 +507,126 @@ fn main() {
         .get_matches();
     let arguments = Arguments::from_matches(&matches).unwrap_or_else(|e| e.exit());
 
-    if arguments.detect_only {
-        println!("Terminal: {}", arguments.terminal_capabilities.name);
+    let config = effective_config(&arguments, size).unwrap_or_else(|error| {
+        eprintln!("Error: {:#}", error);
+        std::process::exit(1);
+    });
+
+    let mut themes = themes::load_themes().unwrap_or_else(|error| {
+        eprintln!("Error: {:#}", error);
+        std::process::exit(1);
+    });
+
+    if arguments.print_config {
+        match config.to_toml() {
+            Ok(toml) => print!("{}", toml),
+            Err(error) => {
+                eprintln!("Error: {:#}", error);
+                std::process::exit(1);
+            }
+        }
+    } else if arguments.clear_cache {
+        if let Some(cache) = dirs::cache_dir().map(Cache::new) {
+            if let Err(error) = cache.clear() {
+                eprintln!(
+                    "Error: Failed to clear cache at {}: {}",
+                    cache.directory().display(),
+                    error
+                );
+                std::process::exit(1);
+            }
+        }
+    } else if arguments.detect_only {
+        println!("Terminal: {}", config.terminal_capabilities().name);
+    } else if arguments.list_themes {
+        for name in themes.themes.keys() {
+            println!("{}", name);
+        }
     } else {
-        let Arguments {
-            filenames,
-            dump_events,
-            fail_fast,
-            terminal_capabilities,
+        let format = arguments.format;
+        let terminal_capabilities = match format {
+            Format::Terminal | Format::Events => config.terminal_capabilities(),
+            Format::Html => TerminalCapabilities::html(),
+        };
+        let resource_policy = config.resource_policy();
+        let http = HttpClient::new(config.http_settings());
+        let Config {
             columns,
-            resource_access,
+            cache,
             paginate,
+            pager,
+            fail,
+            theme,
+            stylesheet,
             ..
-        } = arguments;
+        } = config;
+        // The effective configuration has defaults for all these settings.
+        let columns = columns.unwrap_or(size.columns);
+        let cache = dirs::cache_dir()
+            .filter(|_| cache.unwrap_or(true))
+            .map(Cache::new);
+        let fail_fast = fail.unwrap_or(false);
+        let follow = arguments.follow;
+        let toc = arguments.toc;
+        let verbose = arguments.verbose;
+        let section = arguments.section;
+        let filenames = arguments.filenames;
+
+        let theme = theme.unwrap_or_default();
+        let theme = themes.themes.remove(&theme).unwrap_or_else(|| {
+            eprintln!(
+                "Error: Unknown theme: {}; use --list-themes to list available themes",
+                theme
+            );
+            std::process::exit(1);
+        });
 
-        let exit_code = match Output::new(paginate) {
+        let stylesheet = stylesheet
+            .map_or_else(|| Ok(Stylesheet::default()), Stylesheet::load)
+            .unwrap_or_else(|error| {
+                eprintln!("Error: {}", error);
+                std::process::exit(1);
+            });
+
+        let pager = pager.unwrap_or_default();
+        // HTML goes to a file or the clipboard, not to a pager
+        let paginate = paginate.unwrap_or(false) && format != Format::Html;
+        let exit_code = match Output::new(paginate, &pager) {
             Ok(mut output) => {
                 let settings = Settings {
                     terminal_capabilities,
                     terminal_size: TerminalSize { columns, ..size },
-                    resource_access,
+                    resource_policy,
+                    cache,
                     syntax_set: SyntaxSet::load_defaults_newlines(),
+                    theme,
+                    stylesheet,
+                    error_recovery: ErrorRecovery::Degrade,
+                    diagnostics: if verbose {
+                        Some(Box::new(PrintWarnings))
+                    } else {
+                        None
+                    },
                 };
-                filenames
-                    .iter()
-                    .try_fold(0, |code, filename| {
-                        process_file(filename, &settings, dump_events, &mut output)
+                let title = filenames.join(" ");
+                let code = match ignore_broken_pipe(format.start(output.writer(), &title)) {
+                    Ok(()) => filenames
+                        .iter()
+                        .try_fold(0, |code, filename| {
+                            let local = filename != "-" && remote_url(filename).is_none();
+                            if follow && local && format != Format::Events {
+                                process_book(filename, &settings, &http, toc, format, &mut output)
+                            } else {
+                                process_file(
+                                    filename,
+                                    &settings,
+                                    &http,
+                                    toc,
+                                    format,
+                                    section.as_deref(),
+                                    &mut output,
+                                )
+                            }
                             .map(|_| code)
                             .or_else(|error| {
                                 eprintln!("Error: {}: {}", filename, error);
@@ -197,8 +636,20 @@ fn main() {
                                     Ok(1)
                                 }
                             })
-                    })
-                    .unwrap_or(1)
+                        })
+                        .unwrap_or(1),
+                    Err(error) => {
+                        eprintln!("Error: {}", error);
+                        1
+                    }
+                };
+                match ignore_broken_pipe(format.end(output.writer())) {
+                    Ok(()) => code,
+                    Err(error) => {
+                        eprintln!("Error: {}", error);
+                        1
+                    }
+                }
             }
             Err(error) => {
                 eprintln!("Error: {:#}", error);
@@ -27,24 +27,19 @@ impl Drop for Output {
     }
 }
 
-fn parse_env_var(name: &str) -> Result<Option<Vec<String>>> {
+/// Get the value of the environment variable `name`.
+///
+/// Return `None` if the variable is not set, and fail if its value is not
+/// unicode.
+pub fn env_var(name: &str) -> Result<Option<String>> {
     use std::env::VarError;
     match std::env::var(name) {
-        Ok(value) => shell_words::split(&value)
-            .with_context(|| format!("Failed to parse value {} of {}", &value, &name))
-            .map(Some),
+        Ok(value) => Ok(Some(value)),
         Err(VarError::NotPresent) => Ok(None),
         Err(VarError::NotUnicode(value)) => bail!("Value of {} not unicode: {:?}", name, value),
     }
 }
 
-fn pager_from_env() -> Result<Vec<String>> {
-    match parse_env_var("MDCAT_PAGER")? {
-        Some(command) => Ok(command),
-        None => Ok(parse_env_var("PAGER")?.unwrap_or_else(|| vec!["less".into(), "-R".into()])),
-    }
-}
-
 impl Output {
     /// Get the writer to write to the output.
     ///
@@ -58,15 +53,16 @@ impl Output {
 
     /// Create a new output.
     ///
-    /// If `try_paginate` is `true` try to output to a pager.  If stdout is not a TTY, that is, if
-    /// there's no terminal to paginate on, print to stdout nonetheless.
+    /// If `try_paginate` is `true` try to output to the `pager` command.  If stdout is not a TTY,
+    /// that is, if there's no terminal to paginate on, print to stdout nonetheless.
     ///
-    /// Take the pager command from `$MDCAT_PAGER` or `$PAGER`, and default to `less -R` if both are
-    /// unset.  If any of the variables is empty use stdout (assuming that the user
-    /// wanted to disabled paging explicitly).
-    pub fn new(try_paginate: bool) -> Result<Output> {
+    /// `pager` is a shell command line.  If it is empty use stdout (assuming that the user wanted
+    /// to disable paging explicitly).
+    pub fn new(try_paginate: bool, pager: &str) -> Result<Output> {
         if try_paginate {
-            match pager_from_env()?.split_first() {
+            let command = shell_words::split(pager)
+                .with_context(|| format!("Failed to parse pager command {}", pager))?;
+            match command.split_first() {
                 None => Ok(Output::Stdout(std::io::stdout())),
                 Some((command, args)) => Command::new(command)
                     .args(args)
@@ -0,0 +1,35 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Themes for syntax highlighting.
+
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use syntect::highlighting::ThemeSet;
+
+use crate::dirs::config_dir;
+
+/// The directory for user themes.
+///
+/// This is the `themes` directory in the configuration directory of mdcat.
+pub fn user_themes_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("themes"))
+}
+
+/// Load all themes.
+///
+/// Load all themes bundled with syntect and add all `.tmTheme` files from the
+/// user themes directory, if it exists.  User themes take their name from the
+/// file name, and override bundled themes of the same name.
+pub fn load_themes() -> Result<ThemeSet> {
+    let mut themes = ThemeSet::load_defaults();
+    if let Some(directory) = user_themes_dir().filter(|dir| dir.is_dir()) {
+        themes
+            .add_from_folder(&directory)
+            .with_context(|| format!("Failed to load themes from {}", directory.display()))?;
+    }
+    Ok(themes)
+}
@@ -0,0 +1,256 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! An on-disk cache for resources.
+//!
+//! The cache keeps the contents of HTTP resources along with their `ETag` and
+//! `Last-Modified` headers to revalidate them, and images which mdcat rendered
+//! or resized for the terminal, keyed by their target size.
+//!
+//! The cache is strictly optional: mdcat treats unreadable cache entries as
+//! missing, and ignores failures to write to the cache.
+
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use crate::terminal::size::PixelSize;
+
+/// An on-disk cache for resources.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    directory: PathBuf,
+}
+
+/// A cached HTTP response.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct HttpMetadata {
+    /// The URL of the response, to tell apart hash collisions.
+    pub url: String,
+    /// The `ETag` header of the response.
+    pub etag: Option<String>,
+    /// The `Last-Modified` header of the response.
+    pub last_modified: Option<String>,
+    /// The `Content-Type` header of the response.
+    pub content_type: Option<String>,
+}
+
+impl HttpMetadata {
+    /// Whether the response has a validator to revalidate the response with.
+    pub fn has_validator(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+}
+
+/// Hash `parts` with 64 bit FNV-1a.
+///
+/// We need a hash function which is stable across builds and platforms to
+/// find cached entries again, which rules out the hasher of the standard
+/// library.
+fn fnv1a<'a, I: IntoIterator<Item = &'a [u8]>>(parts: I) -> String {
+    let hash = parts
+        .into_iter()
+        // Separate parts to make the hash unambiguous
+        .flat_map(|part| part.iter().chain(std::iter::once(&0xff)))
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+/// Write `contents` to `path` atomically.
+///
+/// Write to a temporary file next to `path` first, and then rename the
+/// temporary file to `path`, so that concurrent readers never see partial
+/// contents.
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let directory = path
+        .parent()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Cache entry without directory"))?;
+    std::fs::create_dir_all(directory)?;
+    let temp_file = path.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&temp_file, contents)?;
+    std::fs::rename(&temp_file, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp_file);
+    })
+}
+
+impl Cache {
+    /// Create a cache in the given `directory`.
+    ///
+    /// Create `directory` only when writing to the cache.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Cache {
+        Cache {
+            directory: directory.into(),
+        }
+    }
+
+    /// The directory of this cache.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Remove all entries from this cache.
+    pub fn clear(&self) -> Result<()> {
+        match std::fs::remove_dir_all(&self.directory) {
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn http_paths(&self, url: &Url) -> (PathBuf, PathBuf) {
+        let key = fnv1a(vec![url.as_str().as_bytes()]);
+        let directory = self.directory.join("http");
+        (directory.join(format!("{}.toml", key)), directory.join(key))
+    }
+
+    /// Get the cached HTTP response for `url`.
+    pub(crate) fn http_response(&self, url: &Url) -> Option<(HttpMetadata, Vec<u8>)> {
+        let (metadata_file, contents_file) = self.http_paths(url);
+        let metadata: HttpMetadata = std::fs::read_to_string(metadata_file)
+            .ok()
+            .and_then(|metadata| toml::from_str(&metadata).ok())?;
+        if metadata.url == url.as_str() {
+            std::fs::read(contents_file)
+                .ok()
+                .map(|contents| (metadata, contents))
+        } else {
+            None
+        }
+    }
+
+    /// Put a HTTP response for `url` into the cache.
+    pub(crate) fn put_http_response(
+        &self,
+        url: &Url,
+        metadata: &HttpMetadata,
+        contents: &[u8],
+    ) -> Result<()> {
+        let (metadata_file, contents_file) = self.http_paths(url);
+        let metadata =
+            toml::to_string(metadata).map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+        // Write the contents first, so that we never see metadata without contents
+        write_atomically(&contents_file, contents)?;
+        write_atomically(&metadata_file, metadata.as_bytes())
+    }
+
+    fn rendered_path(&self, kind: &str, contents: &[u8], size: Option<PixelSize>) -> PathBuf {
+        let size = size.map_or_else(String::new, |size| format!("{}x{}", size.x, size.y));
+        let key = fnv1a(vec![kind.as_bytes(), size.as_bytes(), contents]);
+        self.directory.join("rendered").join(format!("{}.png", key))
+    }
+
+    /// Get a rendered image from the cache or render and cache it.
+    ///
+    /// Key the rendered image by the `kind` of rendering, the `contents` of the
+    /// original image, and the target `size`.  If the image is not cached
+    /// call `render` to render it, and put the result into the cache.
+    pub(crate) fn rendered<F>(
+        &self,
+        kind: &str,
+        contents: &[u8],
+        size: Option<PixelSize>,
+        render: F,
+    ) -> Result<Vec<u8>>
+    where
+        F: FnOnce() -> Result<Vec<u8>>,
+    {
+        let path = self.rendered_path(kind, contents, size);
+        match std::fs::read(&path) {
+            Ok(rendered) => Ok(rendered),
+            Err(_) => {
+                let rendered = render()?;
+                let _ = write_atomically(&path, &rendered);
+                Ok(rendered)
+            }
+        }
+    }
+}
+
+/// Render an image with `render`, using `cache` if any.
+///
+/// See [`Cache::rendered`].
+pub(crate) fn rendered<F>(
+    cache: Option<&Cache>,
+    kind: &str,
+    contents: &[u8],
+    size: Option<PixelSize>,
+    render: F,
+) -> Result<Vec<u8>>
+where
+    F: FnOnce() -> Result<Vec<u8>>,
+{
+    match cache {
+        Some(cache) => cache.rendered(kind, contents, size, render),
+        None => render(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn temp_cache(name: &str) -> Cache {
+        let directory =
+            std::env::temp_dir().join(format!("mdcat-cache-test-{}-{}", name, std::process::id()));
+        let cache = Cache::new(directory);
+        cache.clear().unwrap();
+        cache
+    }
+
+    #[test]
+    fn fnv1a_is_stable() {
+        assert_eq!(fnv1a(vec![&b"mdcat"[..]]), "b72ad4457e6a4c25");
+        assert_ne!(
+            fnv1a(vec![&b"ab"[..], &b"c"[..]]),
+            fnv1a(vec![&b"a"[..], &b"bc"[..]])
+        );
+    }
+
+    #[test]
+    fn http_response_roundtrip() {
+        let cache = temp_cache("http");
+        let url = Url::parse("https://example.com/image.png").unwrap();
+        assert_eq!(cache.http_response(&url), None);
+
+        let metadata = HttpMetadata {
+            url: url.to_string(),
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+            content_type: Some("image/png".to_string()),
+        };
+        cache.put_http_response(&url, &metadata, b"foo").unwrap();
+        assert_eq!(cache.http_response(&url), Some((metadata, b"foo".to_vec())));
+
+        let other = Url::parse("https://example.com/other.png").unwrap();
+        assert_eq!(cache.http_response(&other), None);
+
+        cache.clear().unwrap();
+        assert_eq!(cache.http_response(&url), None);
+    }
+
+    #[test]
+    fn render_only_once_per_size() {
+        let cache = temp_cache("rendered");
+        let size = Some(PixelSize::from_xy((10, 20)));
+        let rendered = cache.rendered("svg", b"<svg/>", size, || Ok(b"first".to_vec()));
+        assert_eq!(rendered.unwrap(), b"first");
+        let rendered = cache.rendered("svg", b"<svg/>", size, || Ok(b"second".to_vec()));
+        assert_eq!(rendered.unwrap(), b"first");
+
+        let rendered = cache.rendered("svg", b"<svg/>", None, || Ok(b"third".to_vec()));
+        assert_eq!(rendered.unwrap(), b"third");
+        let rendered = cache.rendered("svg", b"<svg />", size, || Ok(b"fourth".to_vec()));
+        assert_eq!(rendered.unwrap(), b"fourth");
+
+        let error = cache.rendered("png", b"", size, || Err(Error::other("failed")));
+        assert!(error.is_err());
+        cache.clear().unwrap();
+    }
+}
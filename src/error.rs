@@ -0,0 +1,101 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Errors and diagnostics of mdcat.
+
+use std::fmt::{Debug, Display, Formatter};
+use std::io::ErrorKind;
+use url::Url;
+
+use crate::RenderError;
+
+/// The mdcat error type.
+///
+/// `push_tty` only fails if it cannot write output, or if it cannot render a
+/// document with [`crate::ErrorRecovery::Fail`].  It reports all other errors,
+/// e.g. images it cannot show, to [`crate::Settings::diagnostics`].
+#[derive(Debug)]
+pub enum Error {
+    /// An I/O error, e.g. when writing output.
+    Io(std::io::Error),
+    /// The resource policy denied access to a URL.
+    AccessDenied {
+        /// The denied URL.
+        url: Url,
+        /// Why the policy denied access.
+        reason: String,
+    },
+    /// Failed to fetch a resource.
+    Fetch {
+        /// The URL of the resource.
+        url: Url,
+        /// The cause of the failure.
+        source: anyhow::Error,
+    },
+    /// Failed to decode or render an image.
+    Decode {
+        /// The URL of the image.
+        url: Url,
+        /// The cause of the failure.
+        source: anyhow::Error,
+    },
+    /// The rendering algorithm failed.
+    Render(RenderError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::AccessDenied { url, reason } => {
+                write!(f, "Access denied to URL {}: {}", url, reason)
+            }
+            // The source already tells the URL
+            Error::Fetch { source, .. } => write!(f, "{:#}", source),
+            Error::Decode { url, source } => {
+                write!(f, "Failed to show image {}: {:#}", url, source)
+            }
+            Error::Render(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<RenderError> for Error {
+    fn from(error: RenderError) -> Self {
+        Error::Render(error)
+    }
+}
+
+impl From<Error> for std::io::Error {
+    /// Convert to an I/O error, for callers which only handle I/O errors.
+    fn from(error: Error) -> Self {
+        let kind = match error {
+            Error::Io(error) => return error,
+            Error::AccessDenied { .. } => ErrorKind::PermissionDenied,
+            Error::Render(_) => ErrorKind::InvalidData,
+            Error::Fetch { .. } | Error::Decode { .. } => ErrorKind::Other,
+        };
+        std::io::Error::new(kind, error)
+    }
+}
+
+/// A sink for diagnostics.
+///
+/// mdcat reports errors which do not stop rendering to this sink, e.g. images
+/// it could not load, or events it could not render with
+/// [`crate::ErrorRecovery::Degrade`].
+pub trait Diagnostics: Debug + Send + Sync {
+    /// Report a non-fatal `warning`.
+    fn warn(&self, warning: &Error);
+}
@@ -0,0 +1,296 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Render to HTML.
+//!
+//! Write rendered output as HTML which looks like mdcat in a terminal, e.g. to
+//! paste it into bug reports or chat.
+
+use std::io::{Result, Write};
+
+use ansi_term::{Colour, Style};
+use anyhow::anyhow;
+use fehler::throws;
+use pulldown_cmark::Event;
+use url::Url;
+
+use crate::renderer::{InlineImage, Renderer};
+use crate::terminal::colour_to_rgb;
+use crate::{push_events, Environment, Error, Settings};
+
+/// The default foreground colour of HTML output.
+///
+/// White of the xterm palette, like the default foreground of a dark terminal.
+const FOREGROUND: &str = "#e5e5e5";
+
+/// The default background colour of HTML output.
+///
+/// Our default styles are made for dark terminals, so use a black background.
+const BACKGROUND: &str = "#000000";
+
+/// A renderer which writes HTML.
+///
+/// Write text with the colours and attributes of its style as HTML, links as
+/// HTML links, and embed images as data URIs.  Write all output inside the
+/// `<pre>` element of a document started with [`write_html_start`].
+#[derive(Debug)]
+pub struct HtmlRenderer<W> {
+    writer: W,
+}
+
+impl<W: Write> HtmlRenderer<W> {
+    /// Write HTML to `writer`.
+    pub fn new(writer: W) -> Self {
+        HtmlRenderer { writer }
+    }
+
+    /// Get the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Escape `text` for HTML.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The CSS value of `colour`.
+fn css_colour(colour: Colour) -> String {
+    let (r, g, b) = colour_to_rgb(colour);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// The CSS declarations for `style`.
+fn css_style(style: &Style) -> String {
+    let mut declarations = Vec::new();
+    let foreground = style.foreground.map(css_colour);
+    let background = style.background.map(css_colour);
+    let (foreground, background) = if style.is_reverse {
+        (
+            Some(background.unwrap_or_else(|| BACKGROUND.to_string())),
+            Some(foreground.unwrap_or_else(|| FOREGROUND.to_string())),
+        )
+    } else {
+        (foreground, background)
+    };
+    if let Some(foreground) = foreground {
+        declarations.push(format!("color:{}", foreground));
+    }
+    if let Some(background) = background {
+        declarations.push(format!("background-color:{}", background));
+    }
+    if style.is_bold {
+        declarations.push("font-weight:bold".to_string());
+    }
+    if style.is_dimmed {
+        declarations.push("opacity:0.5".to_string());
+    }
+    if style.is_italic {
+        declarations.push("font-style:italic".to_string());
+    }
+    let decorations: Vec<&str> = [
+        (style.is_underline, "underline"),
+        (style.is_strikethrough, "line-through"),
+    ]
+    .iter()
+    .filter(|(enabled, _)| *enabled)
+    .map(|(_, decoration)| *decoration)
+    .collect();
+    if !decorations.is_empty() {
+        declarations.push(format!("text-decoration:{}", decorations.join(" ")));
+    }
+    if style.is_hidden {
+        declarations.push("visibility:hidden".to_string());
+    }
+    declarations.join(";")
+}
+
+impl<W: Write> Renderer for HtmlRenderer<W> {
+    fn write_styled(&mut self, style: &Style, text: &str) -> Result<()> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        let css = css_style(style);
+        if css.is_empty() {
+            write!(self.writer, "{}", escape(text))
+        } else {
+            write!(
+                self.writer,
+                r#"<span style="{}">{}</span>"#,
+                css,
+                escape(text)
+            )
+        }
+    }
+
+    fn start_link(&mut self, url: &Url) -> Result<()> {
+        write!(self.writer, r#"<a href="{}">"#, escape(url.as_str()))
+    }
+
+    fn end_link(&mut self) -> Result<()> {
+        write!(self.writer, "</a>")
+    }
+
+    fn write_image(
+        &mut self,
+        image: &InlineImage<'_>,
+    ) -> std::result::Result<Option<usize>, Error> {
+        let url = image.url;
+        let resource = image.read()?;
+        let mime = resource
+            .detect_mime_type()
+            .map_err(|source| Error::Decode {
+                url: url.clone(),
+                source,
+            })?;
+        if mime.type_() != mime::IMAGE {
+            return Err(Error::Decode {
+                url: url.clone(),
+                source: anyhow!("Not an image but {}", mime),
+            });
+        }
+        write!(
+            self.writer,
+            r#"<img src="data:{};base64,{}" style="max-width:100%">"#,
+            mime,
+            base64::encode(&resource.contents)
+        )?;
+        Ok(Some(image.column))
+    }
+}
+
+/// Write the start of an HTML document with the given `title` to `writer`.
+///
+/// Start a `<pre>` element for all rendered output, with a light foreground
+/// on a dark background like a terminal; end it with [`write_html_end`].
+pub fn write_html_start<W: Write + ?Sized>(writer: &mut W, title: &str) -> Result<()> {
+    write!(
+        writer,
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{2}</title>
+<style>
+body {{ background-color: {1}; }}
+pre {{ color: {0}; background-color: {1}; padding: 1em; font-family: monospace; }}
+pre a {{ color: inherit; text-decoration: none; }}
+pre a:hover {{ text-decoration: underline; }}
+</style>
+</head>
+<body>
+<pre>"#,
+        FOREGROUND,
+        BACKGROUND,
+        escape(title),
+    )
+}
+
+/// Write the end of an HTML document started with [`write_html_start`] to
+/// `writer`.
+pub fn write_html_end<W: Write + ?Sized>(writer: &mut W) -> Result<()> {
+    writeln!(writer, "</pre>\n</body>\n</html>")
+}
+
+/// Write markdown as HTML.
+///
+/// Iterate over Markdown AST `events`, lay out each event like
+/// [`crate::push_tty`] would, and write the result as HTML to `writer`, using
+/// the given `settings` and `environment` for rendering and resource access.
+///
+/// Use [`crate::TerminalCapabilities::html`] in `settings` to get colours and
+/// inline links.  Write [`write_html_start`] before and [`write_html_end`]
+/// after to get a complete HTML document.
+#[throws(Error)]
+pub fn push_html<'e, W, I>(settings: &Settings, environment: &Environment, writer: W, events: I)
+where
+    I: Iterator<Item = Event<'e>>,
+    W: Write,
+{
+    push_events(settings, environment, HtmlRenderer::new(writer), events)?;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_settings;
+    use crate::TerminalCapabilities;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::Parser;
+
+    fn render(markup: &str, environment: &Environment) -> String {
+        let settings = test_settings(TerminalCapabilities::html());
+        let mut buffer = Vec::new();
+        push_html(&settings, environment, &mut buffer, Parser::new(markup)).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn escape_text() {
+        let mut renderer = HtmlRenderer::new(Vec::new());
+        renderer
+            .write_styled(&Style::new(), "<a href=\"x\">&'</a>")
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(renderer.into_inner()).unwrap(),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn styles_to_css() {
+        assert_eq!(css_style(&Style::new()), "");
+        assert_eq!(
+            css_style(&Style::new().fg(Colour::Blue).on(Colour::Fixed(244)).bold()),
+            "color:#0000ee;background-color:#808080;font-weight:bold"
+        );
+        assert_eq!(
+            css_style(&Style::new().italic().underline().strikethrough().dimmed()),
+            "opacity:0.5;font-style:italic;text-decoration:underline line-through"
+        );
+        assert_eq!(
+            css_style(&Style::new().fg(Colour::RGB(0x12, 0x34, 0x56)).reverse()),
+            "color:#000000;background-color:#123456"
+        );
+    }
+
+    #[test]
+    fn render_styles_and_links() {
+        let env = Environment::for_localhost(Url::parse("http://example.com/").unwrap()).unwrap();
+        assert_eq!(
+            render("*Hello* [world](world.html) & <you>", &env),
+            r#"<span style="font-style:italic">Hello</span> <a href="http://example.com/world.html"><span style="color:#0000ee">world</span></a> &amp; <span style="color:#00cd00">&lt;you&gt;</span>
+"#
+        );
+    }
+
+    #[test]
+    fn embed_images_as_data_uri() {
+        let directory = Url::from_directory_path(std::env::current_dir().unwrap()).unwrap();
+        let env = Environment::for_localhost(directory).unwrap();
+        let html = render("![Logo](sample/rust-logo-128x128.png)", &env);
+        let contents = std::fs::read("sample/rust-logo-128x128.png").unwrap();
+        assert_eq!(
+            html,
+            format!(
+                "<img src=\"data:image/png;base64,{}\" style=\"max-width:100%\">\n",
+                base64::encode(&contents)
+            )
+        );
+    }
+}
@@ -0,0 +1,349 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! HTTP access for mdcat.
+//!
+//! mdcat shares a HTTP client across all documents it renders, to reuse
+//! connections and to configure timeouts, redirects and retries in one place.
+
+use anyhow::{anyhow, Context, Error};
+use fehler::{throw, throws};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use ureq::{Agent, AgentBuilder, Response};
+use url::Url;
+
+#[cfg(test)]
+pub(crate) mod test_server;
+
+/// Settings for HTTP requests.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HttpSettings {
+    /// The timeout for connecting to a server.
+    ///
+    /// The timeout of the entire request is part of the
+    /// [`crate::ResourcePolicy`].
+    pub connect_timeout: Duration,
+    /// The maximum number of redirects to follow.
+    pub redirects: u32,
+    /// How often to retry a failed request.
+    ///
+    /// Retry requests which failed to connect or to read the response, and
+    /// requests which failed with status 429 or a server error.
+    pub retries: u32,
+    /// The time to wait before the first retry.
+    ///
+    /// Double the time for every further retry.
+    pub backoff: Duration,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        HttpSettings {
+            connect_timeout: Duration::from_secs(10),
+            redirects: 5,
+            retries: 2,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+/// A HTTP client.
+///
+/// Clones of a client share the same connections.
+#[derive(Debug, Clone, Default)]
+pub struct HttpClient {
+    settings: HttpSettings,
+    /// Agents by proxy URL, to reuse connections.
+    agents: Arc<Mutex<HashMap<Option<String>, Agent>>>,
+}
+
+/// Whether to retry a request which failed with `error`.
+fn is_transient(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::Status(status, _) => *status == 429 || 500 <= *status,
+        ureq::Error::Transport(_) => matches!(
+            error.kind(),
+            ureq::ErrorKind::Dns | ureq::ErrorKind::ConnectionFailed | ureq::ErrorKind::Io
+        ),
+    }
+}
+
+/// The target of a redirect `response` to a request for `url`.
+///
+/// Resolve the `Location` of the response against `url`; return `None` if
+/// `response` is no redirect.
+fn redirect_target(url: &Url, response: &Response) -> Option<Url> {
+    match response.status() {
+        301 | 302 | 303 | 307 | 308 => response
+            .header("Location")
+            .and_then(|location| url.join(location).ok()),
+        _ => None,
+    }
+}
+
+impl HttpClient {
+    /// Create a new HTTP client with the given `settings`.
+    pub fn new(settings: HttpSettings) -> Self {
+        HttpClient {
+            settings,
+            agents: Arc::default(),
+        }
+    }
+
+    /// The settings of this client.
+    pub fn settings(&self) -> &HttpSettings {
+        &self.settings
+    }
+
+    /// Get an agent for `url`.
+    ///
+    /// Use the proxy configured for `url` in the environment, if any.
+    #[throws]
+    fn agent(&self, url: &Url) -> Agent {
+        let proxy_url = env_proxy::for_url(url).to_string();
+        let mut agents = self
+            .agents
+            .lock()
+            .map_err(|_| anyhow::anyhow!("HTTP client poisoned"))?;
+        match agents.get(&proxy_url) {
+            Some(agent) => agent.clone(),
+            None => {
+                let builder = AgentBuilder::new()
+                    .timeout_connect(self.settings.connect_timeout)
+                    // We follow redirects ourselves, to check every hop
+                    .redirects(0)
+                    .user_agent(concat!("mdcat/", env!("CARGO_PKG_VERSION")));
+                let agent = match &proxy_url {
+                    None => builder,
+                    Some(proxy_url) => {
+                        builder.proxy(ureq::Proxy::new(proxy_url).with_context(|| {
+                            format!("Failed to set proxy for URL {} to {}", url, proxy_url)
+                        })?)
+                    }
+                }
+                .build();
+                agents.insert(proxy_url, agent.clone());
+                agent
+            }
+        }
+    }
+
+    /// GET `url` with the given `headers`, following redirects.
+    ///
+    /// Follow at most as many redirects as the settings of this client permit,
+    /// and check the target of every redirect with `permit` before following
+    /// it.  Fail with the error of `permit` if it rejects a target.
+    ///
+    /// Give up on each request after `timeout`, and retry transient failures
+    /// according to the settings of this client.
+    #[throws]
+    pub(crate) fn get<F>(
+        &self,
+        url: &Url,
+        headers: &[(&str, &str)],
+        timeout: Duration,
+        permit: F,
+    ) -> Response
+    where
+        F: Fn(&Url) -> std::result::Result<(), crate::Error>,
+    {
+        let mut url = url.clone();
+        let mut redirects = 0;
+        loop {
+            let response = self.get_once(&url, headers, timeout)?;
+            match redirect_target(&url, &response) {
+                None => break response,
+                Some(_) if self.settings.redirects <= redirects => {
+                    throw!(anyhow!(
+                        "Failed to GET {}: Too Many Redirects (more than {})",
+                        url,
+                        self.settings.redirects
+                    ))
+                }
+                Some(target) => {
+                    permit(&target)?;
+                    url = target;
+                    redirects += 1;
+                }
+            }
+        }
+    }
+
+    /// GET `url` with the given `headers`, without following redirects.
+    ///
+    /// Give up on each attempt after `timeout`, and retry transient failures
+    /// according to the settings of this client.
+    #[throws]
+    fn get_once(&self, url: &Url, headers: &[(&str, &str)], timeout: Duration) -> Response {
+        let agent = self.agent(url)?;
+        let mut attempt = 0;
+        loop {
+            let request = headers.iter().fold(
+                agent.request_url("GET", url).timeout(timeout),
+                |request, (name, value)| request.set(name, value),
+            );
+            match request.call() {
+                Err(error) if attempt < self.settings.retries && is_transient(&error) => {
+                    std::thread::sleep(self.settings.backoff * 2_u32.pow(attempt));
+                    attempt += 1;
+                }
+                result => {
+                    break result.with_context(|| match attempt {
+                        0 => format!("Failed to GET {}", url),
+                        _ => format!("Failed to GET {} after {} retries", url, attempt),
+                    })?
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_server::{TestResponse, TestServer};
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn permit_all(_: &Url) -> std::result::Result<(), crate::Error> {
+        Ok(())
+    }
+
+    fn client(retries: u32) -> HttpClient {
+        HttpClient::new(HttpSettings {
+            retries,
+            backoff: Duration::from_millis(1),
+            ..HttpSettings::default()
+        })
+    }
+
+    #[test]
+    fn retry_server_errors() {
+        let server = TestServer::start(|request| match request.attempt {
+            0 | 1 => TestResponse::status(503),
+            _ => TestResponse::ok("text/plain", b"foo".to_vec()),
+        });
+        let url = server.url("/flaky");
+        let response = client(2)
+            .get(&url, &[], Duration::from_secs(5), permit_all)
+            .unwrap();
+        assert_eq!(response.into_string().unwrap(), "foo");
+
+        let error = client(1)
+            .get(
+                &server.url("/flaky-again"),
+                &[],
+                Duration::from_secs(5),
+                permit_all,
+            )
+            .unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            format!(
+                "Failed to GET {0} after 1 retries: {0}: status code 503",
+                server.url("/flaky-again")
+            )
+        );
+    }
+
+    #[test]
+    fn do_not_retry_client_errors() {
+        let server = TestServer::start(|_| TestResponse::status(404));
+        let url = server.url("/missing");
+        let error = client(2)
+            .get(&url, &[], Duration::from_secs(5), permit_all)
+            .unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            format!("Failed to GET {0}: {0}: status code 404", url)
+        );
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn limit_redirects() {
+        let server = TestServer::start(|request| {
+            let next = request.path.len();
+            TestResponse::redirect(&format!("/{}", "r".repeat(next)))
+        });
+        let client = HttpClient::new(HttpSettings {
+            redirects: 3,
+            ..client(0).settings
+        });
+        let error = client
+            .get(&server.url("/"), &[], Duration::from_secs(5), permit_all)
+            .unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("Too Many Redirects"),
+            "Unexpected error: {:#}",
+            error
+        );
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[test]
+    fn check_redirect_targets() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/moved" => TestResponse::redirect("/denied"),
+            _ => TestResponse::ok("text/plain", b"denied".to_vec()),
+        });
+        let error = client(0)
+            .get(&server.url("/moved"), &[], Duration::from_secs(5), |url| {
+                Err(crate::Error::AccessDenied {
+                    url: url.clone(),
+                    reason: "denied".to_string(),
+                })
+            })
+            .unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            format!("Access denied to URL {}: denied", server.url("/denied"))
+        );
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn time_out_slow_responses() {
+        let server = TestServer::start(|_| {
+            std::thread::sleep(Duration::from_millis(500));
+            TestResponse::ok("text/plain", b"slow".to_vec())
+        });
+        let error = client(0)
+            .get(
+                &server.url("/slow"),
+                &[],
+                Duration::from_millis(100),
+                permit_all,
+            )
+            .unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("timed out"),
+            "Unexpected error: {:#}",
+            error
+        );
+    }
+
+    #[test]
+    fn send_user_agent_and_headers() {
+        let server = TestServer::start(|_| TestResponse::status(204));
+        client(0)
+            .get(
+                &server.url("/"),
+                &[("If-None-Match", "\"foo\"")],
+                Duration::from_secs(5),
+                permit_all,
+            )
+            .unwrap();
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].header("user-agent"),
+            Some(concat!("mdcat/", env!("CARGO_PKG_VERSION")))
+        );
+        assert_eq!(requests[0].header("if-none-match"), Some("\"foo\""));
+    }
+}
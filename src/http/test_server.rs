@@ -0,0 +1,179 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A minimal local HTTP server for tests.
+//!
+//! The server answers every request on a new thread with a response from a
+//! handler function, and closes the connection after each response.
+
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// A request the test server received.
+#[derive(Debug, Clone)]
+pub(crate) struct TestRequest {
+    /// The path of the request, including the query string.
+    pub path: String,
+    /// The headers of the request, with lowercase names.
+    pub headers: Vec<(String, String)>,
+    /// How many requests for the same path the server received before.
+    pub attempt: usize,
+}
+
+impl TestRequest {
+    /// Get the value of the header `name`, in lowercase.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A response of the test server.
+#[derive(Debug, Clone)]
+pub(crate) struct TestResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl TestResponse {
+    /// An empty response with `status`.
+    pub fn status(status: u16) -> Self {
+        TestResponse {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// A successful response with the given `content_type` and `body`.
+    pub fn ok(content_type: &str, body: Vec<u8>) -> Self {
+        TestResponse {
+            body,
+            ..TestResponse::status(200).with_header("Content-Type", content_type)
+        }
+    }
+
+    /// A redirect to `location`.
+    pub fn redirect(location: &str) -> Self {
+        TestResponse::status(302).with_header("Location", location)
+    }
+
+    /// Add a header to this response.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn write_to(&self, writer: &mut TcpStream) -> std::io::Result<()> {
+        write!(writer, "HTTP/1.1 {} Test\r\n", self.status)?;
+        let has_length = self
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("Content-Length"));
+        if !has_length {
+            write!(writer, "Content-Length: {}\r\n", self.body.len())?;
+        }
+        for (name, value) in &self.headers {
+            write!(writer, "{}: {}\r\n", name, value)?;
+        }
+        write!(writer, "Connection: close\r\n\r\n")?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+}
+
+type Handler = dyn Fn(&TestRequest) -> TestResponse + Send + Sync;
+
+/// A local HTTP server for tests.
+///
+/// The server runs until the test process exits.
+pub(crate) struct TestServer {
+    address: SocketAddr,
+    requests: Arc<Mutex<Vec<TestRequest>>>,
+}
+
+/// Read the head of a HTTP request from `stream`.
+///
+/// Return the path and the headers of the request.
+fn read_request(stream: &TcpStream) -> std::io::Result<(String, Vec<(String, String)>)> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or("/")
+        .to_string();
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    Ok((path, headers))
+}
+
+impl TestServer {
+    /// Start a server which answers requests with `handler`.
+    pub fn start<F>(handler: F) -> TestServer
+    where
+        F: Fn(&TestRequest) -> TestResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind test server");
+        let address = listener.local_addr().expect("Test server without address");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let server_requests = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let handler = handler.clone();
+                let requests = server_requests.clone();
+                std::thread::spawn(move || {
+                    let mut stream = stream;
+                    if let Ok((path, headers)) = read_request(&stream) {
+                        let request = {
+                            let mut requests = requests.lock().unwrap();
+                            let attempt = requests
+                                .iter()
+                                .filter(|r: &&TestRequest| r.path == path)
+                                .count();
+                            let request = TestRequest {
+                                path,
+                                headers,
+                                attempt,
+                            };
+                            requests.push(request.clone());
+                            request
+                        };
+                        // The client may have given up already
+                        let _ = handler(&request).write_to(&mut stream);
+                    }
+                });
+            }
+        });
+        TestServer { address, requests }
+    }
+
+    /// The URL of `path` on this server.
+    pub fn url(&self, path: &str) -> Url {
+        Url::parse(&format!("http://{}{}", self.address, path)).unwrap()
+    }
+
+    /// All requests this server received so far.
+    pub fn requests(&self) -> Vec<TestRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
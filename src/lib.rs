@@ -8,31 +8,45 @@
 
 //! Write markdown to TTYs.
 
+use std::collections::HashMap;
 use std::io::{ErrorKind, Result, Write};
 use std::path::Path;
 
 use fehler::throws;
 use pulldown_cmark::Event;
-use syntect::highlighting::ThemeSet;
+use syntect::highlighting::Theme;
 use syntect::parsing::SyntaxSet;
 
 // Expose some select things for use in main
-pub use crate::resources::ResourceAccess;
+pub use crate::cache::Cache;
+pub use crate::error::{Diagnostics, Error};
+pub use crate::html::{push_html, write_html_end, write_html_start, HtmlRenderer};
+pub use crate::http::{HttpClient, HttpSettings};
+pub use crate::outline::{section, Heading, Outline};
+pub use crate::references::UrlBase;
+pub use crate::render::{ErrorRecovery, RenderError};
+pub use crate::renderer::{Block, InlineImage, Renderer};
+pub use crate::resources::{read_url, Resource, ResourcePolicy};
+pub use crate::spans::{render_spans, Line, Span, SpanRenderer};
+pub use crate::stylesheet::{GlyphStyle, HeadingStyle, Stylesheet, TableBorders};
 pub use crate::terminal::*;
 use url::Url;
 
+mod cache;
+mod error;
+mod html;
+mod http;
 mod magic;
+mod outline;
 mod references;
 mod resources;
+mod spans;
+mod stylesheet;
 mod svg;
 mod terminal;
 
 mod render;
-
-/// The mdcat error type.
-///
-/// This is `std::io::Error`: mdcat never fails visible unless it cannot write output.
-pub type Error = std::io::Error;
+mod renderer;
 
 /// Settings for markdown rendering.
 #[derive(Debug)]
@@ -41,10 +55,29 @@ pub struct Settings {
     pub terminal_capabilities: TerminalCapabilities,
     /// The size of the terminal mdcat writes to.
     pub terminal_size: TerminalSize,
-    /// Whether remote resource access is permitted.
-    pub resource_access: ResourceAccess,
+    /// What resources mdcat may access.
+    pub resource_policy: ResourcePolicy,
+    /// The cache for remote resources and rendered images, if any.
+    pub cache: Option<Cache>,
     /// Syntax set for syntax highlighting of code blocks.
     pub syntax_set: SyntaxSet,
+    /// Theme for syntax highlighting of code blocks.
+    pub theme: Theme,
+    /// Stylesheet for all other markdown elements.
+    pub stylesheet: Stylesheet,
+    /// What to do if mdcat cannot render an event.
+    pub error_recovery: ErrorRecovery,
+    /// Where to report non-fatal errors, if anywhere.
+    pub diagnostics: Option<Box<dyn Diagnostics>>,
+}
+
+impl Settings {
+    /// Report a non-fatal `warning` to the diagnostics sink, if any.
+    pub(crate) fn warn(&self, warning: Error) {
+        if let Some(diagnostics) = &self.diagnostics {
+            diagnostics.warn(&warning);
+        }
+    }
 }
 
 /// The environment to render markdown in.
@@ -54,22 +87,35 @@ pub struct Environment {
     pub base_url: Url,
     /// The local host name.
     pub hostname: String,
+    /// The client for HTTP resources.
+    pub http: HttpClient,
+    /// Anchors of documents rendered into the same output, by URL.
+    ///
+    /// Render links to these documents as references to their anchors instead
+    /// of links.
+    pub anchors: HashMap<Url, String>,
 }
 
 impl Environment {
     /// Create an environment for the local host with the given `base_url`.
     ///
-    /// Take the local hostname from `gethostname`.
+    /// Take the local hostname from `gethostname`, and use a HTTP client with
+    /// default settings.
     pub fn for_localhost(base_url: Url) -> Result<Self> {
         gethostname::gethostname()
             .into_string()
             .map_err(|raw| {
-                Error::new(
+                std::io::Error::new(
                     ErrorKind::InvalidData,
                     format!("gethostname() returned invalid unicode data: {:?}", raw),
                 )
             })
-            .map(|hostname| Environment { base_url, hostname })
+            .map(|hostname| Environment {
+                base_url,
+                hostname,
+                http: HttpClient::default(),
+                anchors: HashMap::new(),
+            })
     }
 
     /// Create an environment for a local diretory.
@@ -81,7 +127,7 @@ impl Environment {
     pub fn for_local_directory<P: AsRef<Path>>(base_dir: &P) -> Result<Self> {
         Url::from_directory_path(base_dir)
             .map_err(|_| {
-                Error::new(
+                std::io::Error::new(
                     ErrorKind::InvalidInput,
                     format!(
                         "Base directory {} must be an absolute path",
@@ -91,6 +137,13 @@ impl Environment {
             })
             .and_then(Self::for_localhost)
     }
+
+    /// The anchor of the document `reference` refers to, if any.
+    pub(crate) fn anchor(&self, reference: &str) -> Option<&str> {
+        let mut url = self.resolve_reference(reference)?;
+        url.set_fragment(None);
+        self.anchors.get(&url).map(String::as_str)
+    }
 }
 
 /// Write markdown to a TTY.
@@ -99,28 +152,82 @@ impl Environment {
 /// write the result to a `writer`, using the given `settings` and `environment`
 /// for rendering and resource access.
 ///
-/// `push_tty` tries to limit output to the given number of TTY `columns` but
-/// does not guarantee that output stays within the column limit.
+/// `push_tty` wraps inline text to the number of columns in the terminal size
+/// of `settings`, but does not guarantee that output stays within the column
+/// limit: It never breaks words which do not fit into a line, and does not
+/// wrap code blocks.
+///
+/// Before writing anything `push_tty` fetches all remote images in `events`
+/// concurrently.
 #[throws]
 pub fn push_tty<'a, 'e, W, I>(
     settings: &Settings,
     environment: &Environment,
     writer: &'a mut W,
-    mut events: I,
+    events: I,
 ) -> ()
 where
     I: Iterator<Item = Event<'e>>,
     W: Write,
 {
-    let theme = &ThemeSet::load_defaults().themes["Solarized (dark)"];
+    let renderer = TerminalRenderer::new(
+        writer,
+        &settings.terminal_capabilities,
+        &environment.hostname,
+    );
+    push_events(settings, environment, renderer, events)?;
+}
+
+/// Lay out markdown for a `renderer`.
+///
+/// Iterate over Markdown AST `events`, lay out each event like `push_tty`
+/// would, and write the result to `renderer`, using the given `settings` and
+/// `environment` for rendering and resource access.
+///
+/// The `terminal_capabilities` of `settings` still control the layout, e.g.
+/// whether mdcat writes inline links or reference links, and how it draws
+/// tables.
+#[throws]
+pub fn push_events<'e, R, I>(
+    settings: &Settings,
+    environment: &Environment,
+    renderer: R,
+    events: I,
+) -> ()
+where
+    I: Iterator<Item = Event<'e>>,
+    R: Renderer,
+{
     use render::*;
-    let (final_state, final_data) = events.try_fold(
-        (State::default(), StateData::default()),
-        |(state, data), event| {
-            write_event(writer, settings, environment, theme, state, data, event)
-        },
+    let events: Vec<Event<'e>> = events.collect();
+    let prefetched = prefetch_images(settings, environment, &events);
+    let data = StateData::with_prefetched(prefetched).with_outline(Outline::from_events(&events));
+    let mut writer = ColumnWriter::new(renderer);
+    let (final_state, final_data) = events
+        .into_iter()
+        .try_fold((State::default(), data), |(state, data), event| {
+            write_event(&mut writer, settings, environment, state, data, event)
+        })?;
+    finish(&mut writer, settings, environment, final_state, final_data)?;
+}
+
+/// Write the `outline` of a document as table of contents to `writer`.
+///
+/// Write the headings of the outline as nested list, like `push_tty` would
+/// write a list in a document.
+#[throws]
+pub fn push_toc<W: Write>(
+    settings: &Settings,
+    environment: &Environment,
+    writer: &mut W,
+    outline: &Outline,
+) -> () {
+    push_tty(
+        settings,
+        environment,
+        writer,
+        outline.to_events().into_iter(),
     )?;
-    finish(writer, settings, environment, final_state, final_data)?;
 }
 
 /// Write as push_tty would, but ignore actual output and instead write states and events.
@@ -138,8 +245,11 @@ where
     use ansi_term::*;
     use render::*;
 
-    let theme = &ThemeSet::load_defaults().themes["Solarized (dark)"];
-    let mut sink = std::io::sink();
+    let mut sink = ColumnWriter::new(TerminalRenderer::new(
+        std::io::sink(),
+        &settings.terminal_capabilities,
+        &environment.hostname,
+    ));
     let (final_state, _) = events.try_fold(
         (State::default(), StateData::default()),
         |(state, data), event| {
@@ -149,7 +259,7 @@ where
                 .fg(Colour::Purple)
                 .paint(format!("{:?}", event));
             writeln!(writer, "{} {} {}", s, sep, e)?;
-            write_event(&mut sink, settings, environment, theme, state, data, event)
+            write_event(&mut sink, settings, environment, state, data, event)
         },
     )?;
     writeln!(writer, "{:?}", final_state)?;
@@ -157,13 +267,30 @@ where
 
 #[cfg(test)]
 mod tests {
-    use pulldown_cmark::Parser;
+    use pulldown_cmark::{Options, Parser};
 
     use super::*;
 
+    /// Settings for tests, with the given terminal `capabilities`.
+    ///
+    /// Override other settings with struct update syntax.
+    pub(crate) fn test_settings(capabilities: TerminalCapabilities) -> Settings {
+        Settings {
+            resource_policy: ResourcePolicy::local_only(),
+            cache: None,
+            syntax_set: SyntaxSet::default(),
+            terminal_capabilities: capabilities,
+            terminal_size: TerminalSize::default(),
+            theme: Theme::default(),
+            stylesheet: Stylesheet::default(),
+            error_recovery: ErrorRecovery::Fail,
+            diagnostics: None,
+        }
+    }
+
     #[throws(anyhow::Error)]
-    fn render_string(input: &str, settings: &Settings) -> String {
-        let source = Parser::new(input);
+    fn render_string(input: &str, options: Options, settings: &Settings) -> String {
+        let source = Parser::new_ext(input, options);
         let mut sink = Vec::new();
         let env =
             Environment::for_local_directory(&std::env::current_dir().expect("Working directory"))?;
@@ -174,20 +301,23 @@ mod tests {
     mod layout {
         use anyhow::Result;
         use pretty_assertions::assert_eq;
-        use syntect::parsing::SyntaxSet;
+        use pulldown_cmark::Options;
 
         use crate::*;
 
-        use super::render_string;
+        use super::{render_string, test_settings};
 
         fn render(markup: &str) -> Result<String> {
+            render_with_stylesheet(markup, Stylesheet::default())
+        }
+
+        fn render_with_stylesheet(markup: &str, stylesheet: Stylesheet) -> Result<String> {
             render_string(
                 markup,
+                Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES,
                 &Settings {
-                    resource_access: ResourceAccess::LocalOnly,
-                    syntax_set: SyntaxSet::default(),
-                    terminal_capabilities: TerminalCapabilities::none(),
-                    terminal_size: TerminalSize::default(),
+                    stylesheet,
+                    ..test_settings(TerminalCapabilities::none())
                 },
             )
         }
@@ -312,26 +442,197 @@ Hello Donald[2]
 [2]: http://example.com/Donald\n"
             )
         }
+
+        #[test]
+        fn reflow_soft_breaks() {
+            assert_eq!(
+                render(
+                    "Lorem ipsum dolor sit amet,
+consetetur sadipscing elitr,
+sed diam nonumy eirmod tempor invidunt ut labore et dolore magna aliquyam erat."
+                )
+                .unwrap(),
+                "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod
+tempor invidunt ut labore et dolore magna aliquyam erat.\n"
+            )
+        }
+
+        #[test]
+        fn wrap_with_indent_in_block_quotes_and_lists() {
+            assert_eq!(
+                render(
+                    "> Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod tempor.
+
+* Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod tempor."
+                )
+                .unwrap(),
+                "    Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy
+    eirmod tempor.
+
+\u{2022} Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy
+  eirmod tempor.\n"
+            )
+        }
+
+        #[test]
+        fn wrap_wide_characters() {
+            let markup = "日本語".repeat(20);
+            assert_eq!(
+                render(&markup).unwrap(),
+                format!(
+                    "{}\n{}\n",
+                    "日本語".repeat(13) + "日",
+                    "本語".to_string() + &"日本語".repeat(6)
+                )
+            )
+        }
+
+        #[test]
+        fn keep_code_spans_and_inline_html_verbatim() {
+            assert_eq!(
+                render(&format!(
+                    "{} `a    b` <span  class=\"x\">",
+                    "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam"
+                ))
+                .unwrap(),
+                "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam a    b
+<span  class=\"x\">\n"
+            )
+        }
+
+        #[test]
+        fn do_not_break_long_words() {
+            let word = "a".repeat(100);
+            assert_eq!(
+                render(&format!("Hello {} World", word)).unwrap(),
+                format!("Hello\n{}\nWorld\n", word)
+            )
+        }
+
+        #[test]
+        fn footnotes_at_end() {
+            assert_eq!(
+                render(
+                    "Hello[^world] and goodbye[^1].
+
+[^1]: A [link](http://example.com) in a footnote.
+
+# Headline
+
+[^world]: The world."
+                )
+                .unwrap(),
+                "Hello¹ and goodbye².
+
+┄Headline
+
+¹ The world.
+
+² A link[1] in a footnote.
+
+[1]: http://example.com\n"
+            )
+        }
+
+        #[test]
+        fn table_with_ascii_borders() {
+            assert_eq!(
+                render(
+                    "| Left | Right |
+| :--- | ----: |
+| a | b |"
+                )
+                .unwrap(),
+                "+------+-------+
+| Left | Right |
++------+-------+
+| a    |     b |
++------+-------+\n"
+            )
+        }
+
+        #[test]
+        fn table_wraps_cells_to_columns() {
+            let markup = "| Name | Description |
+| ---- | ----------- |
+| mdcat | Fancy cat for Markdown (that is, CommonMark) which renders all basic CommonMark syntax, highlights code blocks, and shows links and images inline in supported terminals. |";
+            let output = render(markup).unwrap();
+            assert!(output.lines().count() > 5, "{}", output);
+            for line in output.lines() {
+                assert_eq!(line.chars().count(), 80, "{}", line);
+            }
+        }
+
+        #[test]
+        fn glyphs_from_stylesheet() {
+            let stylesheet = Stylesheet::from_toml(
+                r###"
+rule = { glyph = "-" }
+
+[[headings]]
+prefix = "# "
+
+[[headings]]
+prefix = "## "
+
+[[bullets]]
+glyph = "*"
+
+[[bullets]]
+glyph = "->"
+"###,
+            )
+            .unwrap();
+            assert_eq!(
+                render_with_stylesheet(
+                    "# Title
+
+## Section
+
+### Subsection
+
+* foo
+  * bar
+
+    baz
+* spam
+
+----",
+                    stylesheet
+                )
+                .unwrap(),
+                "# Title
+
+## Section
+
+## Subsection
+
+* foo
+  -> bar
+
+     baz
+
+* spam
+
+--------------------------------------------------------------------------------\n"
+            )
+        }
     }
 
     mod disabled_features {
         use anyhow::Result;
         use pretty_assertions::assert_eq;
-        use syntect::parsing::SyntaxSet;
+        use pulldown_cmark::Options;
 
         use crate::*;
 
-        use super::render_string;
+        use super::{render_string, test_settings};
 
         fn render(markup: &str) -> Result<String> {
             render_string(
                 markup,
-                &Settings {
-                    resource_access: ResourceAccess::LocalOnly,
-                    syntax_set: SyntaxSet::default(),
-                    terminal_capabilities: TerminalCapabilities::none(),
-                    terminal_size: TerminalSize::default(),
-                },
+                Options::empty(),
+                &test_settings(TerminalCapabilities::none()),
             )
         }
 
@@ -351,4 +652,374 @@ Hello Donald[2]
             )
         }
     }
+
+    mod anchors {
+        use pretty_assertions::assert_eq;
+        use pulldown_cmark::Parser;
+
+        use crate::*;
+
+        use super::test_settings;
+
+        fn render(markup: &str, capabilities: TerminalCapabilities) -> String {
+            let directory = std::env::current_dir().unwrap();
+            let mut env = Environment::for_local_directory(&directory).unwrap();
+            env.anchors.insert(
+                Url::from_file_path(directory.join("docs").join("install.md")).unwrap(),
+                "§2".to_string(),
+            );
+            let settings = test_settings(capabilities);
+            let mut sink = Vec::new();
+            push_tty(&settings, &env, &mut sink, Parser::new(markup)).unwrap();
+            String::from_utf8(sink).unwrap()
+        }
+
+        #[test]
+        fn refer_to_anchors_of_documents_in_same_output() {
+            assert_eq!(
+                render(
+                    "See [install](./docs/install.md#usage) and [home](https://example.com).",
+                    TerminalCapabilities::none()
+                ),
+                "See install[§2] and home[1].\n\n[1]: https://example.com\n"
+            )
+        }
+
+        #[test]
+        fn do_not_link_documents_in_same_output() {
+            let rendered = render(
+                "See [install](docs/install.md).",
+                TerminalCapabilities::vte50(),
+            );
+            assert!(!rendered.contains("\x1b]8;"), "Rendered: {:?}", rendered);
+            assert!(rendered.contains("[§2]"), "Rendered: {:?}", rendered);
+        }
+
+        #[test]
+        fn refer_to_headings_in_same_document() {
+            assert_eq!(
+                render(
+                    "# Usage\n\nSee [install](#installation) and [other](#other).\n\n## Installation",
+                    TerminalCapabilities::none()
+                ),
+                "┄Usage\n\nSee install[1] and other[2].\n\n[1]: #installation (Installation)\n[2]: #other\n\n┄┄Installation\n"
+            )
+        }
+
+        #[test]
+        fn do_not_link_headings_in_same_document() {
+            let rendered = render(
+                "See [install](#installation).\n\n# Installation",
+                TerminalCapabilities::vte50(),
+            );
+            assert!(!rendered.contains("\x1b]8;"), "Rendered: {:?}", rendered);
+            assert!(
+                rendered.contains("#installation (Installation)"),
+                "Rendered: {:?}",
+                rendered
+            );
+        }
+    }
+
+    mod render_errors {
+        use pretty_assertions::assert_eq;
+        use pulldown_cmark::{Event, Parser, Tag};
+        use std::sync::{Arc, Mutex};
+
+        use crate::*;
+
+        use super::test_settings;
+
+        /// Collect warnings as strings.
+        #[derive(Debug, Clone, Default)]
+        struct Warnings(Arc<Mutex<Vec<String>>>);
+
+        impl Diagnostics for Warnings {
+            fn warn(&self, warning: &Error) {
+                self.0.lock().unwrap().push(warning.to_string());
+            }
+        }
+
+        fn render_with<'a, I>(
+            events: I,
+            error_recovery: ErrorRecovery,
+            capabilities: TerminalCapabilities,
+            warnings: &Warnings,
+        ) -> std::result::Result<String, Error>
+        where
+            I: IntoIterator<Item = Event<'a>>,
+        {
+            let env = Environment::for_local_directory(&std::env::current_dir()?)?;
+            let settings = Settings {
+                error_recovery,
+                diagnostics: Some(Box::new(warnings.clone())),
+                ..test_settings(capabilities)
+            };
+            let mut sink = Vec::new();
+            push_tty(&settings, &env, &mut sink, events.into_iter())?;
+            Ok(String::from_utf8(sink).unwrap())
+        }
+
+        fn render<'a, I>(
+            events: I,
+            error_recovery: ErrorRecovery,
+        ) -> std::result::Result<String, Error>
+        where
+            I: IntoIterator<Item = Event<'a>>,
+        {
+            let warnings = Warnings::default();
+            render_with(
+                events,
+                error_recovery,
+                TerminalCapabilities::none(),
+                &warnings,
+            )
+        }
+
+        fn render_error(error: Error) -> RenderError {
+            match error {
+                Error::Render(error) => error,
+                other => panic!("Not a render error: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn impossible_event() {
+            let events = vec![
+                Event::Text("foo".into()),
+                Event::End(Tag::Paragraph),
+                Event::Text("bar".into()),
+            ];
+            let error = render(events.clone(), ErrorRecovery::Fail).unwrap_err();
+            assert!(
+                matches!(render_error(error), RenderError::ImpossibleEvent { .. }),
+                "Unexpected error"
+            );
+            assert_eq!(render(events, ErrorRecovery::Degrade).unwrap(), "foobar");
+        }
+
+        #[test]
+        fn nesting_too_deep() {
+            let markup = format!("{} foo", ">".repeat(200));
+            let error = render(Parser::new(&markup), ErrorRecovery::Fail).unwrap_err();
+            assert_eq!(
+                render_error(error),
+                RenderError::NestingTooDeep { limit: 100 }
+            );
+            let rendered = render(Parser::new(&markup), ErrorRecovery::Degrade).unwrap();
+            assert!(rendered.contains("foo"), "Rendered: {:?}", rendered);
+        }
+
+        #[test]
+        fn render_content_after_too_deeply_nested_block() {
+            let warnings = Warnings::default();
+            let markup = format!("- a\n\n  {}foo\n\n  bar\n\nbaz", "> ".repeat(120));
+            let rendered = render_with(
+                Parser::new(&markup),
+                ErrorRecovery::Degrade,
+                TerminalCapabilities::none(),
+                &warnings,
+            )
+            .unwrap();
+            // The item continues after the nested block, and the list ends
+            // before the last paragraph.
+            assert!(
+                rendered.ends_with("foo\n  bar\n\nbaz\n"),
+                "Rendered: {:?}",
+                rendered
+            );
+            let warnings = warnings.0.lock().unwrap();
+            assert!(
+                warnings
+                    .iter()
+                    .all(|warning| !warning.contains("Event End")),
+                "Warnings: {:?}",
+                warnings
+            );
+        }
+
+        #[test]
+        fn unfinished_state() {
+            let events = vec![Event::Start(Tag::Paragraph), Event::Text("foo".into())];
+            let error = render(events.clone(), ErrorRecovery::Fail).unwrap_err();
+            assert!(
+                matches!(render_error(error), RenderError::UnfinishedState { .. }),
+                "Unexpected error"
+            );
+            let warnings = Warnings::default();
+            let rendered = render_with(
+                events,
+                ErrorRecovery::Degrade,
+                TerminalCapabilities::none(),
+                &warnings,
+            )
+            .unwrap();
+            assert_eq!(rendered, "foo\n");
+            let warnings = warnings.0.lock().unwrap();
+            assert_eq!(warnings.len(), 1, "Warnings: {:?}", warnings);
+            assert!(
+                warnings[0].starts_with("Must finish in state TopLevel"),
+                "Warnings: {:?}",
+                warnings
+            );
+        }
+
+        #[test]
+        fn report_render_errors_when_degrading() {
+            let warnings = Warnings::default();
+            let events = vec![Event::End(Tag::Paragraph), Event::Text("foo".into())];
+            let rendered = render_with(
+                events,
+                ErrorRecovery::Degrade,
+                TerminalCapabilities::none(),
+                &warnings,
+            )
+            .unwrap();
+            assert_eq!(rendered, "foo");
+            assert_eq!(
+                *warnings.0.lock().unwrap(),
+                vec![
+                    "Event End(Paragraph) impossible in state TopLevel(TopLevelAttrs { margin_before: NoMargin })",
+                    "Event Text(Borrowed(\"foo\")) impossible in state TopLevel(TopLevelAttrs { margin_before: NoMargin })",
+                ]
+            );
+        }
+
+        #[test]
+        fn report_images_which_cannot_be_shown() {
+            let warnings = Warnings::default();
+            let rendered = render_with(
+                Parser::new("![missing](does-not-exist.png) ![denied](/etc/passwd)"),
+                ErrorRecovery::Fail,
+                TerminalCapabilities::iterm2(),
+                &warnings,
+            )
+            .unwrap();
+            assert!(rendered.contains("missing"), "Rendered: {:?}", rendered);
+            let warnings = warnings.0.lock().unwrap();
+            assert_eq!(warnings.len(), 2, "Warnings: {:?}", warnings);
+            let missing = Url::from_directory_path(std::env::current_dir().unwrap())
+                .unwrap()
+                .join("does-not-exist.png")
+                .unwrap();
+            assert!(
+                warnings[0].starts_with(&format!("Failed to open file at {}: ", missing)),
+                "Warnings: {:?}",
+                warnings
+            );
+            assert!(
+                warnings[1].starts_with("Access denied to URL file:///etc/passwd: outside of"),
+                "Warnings: {:?}",
+                warnings
+            );
+        }
+
+        #[test]
+        fn degrade_gracefully_on_nasty_documents() {
+            let documents = vec![
+                format!("{}foo", "- ".repeat(150)),
+                format!("{}foo", "> - ".repeat(80)),
+                format!("{}foo{}", "*_".repeat(100), "_*".repeat(100)),
+                format!("{}foo\n\n[^1]: {}", "> [^1] ".repeat(60), "> ".repeat(120)),
+                "| a |\n|---|\n| <div> |\n\n<div>\n\n> | b |\n> |---|".to_string(),
+                format!("{}---", "> ".repeat(30)),
+                format!("{}a\n{}***", "- ".repeat(45), "  ".repeat(45)),
+            ];
+            for document in documents {
+                let parser = Parser::new_ext(&document, pulldown_cmark::Options::all());
+                render(parser, ErrorRecovery::Degrade).unwrap();
+            }
+        }
+    }
+
+    mod renderers {
+        use pretty_assertions::assert_eq;
+        use pulldown_cmark::Parser;
+
+        use crate::*;
+
+        use super::test_settings;
+
+        /// Record all output in a string with HTML-like markup.
+        #[derive(Debug, Default)]
+        struct Recorder {
+            output: String,
+            show_images: bool,
+        }
+
+        impl Renderer for Recorder {
+            fn write_styled(&mut self, _style: &ansi_term::Style, text: &str) -> Result<()> {
+                self.output.push_str(text);
+                Ok(())
+            }
+
+            fn start_link(&mut self, url: &Url) -> Result<()> {
+                self.output.push_str(&format!("<a {}>", url));
+                Ok(())
+            }
+
+            fn end_link(&mut self) -> Result<()> {
+                self.output.push_str("</a>");
+                Ok(())
+            }
+
+            fn start_block(&mut self, block: Block) -> Result<()> {
+                self.output.push_str(&format!("<{:?}>", block));
+                Ok(())
+            }
+
+            fn end_block(&mut self, block: Block) -> Result<()> {
+                self.output.push_str(&format!("</{:?}>", block));
+                Ok(())
+            }
+
+            fn write_image(
+                &mut self,
+                image: &InlineImage<'_>,
+            ) -> std::result::Result<Option<usize>, Error> {
+                if self.show_images {
+                    self.output.push_str(&format!("<img {}>", image.url));
+                    Ok(Some(image.column + 1))
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+
+        fn render(markup: &str, show_images: bool) -> String {
+            let env =
+                Environment::for_localhost(Url::parse("http://example.com/").unwrap()).unwrap();
+            let settings = test_settings(TerminalCapabilities::vte50());
+            let mut recorder = Recorder {
+                show_images,
+                ..Recorder::default()
+            };
+            push_events(&settings, &env, &mut recorder, Parser::new(markup)).unwrap();
+            recorder.output
+        }
+
+        #[test]
+        fn write_blocks_and_links() {
+            assert_eq!(
+                render("# Hello\n\nSee [docs](docs.html).\n\n- foo\n---", false),
+                "<Heading(1)>┄Hello\n</Heading(1)><Paragraph>\nSee <a http://example.com/docs.html>docs</a>.\n</Paragraph><List>\n<Item>• foo\n</Item></List><Rule>\n════════════════════════════════════════════════════════════════════════════════\n</Rule>"
+            );
+        }
+
+        #[test]
+        fn link_images_the_renderer_cannot_show() {
+            assert_eq!(
+                render("![logo](logo.png)", false),
+                "<Paragraph><a http://example.com/logo.png>logo</a>\n</Paragraph>"
+            );
+        }
+
+        #[test]
+        fn show_images_with_the_renderer() {
+            assert_eq!(
+                render("An image: ![logo](logo.png)", true),
+                "<Paragraph>An image: <img http://example.com/logo.png>\n</Paragraph>"
+            );
+        }
+    }
 }
@@ -22,9 +22,103 @@ pub fn is_png(mime: &Mime) -> bool {
     *mime == mime::IMAGE_PNG
 }
 
+/// Whether `buffer` starts with an SVG document.
+///
+/// Skip over a byte order mark, whitespace, the XML prolog, comments, and the
+/// document type declaration, and check whether the first element is `<svg>`.
+fn is_svg_document(buffer: &[u8]) -> bool {
+    // The start of the document is enough to find the root element
+    let head = String::from_utf8_lossy(&buffer[..buffer.len().min(4096)]);
+    let mut rest = head.trim_start_matches('\u{feff}');
+    loop {
+        rest = rest.trim_start();
+        let end = if rest.starts_with("<?") {
+            rest.find("?>").map(|index| index + 2)
+        } else if rest.starts_with("<!--") {
+            rest.find("-->").map(|index| index + 3)
+        } else if rest.starts_with("<!DOCTYPE") || rest.starts_with("<!doctype") {
+            // Skip over an internal subset of the declaration, if any
+            match (rest.find('['), rest.find('>')) {
+                (Some(subset), Some(end)) if subset < end => rest.find("]>").map(|index| index + 2),
+                (_, end) => end.map(|index| index + 1),
+            }
+        } else {
+            let element = rest
+                .strip_prefix("<svg")
+                .or_else(|| rest.strip_prefix("<svg:svg"));
+            return element.is_some_and(|element| {
+                element.starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            });
+        };
+        match end {
+            Some(end) => rest = &rest[end..],
+            None => return false,
+        }
+    }
+}
+
+/// Detect the mime type of an image from the magic bytes in `buffer`.
+///
+/// Support PNG, JPEG, GIF, WebP, BMP, ICO, TIFF and SVG images; return `None`
+/// for anything else.
+pub fn sniff_mime_type(buffer: &[u8]) -> Option<Mime> {
+    let mime = if buffer.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if buffer.starts_with(b"\xff\xd8\xff") {
+        "image/jpeg"
+    } else if buffer.starts_with(b"GIF87a") || buffer.starts_with(b"GIF89a") {
+        "image/gif"
+    } else if buffer.starts_with(b"RIFF") && buffer.get(8..12) == Some(b"WEBP") {
+        "image/webp"
+    } else if buffer.starts_with(b"BM") {
+        "image/bmp"
+    } else if buffer.starts_with(b"\0\0\x01\0") {
+        "image/x-icon"
+    } else if buffer.starts_with(b"II*\0") || buffer.starts_with(b"MM\0*") {
+        "image/tiff"
+    } else if is_svg_document(buffer) {
+        "image/svg+xml"
+    } else {
+        return None;
+    };
+    mime.parse().ok()
+}
+
+/// Guess the mime type of an image from the extension of `path`.
+pub fn mime_type_from_extension(path: &str) -> Option<Mime> {
+    let extension = path.rsplit('/').next()?.rsplit_once('.')?.1;
+    let mime = match extension.to_ascii_lowercase().as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "ico" => "image/x-icon",
+        "tif" | "tiff" => "image/tiff",
+        "svg" => "image/svg+xml",
+        _ => return None,
+    };
+    mime.parse().ok()
+}
+
+/// Detect the mime type of `buffer`.
+///
+/// Look at the magic bytes of `buffer` first, see [`sniff_mime_type`].  If
+/// they're inconclusive use `hint` if it denotes an image type, e.g. from the
+/// `Content-Type` of a HTTP response or the extension of a file.  As a last
+/// resort ask `file`; if `file` isn't installed assume arbitrary binary data.
+pub fn detect_mime_type(buffer: &[u8], hint: Option<&Mime>) -> Result<Mime> {
+    match sniff_mime_type(buffer).or_else(|| hint.filter(|m| m.type_() == mime::IMAGE).cloned()) {
+        Some(mime) => Ok(mime),
+        None => Ok(detect_mime_type_with_file(buffer)?.unwrap_or(mime::APPLICATION_OCTET_STREAM)),
+    }
+}
+
 /// Detect mime type with `file`.
-pub fn detect_mime_type(buffer: &[u8]) -> Result<Mime> {
-    let mut process = Command::new("file")
+///
+/// Return `None` if `file` is not installed.
+fn detect_mime_type_with_file(buffer: &[u8]) -> Result<Option<Mime>> {
+    let mut process = match Command::new("file")
         .arg("--brief")
         .arg("--mime-type")
         .arg("-")
@@ -32,7 +126,13 @@ pub fn detect_mime_type(buffer: &[u8]) -> Result<Mime> {
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
-        .with_context(|| "Failed to spawn mime --brief --mime-type")?;
+    {
+        Ok(process) => process,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(error) => {
+            return Err(error).with_context(|| "Failed to spawn mime --brief --mime-type")
+        }
+    };
 
     process
         .stdin
@@ -58,6 +158,7 @@ pub fn detect_mime_type(buffer: &[u8]) -> Result<Mime> {
             .trim();
         stdout
             .parse()
+            .map(Some)
             .with_context(|| format!("Failed to parse mime type from output: {}", stdout))
     } else {
         Err(anyhow!(
@@ -76,7 +177,7 @@ mod tests {
     #[test]
     fn detect_mimetype_of_png_image() {
         let data = include_bytes!("../sample/rust-logo-128x128.png");
-        let result = detect_mime_type(data);
+        let result = detect_mime_type(data, None);
         assert!(result.is_ok(), "Unexpected error: {:?}", result);
         assert_eq!(result.unwrap(), mime::IMAGE_PNG);
     }
@@ -84,28 +185,97 @@ mod tests {
     #[test]
     fn detect_mimetype_of_svg_image() {
         let data = include_bytes!("../sample/rust-logo.svg");
-        let result = detect_mime_type(data);
+        let result = detect_mime_type(data, None);
         assert!(result.is_ok(), "Unexpected error: {:?}", result);
         let mime = result.unwrap();
         assert_eq!(mime.type_(), mime::IMAGE);
         assert_eq!(mime.subtype().as_str(), "svg");
     }
 
+    #[test]
+    fn sniff_image_types() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", "image/png"),
+            (b"\xff\xd8\xff\xe0\0\x10JFIF", "image/jpeg"),
+            (b"GIF89a\x01\0\x01\0", "image/gif"),
+            (b"GIF87a\x01\0\x01\0", "image/gif"),
+            (b"RIFF\x24\0\0\0WEBPVP8 ", "image/webp"),
+            (b"BM\x36\0\0\0", "image/bmp"),
+            (b"\0\0\x01\0\x01\0\x10\x10", "image/x-icon"),
+            (b"II*\0\x08\0\0\0", "image/tiff"),
+            (b"MM\0*\0\0\0\x08", "image/tiff"),
+            (
+                b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>",
+                "image/svg+xml",
+            ),
+        ];
+        for (data, mime) in cases {
+            assert_eq!(
+                sniff_mime_type(data).map(|m| m.to_string()).as_deref(),
+                Some(*mime)
+            );
+        }
+        assert_eq!(sniff_mime_type(b"RIFF\x24\0\0\0WAVEfmt "), None);
+        assert_eq!(sniff_mime_type(b"Hello world"), None);
+        assert_eq!(sniff_mime_type(b""), None);
+    }
+
+    #[test]
+    fn sniff_svg_with_prolog_and_doctype() {
+        let svg = "\u{feff}<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>
+<!-- Created with Inkscape -->
+<!DOCTYPE svg PUBLIC \"-//W3C//DTD SVG 1.1//EN\"
+  \"http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd\" [
+  <!ENTITY ns_svg \"http://www.w3.org/2000/svg\">
+]>
+<svg
+   width=\"10\" height=\"10\"></svg>";
+        assert!(sniff_mime_type(svg.as_bytes()).is_some_and(|mime| is_svg(&mime)));
+        assert_eq!(
+            sniff_mime_type(b"<?xml version=\"1.0\"?><html></html>"),
+            None
+        );
+        assert_eq!(sniff_mime_type(b"<svgfoo/>"), None);
+    }
+
+    #[test]
+    fn mime_type_from_file_extension() {
+        assert_eq!(
+            mime_type_from_extension("/images/logo.PNG"),
+            Some(mime::IMAGE_PNG)
+        );
+        assert!(mime_type_from_extension("logo.svg").is_some_and(|mime| is_svg(&mime)));
+        assert_eq!(mime_type_from_extension("/foo.d/logo"), None);
+        assert_eq!(mime_type_from_extension("README.md"), None);
+    }
+
+    #[test]
+    fn prefer_magic_bytes_over_hint() {
+        let data = include_bytes!("../sample/rust-logo-128x128.png");
+        let hint = "image/gif".parse::<Mime>().unwrap();
+        assert_eq!(
+            detect_mime_type(data, Some(&hint)).unwrap(),
+            mime::IMAGE_PNG
+        );
+    }
+
+    #[test]
+    fn use_image_hint_for_unknown_data() {
+        let hint = "image/avif".parse::<Mime>().unwrap();
+        assert_eq!(detect_mime_type(b"\0\0\0", Some(&hint)).unwrap(), hint);
+    }
+
     #[test]
     fn detect_mimetype_of_magic_param_bytes_max_length() {
-        let data = std::iter::repeat(b'\0')
-            .take(1_048_576)
-            .collect::<Vec<u8>>();
-        let result = detect_mime_type(&data);
+        let data = vec![0_u8; 1_048_576];
+        let result = detect_mime_type(&data, None);
         assert!(result.is_ok(), "Unexpected error: {:?}", result);
     }
 
     #[test]
     fn detect_mimetype_of_larger_than_magic_param_bytes_max_length() {
-        let data = std::iter::repeat(b'\0')
-            .take(1_048_576 * 2)
-            .collect::<Vec<u8>>();
-        let result = detect_mime_type(&data);
+        let data = vec![0_u8; 1_048_576 * 2];
+        let result = detect_mime_type(&data, None);
         assert!(result.is_ok(), "Unexpected error: {:?}", result);
     }
 }
@@ -0,0 +1,356 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The outline of a document.
+
+use pulldown_cmark::{CowStr, Event, Tag};
+use std::collections::HashMap;
+
+/// A heading in the outline of a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    /// The level of the heading, from 1 to 6.
+    pub level: u32,
+    /// The text of the heading, without any formatting.
+    pub text: String,
+    /// The slug of the heading, unique within the document.
+    ///
+    /// Like GitHub derive the slug from the text of the heading, and append
+    /// `-1`, `-2`, etc. to the slugs of repeated headings.
+    pub slug: String,
+}
+
+/// The outline of a document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Outline {
+    headings: Vec<Heading>,
+}
+
+/// Turn `text` into a slug like GitHub does for headings.
+///
+/// Lowercase all letters, remove all characters other than letters, numbers,
+/// `-` and `_`, and replace spaces with `-`.
+pub(crate) fn slugify(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Unique slugs for headings in a document.
+///
+/// Like GitHub add `-1`, `-2`, etc. to repeated slugs.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Slugs {
+    counts: HashMap<String, usize>,
+}
+
+impl Slugs {
+    /// Get a unique slug for a heading with the given `text`.
+    pub(crate) fn slug(&mut self, text: &str) -> String {
+        let slug = slugify(text);
+        let mut candidate = slug.clone();
+        while let Some(count) = self.counts.get(&candidate) {
+            let suffix = count + 1;
+            self.counts.insert(candidate, suffix);
+            candidate = format!("{}-{}", slug, suffix);
+        }
+        self.counts.insert(candidate.clone(), 0);
+        candidate
+    }
+}
+
+impl Outline {
+    /// Collect the outline of the document in `events`.
+    pub fn from_events<'a, 'e: 'a, I>(events: I) -> Outline
+    where
+        I: IntoIterator<Item = &'a Event<'e>>,
+    {
+        let mut slugs = Slugs::default();
+        let mut headings = Vec::new();
+        let mut current: Option<(u32, String)> = None;
+        for event in events {
+            match (event, &mut current) {
+                (Event::Start(Tag::Heading(level)), _) => current = Some((*level, String::new())),
+                (Event::End(Tag::Heading(_)), Some(_)) => {
+                    if let Some((level, text)) = current.take() {
+                        let slug = slugs.slug(&text);
+                        headings.push(Heading { level, text, slug });
+                    }
+                }
+                (Event::Text(text), Some((_, heading)))
+                | (Event::Code(text), Some((_, heading))) => heading.push_str(text),
+                (Event::SoftBreak, Some((_, heading))) => heading.push(' '),
+                _ => {}
+            }
+        }
+        Outline { headings }
+    }
+
+    /// All headings of this outline, in document order.
+    pub fn headings(&self) -> &[Heading] {
+        &self.headings
+    }
+
+    /// Whether this outline has no headings.
+    pub fn is_empty(&self) -> bool {
+        self.headings.is_empty()
+    }
+
+    /// Get the heading a fragment-only `reference` like `#installation` refers to.
+    ///
+    /// Compare the fragment to the slugs of all headings, ignoring case.
+    pub fn find_fragment(&self, reference: &str) -> Option<&Heading> {
+        let fragment = reference.strip_prefix('#')?;
+        self.headings
+            .iter()
+            .find(|heading| heading.slug == fragment)
+            .or_else(|| {
+                self.headings
+                    .iter()
+                    .find(|heading| heading.slug.eq_ignore_ascii_case(fragment))
+            })
+    }
+
+    /// Find the heading which best matches `query`.
+    ///
+    /// Match `query` against the text and the slug of each heading, ignoring
+    /// case.  Prefer exact matches, then headings whose slug contains the slug
+    /// of `query`, and then headings whose slug contains all characters of the
+    /// slug of `query` in order.  Among equally good matches take the first
+    /// heading.
+    ///
+    /// Return the index of the heading in [`Outline::headings`].
+    pub fn find(&self, query: &str) -> Option<usize> {
+        let query_slug = slugify(query);
+        let exact = |heading: &Heading| {
+            heading.slug == query
+                || heading.slug == query_slug
+                || heading.text.trim().to_lowercase() == query.trim().to_lowercase()
+        };
+        let contains = |heading: &Heading| heading.slug.contains(&query_slug);
+        let fuzzy = |heading: &Heading| {
+            let mut chars = heading.slug.chars();
+            query_slug.chars().all(|c| chars.any(|h| h == c))
+        };
+        if query_slug.is_empty() {
+            self.headings.iter().position(exact)
+        } else {
+            self.headings
+                .iter()
+                .position(exact)
+                .or_else(|| self.headings.iter().position(contains))
+                .or_else(|| self.headings.iter().position(fuzzy))
+        }
+    }
+
+    /// Markdown events for this outline as nested list.
+    ///
+    /// Nest each heading under the closest preceding heading of a lower level,
+    /// and put headings without such a heading at the top level.
+    pub fn to_events(&self) -> Vec<Event<'_>> {
+        let mut events = Vec::new();
+        // The lowest heading level in each open list
+        let mut levels: Vec<u32> = Vec::new();
+        for heading in &self.headings {
+            while 1 < levels.len() && heading.level <= levels[levels.len() - 2] {
+                levels.pop();
+                events.push(Event::End(Tag::Item));
+                events.push(Event::End(Tag::List(None)));
+            }
+            match levels.last_mut() {
+                Some(level) if heading.level <= *level => {
+                    *level = heading.level;
+                    events.push(Event::End(Tag::Item));
+                }
+                _ => {
+                    levels.push(heading.level);
+                    events.push(Event::Start(Tag::List(None)));
+                }
+            }
+            events.push(Event::Start(Tag::Item));
+            events.push(Event::Text(CowStr::Borrowed(&heading.text)));
+        }
+        for _ in levels {
+            events.push(Event::End(Tag::Item));
+            events.push(Event::End(Tag::List(None)));
+        }
+        events
+    }
+}
+
+/// Filter `events` to the section under a heading.
+///
+/// Return only the events of the heading with the given `index` in the outline
+/// of `events`, and of all following events up to the next heading of the same
+/// or a lower level.
+pub fn section<'e, I>(events: I, index: usize) -> impl Iterator<Item = Event<'e>>
+where
+    I: IntoIterator<Item = Event<'e>>,
+{
+    // The number of headings so far, and the level of the selected heading
+    // once we reached it.
+    let mut headings = 0;
+    let mut level: Option<u32> = None;
+    events
+        .into_iter()
+        .map_while(move |event| {
+            if let Event::Start(Tag::Heading(current)) = event {
+                match level {
+                    Some(level) if current <= level => return None,
+                    Some(_) => {}
+                    None if headings == index => level = Some(current),
+                    None => {}
+                }
+                headings += 1;
+            }
+            Some(Some(event).filter(|_| level.is_some()))
+        })
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::Parser;
+
+    fn outline(markdown: &str) -> Outline {
+        let events: Vec<_> = Parser::new(markdown).collect();
+        Outline::from_events(&events)
+    }
+
+    #[test]
+    fn slugify_like_github() {
+        assert_eq!(slugify("Hello World"), "hello-world");
+        assert_eq!(slugify("What's new in 2.0?"), "whats-new-in-20");
+        assert_eq!(slugify("  foo_bar -- Baz  "), "foo_bar----baz");
+        assert_eq!(slugify("Über `code`"), "über-code");
+    }
+
+    #[test]
+    fn unique_slugs() {
+        let mut slugs = Slugs::default();
+        assert_eq!(slugs.slug("Foo"), "foo");
+        assert_eq!(slugs.slug("Foo"), "foo-1");
+        assert_eq!(slugs.slug("Foo"), "foo-2");
+        assert_eq!(slugs.slug("Foo 1"), "foo-1-1");
+    }
+
+    #[test]
+    fn collect_headings() {
+        let outline = outline(
+            "# The *first* heading\n\nText\n\nSecond `code`\n---\n\n### Third\n\n# The first heading",
+        );
+        assert_eq!(
+            outline.headings(),
+            &[
+                Heading {
+                    level: 1,
+                    text: "The first heading".to_string(),
+                    slug: "the-first-heading".to_string(),
+                },
+                Heading {
+                    level: 2,
+                    text: "Second code".to_string(),
+                    slug: "second-code".to_string(),
+                },
+                Heading {
+                    level: 3,
+                    text: "Third".to_string(),
+                    slug: "third".to_string(),
+                },
+                Heading {
+                    level: 1,
+                    text: "The first heading".to_string(),
+                    slug: "the-first-heading-1".to_string(),
+                },
+            ][..]
+        );
+    }
+
+    #[test]
+    fn find_headings() {
+        let outline = outline(
+            "# mdcat\n## [0.22.0] - 2020-10-25\n## [0.21.1] - 2020-09-01\n### Fixed\n## Installation\n## Install",
+        );
+        assert_eq!(outline.find("Install"), Some(5));
+        assert_eq!(outline.find("INSTALLATION"), Some(4));
+        assert_eq!(outline.find("0220---2020-10-25"), Some(1));
+        assert_eq!(outline.find("[0.21.1] - 2020-09-01"), Some(2));
+        assert_eq!(outline.find("0.21.1"), Some(2));
+        assert_eq!(outline.find("instl"), Some(4));
+        assert_eq!(outline.find("fixd"), Some(3));
+        assert_eq!(outline.find("unknown"), None);
+        assert_eq!(outline.find("?"), None);
+    }
+
+    #[test]
+    fn find_headings_for_fragments() {
+        let outline = outline("# Install\n## Usage\n# Usage");
+        assert_eq!(
+            outline.find_fragment("#install"),
+            Some(&outline.headings()[0])
+        );
+        assert_eq!(
+            outline.find_fragment("#Usage"),
+            Some(&outline.headings()[1])
+        );
+        assert_eq!(
+            outline.find_fragment("#usage-1"),
+            Some(&outline.headings()[2])
+        );
+        assert_eq!(outline.find_fragment("install"), None);
+        assert_eq!(outline.find_fragment("#missing"), None);
+        assert_eq!(outline.find_fragment("README.md#install"), None);
+    }
+
+    #[test]
+    fn filter_section() {
+        let markdown = "# A\n\nfoo\n\n## B\n\nbar\n\n### C\n\nbaz\n\n## D\n\nspam\n\n# E";
+        let text = |index| -> Vec<String> {
+            section(Parser::new(markdown), index)
+                .filter_map(|event| match event {
+                    Event::Text(text) => Some(text.to_string()),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(
+            text(0),
+            vec!["A", "foo", "B", "bar", "C", "baz", "D", "spam"]
+        );
+        assert_eq!(text(1), vec!["B", "bar", "C", "baz"]);
+        assert_eq!(text(2), vec!["C", "baz"]);
+        assert_eq!(text(3), vec!["D", "spam"]);
+        assert_eq!(text(4), vec!["E"]);
+        assert!(text(5).is_empty());
+    }
+
+    #[test]
+    fn nest_headings_as_lists() {
+        let outline = outline("## A\n# B\n### C\n## D\n## E\n# F");
+        let events: Vec<String> = outline
+            .to_events()
+            .iter()
+            .map(|event| match event {
+                Event::Start(Tag::List(_)) => "[".to_string(),
+                Event::End(Tag::List(_)) => "]".to_string(),
+                Event::Start(Tag::Item) => "(".to_string(),
+                Event::End(Tag::Item) => ")".to_string(),
+                Event::Text(text) => text.to_string(),
+                other => panic!("Unexpected event: {:?}", other),
+            })
+            .collect();
+        assert_eq!(events.concat(), "[(A)(B[(C)(D)(E)])(F)]");
+    }
+}
@@ -6,41 +6,91 @@
 
 //! Rendering algorithm.
 
-use std::io::prelude::*;
-
-use ansi_term::{Colour, Style};
-use anyhow::anyhow;
-use fehler::throws;
+use ansi_term::Style;
+use fehler::{throw, throws};
 use pulldown_cmark::Event::*;
 use pulldown_cmark::Tag::*;
-use pulldown_cmark::{Event, LinkType};
-use std::io::Error;
-use syntect::highlighting::{HighlightIterator, Highlighter, Theme};
+use pulldown_cmark::{CowStr, Event, LinkType, Tag};
+use syntect::highlighting::{HighlightIterator, Highlighter};
 use syntect::util::LinesWithEndings;
+use unicode_width::UnicodeWidthStr;
 use url::Url;
 
+use crate::renderer::{Block, InlineImage, Renderer};
+use crate::stylesheet::apply_style;
 use crate::terminal::*;
-use crate::{Environment, Settings};
+use crate::{Environment, Error, GlyphStyle, Settings};
 
 mod data;
+mod error;
 mod state;
+mod table;
+mod wrap;
 mod write;
 
 use crate::references::*;
+use crate::resources::{Prefetched, ResourceLoader};
 use state::*;
 use write::*;
 
+pub use wrap::ColumnWriter;
+
 use crate::render::state::MarginControl::{Margin, NoMargin};
 pub use data::StateData;
+pub use error::{ErrorRecovery, RenderError};
 pub use state::State;
 
+/// The block which `tag` starts or ends, if any.
+fn block_of(tag: &Tag) -> Option<Block> {
+    match tag {
+        Paragraph => Some(Block::Paragraph),
+        Heading(level) => Some(Block::Heading(*level)),
+        BlockQuote => Some(Block::BlockQuote),
+        CodeBlock(_) => Some(Block::CodeBlock),
+        List(_) => Some(Block::List),
+        Item => Some(Block::Item),
+        Table(_) => Some(Block::Table),
+        _ => None,
+    }
+}
+
+/// Write a single `event` in the given `state`.
+///
+/// Tell the renderer about the start and the end of blocks around the output
+/// for the event.
+#[throws]
+pub fn write_event<'a, R: Renderer>(
+    writer: &mut ColumnWriter<R>,
+    settings: &Settings,
+    environment: &Environment,
+    state: State,
+    data: StateData<'a>,
+    event: Event<'a>,
+) -> (State, StateData<'a>) {
+    let (start, end) = match &event {
+        // We do not render footnote definitions right away
+        _ if data.is_collecting_footnote_definition() => (None, None),
+        Start(tag) => (block_of(tag), None),
+        End(tag) => (None, block_of(tag)),
+        Rule => (Some(Block::Rule), Some(Block::Rule)),
+        _ => (None, None),
+    };
+    if let Some(block) = start {
+        writer.start_block(block)?;
+    }
+    let result = layout_event(writer, settings, environment, state, data, event)?;
+    if let Some(block) = end {
+        writer.end_block(block)?;
+    }
+    result
+}
+
 #[allow(clippy::cognitive_complexity)]
 #[throws]
-pub fn write_event<'a, W: Write>(
-    writer: &mut W,
+fn layout_event<'a, R: Renderer>(
+    writer: &mut ColumnWriter<R>,
     settings: &Settings,
     environment: &Environment,
-    theme: &Theme,
     state: State,
     data: StateData<'a>,
     event: Event<'a>,
@@ -50,10 +100,32 @@ pub fn write_event<'a, W: Write>(
     use self::StackedState::*;
     use State::*;
     match (state, event) {
+        // Refuse to nest deeper, to keep pathological documents from growing
+        // indentation and memory without bounds, and skip the end of every
+        // start we refused, to keep the ends of outer blocks in their place.
+        (state, event @ Start(_)) if MAX_STATES <= state.depth() => {
+            let (state, data) = recover(
+                writer,
+                settings,
+                state,
+                data,
+                event,
+                RenderError::NestingTooDeep { limit: MAX_STATES },
+            )?;
+            (state, data.skip_start())
+        }
+        (state, End(_)) if data.is_skipping() => (state, data.skip_end()),
+        // Footnote definitions go to the end of the document, so we just collect
+        // their events for now.
+        (state, event) if data.is_collecting_footnote_definition() => {
+            (state, data.collect_footnote_event(event))
+        }
+        (state, Start(FootnoteDefinition(label))) => (state, data.start_footnote_definition(label)),
+
         // Top level items
         (TopLevel(attrs), Start(Paragraph)) => {
             if attrs.margin_before != NoMargin {
-                writeln!(writer)?;
+                writer.newline()?;
             }
             State::stack_onto(TopLevelAttrs::margin_before())
                 .current(Inline(InlineText, InlineAttrs::default()))
@@ -61,31 +133,36 @@ pub fn write_event<'a, W: Write>(
         }
         (TopLevel(attrs), Start(Heading(level))) => {
             let (data, links) = data.take_links();
-            write_link_refs(writer, environment, &settings.terminal_capabilities, links)?;
+            write_link_refs(
+                writer,
+                environment,
+                &settings.terminal_capabilities,
+                &data.outline,
+                links,
+            )?;
             if attrs.margin_before != NoMargin {
-                writeln!(writer)?;
+                writer.newline()?;
             }
-            write_mark(writer, &settings.terminal_capabilities)?;
+            writer.set_mark()?;
 
             State::stack_onto(TopLevelAttrs::margin_before())
                 .current(write_start_heading(
                     writer,
-                    &settings.terminal_capabilities,
                     Style::new(),
-                    level,
+                    &settings.stylesheet.heading(level),
                 )?)
                 .and_data(data)
         }
         (TopLevel(attrs), Start(BlockQuote)) => {
             if attrs.margin_before != NoMargin {
-                writeln!(writer)?;
+                writer.newline()?;
             }
             State::stack_onto(TopLevelAttrs::margin_before())
                 .current(
                     // We've written a block-level margin already, so the first
                     // block inside the styled block should add another margin.
                     StyledBlockAttrs::default()
-                        .block_quote()
+                        .block_quote(&settings.stylesheet.quote)
                         .without_margin_before()
                         .into(),
                 )
@@ -93,19 +170,19 @@ pub fn write_event<'a, W: Write>(
         }
         (TopLevel(attrs), Rule) => {
             if attrs.margin_before != NoMargin {
-                writeln!(writer)?;
+                writer.newline()?;
             }
             write_rule(
                 writer,
-                &settings.terminal_capabilities,
+                &settings.stylesheet.rule,
                 settings.terminal_size.columns,
             )?;
-            writeln!(writer)?;
+            writer.newline()?;
             TopLevel(TopLevelAttrs::margin_before()).and_data(data)
         }
         (TopLevel(attrs), Start(CodeBlock(kind))) => {
             if attrs.margin_before != NoMargin {
-                writeln!(writer)?;
+                writer.newline()?;
             }
 
             State::stack_onto(TopLevelAttrs::margin_before())
@@ -115,13 +192,12 @@ pub fn write_event<'a, W: Write>(
                     0,
                     Style::new(),
                     kind,
-                    theme,
                 )?)
                 .and_data(data)
         }
         (TopLevel(attrs), Start(List(start))) => {
             if attrs.margin_before != NoMargin {
-                writeln!(writer)?;
+                writer.newline()?;
             }
             let kind = start.map_or(ListItemKind::Unordered, |start| {
                 ListItemKind::Ordered(start)
@@ -131,23 +207,26 @@ pub fn write_event<'a, W: Write>(
                 .current(Inline(ListItem(kind, StartItem), InlineAttrs::default()))
                 .and_data(data)
         }
+        (TopLevel(attrs), Start(Table(alignments))) => {
+            if attrs.margin_before != NoMargin {
+                writer.newline()?;
+            }
+            State::stack_onto(TopLevelAttrs::margin_before())
+                .current(TableBlockAttrs::new(0, Style::new(), alignments).into())
+                .and_data(data)
+        }
         (TopLevel(attrs), Html(html)) => {
             if attrs.margin_before == Margin {
-                writeln!(writer)?;
+                writer.newline()?;
             }
-            write_styled(
-                writer,
-                &settings.terminal_capabilities,
-                &Style::new().fg(Colour::Green),
-                html,
-            )?;
+            writer.write_styled(&settings.stylesheet.html, html)?;
             TopLevel(TopLevelAttrs::no_margin_for_html_only()).and_data(data)
         }
 
         // Nested blocks with style, e.g. paragraphs in quotes, etc.
         (Stacked(stack, StyledBlock(attrs)), Start(Paragraph)) => {
             if attrs.margin_before != NoMargin {
-                writeln!(writer)?;
+                writer.newline()?;
             }
             write_indent(writer, attrs.indent)?;
             let inline = InlineAttrs::from(&attrs);
@@ -158,31 +237,39 @@ pub fn write_event<'a, W: Write>(
         }
         (Stacked(stack, StyledBlock(attrs)), Start(BlockQuote)) => {
             if attrs.margin_before != NoMargin {
-                writeln!(writer)?;
+                writer.newline()?;
             }
             stack
                 .push(attrs.clone().with_margin_before().into())
-                .current(attrs.without_margin_before().block_quote().into())
+                .current(
+                    attrs
+                        .without_margin_before()
+                        .block_quote(&settings.stylesheet.quote)
+                        .into(),
+                )
                 .and_data(data)
         }
         (Stacked(stack, StyledBlock(attrs)), Rule) => {
             if attrs.margin_before != NoMargin {
-                writeln!(writer)?;
+                writer.newline()?;
             }
             write_indent(writer, attrs.indent)?;
             write_rule(
                 writer,
-                &settings.terminal_capabilities,
-                settings.terminal_size.columns - (attrs.indent as usize),
+                &settings.stylesheet.rule,
+                settings
+                    .terminal_size
+                    .columns
+                    .saturating_sub(attrs.indent as usize),
             )?;
-            writeln!(writer)?;
+            writer.newline()?;
             stack
                 .current(attrs.with_margin_before().into())
                 .and_data(data)
         }
         (Stacked(stack, StyledBlock(attrs)), Start(Heading(level))) => {
             if attrs.margin_before != NoMargin {
-                writeln!(writer)?;
+                writer.newline()?;
             }
             write_indent(writer, attrs.indent)?;
 
@@ -192,15 +279,14 @@ pub fn write_event<'a, W: Write>(
                 .push(attrs.with_margin_before().into())
                 .current(write_start_heading(
                     writer,
-                    &settings.terminal_capabilities,
                     style,
-                    level,
+                    &settings.stylesheet.heading(level),
                 )?)
                 .and_data(data)
         }
         (Stacked(stack, StyledBlock(attrs)), Start(List(start))) => {
             if attrs.margin_before != NoMargin {
-                writeln!(writer)?;
+                writer.newline()?;
             }
             let kind = start.map_or(ListItemKind::Unordered, |start| {
                 ListItemKind::Ordered(start)
@@ -213,27 +299,32 @@ pub fn write_event<'a, W: Write>(
         }
         (Stacked(stack, StyledBlock(attrs)), Start(CodeBlock(kind))) => {
             if attrs.margin_before != NoMargin {
-                writeln!(writer)?;
+                writer.newline()?;
             }
             let StyledBlockAttrs { indent, style, .. } = attrs;
             stack
                 .push(attrs.into())
                 .current(write_start_code_block(
-                    writer, settings, indent, style, kind, theme,
+                    writer, settings, indent, style, kind,
                 )?)
                 .and_data(data)
         }
+        (Stacked(stack, StyledBlock(attrs)), Start(Table(alignments))) => {
+            if attrs.margin_before != NoMargin {
+                writer.newline()?;
+            }
+            let table = TableBlockAttrs::new(attrs.indent, attrs.style, alignments);
+            stack
+                .push(attrs.with_margin_before().into())
+                .current(table.into())
+                .and_data(data)
+        }
         (Stacked(stack, StyledBlock(attrs)), Html(html)) => {
             if attrs.margin_before == Margin {
-                writeln!(writer)?;
+                writer.newline()?;
             }
             write_indent(writer, attrs.indent)?;
-            write_styled(
-                writer,
-                &settings.terminal_capabilities,
-                &attrs.style.fg(Colour::Green),
-                html,
-            )?;
+            writer.write_styled(&apply_style(attrs.style, &settings.stylesheet.html), html)?;
             stack
                 .current(attrs.without_margin_for_html_only().into())
                 .and_data(data)
@@ -244,16 +335,18 @@ pub fn write_event<'a, W: Write>(
             let InlineAttrs { indent, style } = attrs;
             if state == ItemBlock {
                 // Add margin
-                writeln!(writer)?;
+                writer.newline()?;
             }
             write_indent(writer, indent)?;
             let indent = match kind {
                 ListItemKind::Unordered => {
-                    write!(writer, "\u{2022} ")?;
-                    indent + 2
+                    let bullet = settings.stylesheet.bullet(stack.list_level());
+                    writer.write_styled(&bullet.style, &bullet.glyph)?;
+                    writer.write_plain(" ")?;
+                    indent + bullet_width(&bullet)
                 }
                 ListItemKind::Ordered(no) => {
-                    write!(writer, "{:>2}. ", no)?;
+                    writer.write_plain(format!("{:>2}. ", no))?;
                     indent + 4
                 }
             };
@@ -268,7 +361,7 @@ pub fn write_event<'a, W: Write>(
             if state != StartItem {
                 // Write margin, unless we're at the start of the list item in which case the first line of the
                 // paragraph should go right beside the item bullet.
-                writeln!(writer)?;
+                writer.newline()?;
                 write_indent(writer, attrs.indent)?;
             }
             stack
@@ -277,31 +370,32 @@ pub fn write_event<'a, W: Write>(
                 .and_data(data)
         }
         (Stacked(stack, Inline(ListItem(kind, _), attrs)), Start(CodeBlock(ck))) => {
-            writeln!(writer)?;
+            writer.newline()?;
             let InlineAttrs { indent, style } = attrs;
             stack
                 .push(Inline(ListItem(kind, ItemBlock), attrs))
-                .current(write_start_code_block(
-                    writer, settings, indent, style, ck, theme,
-                )?)
+                .current(write_start_code_block(writer, settings, indent, style, ck)?)
                 .and_data(data)
         }
         (Stacked(stack, Inline(ListItem(kind, _), attrs)), Rule) => {
-            writeln!(writer)?;
+            writer.newline()?;
             write_indent(writer, attrs.indent)?;
             write_rule(
                 writer,
-                &settings.terminal_capabilities,
-                settings.terminal_size.columns - (attrs.indent as usize),
+                &settings.stylesheet.rule,
+                settings
+                    .terminal_size
+                    .columns
+                    .saturating_sub(attrs.indent as usize),
             )?;
-            writeln!(writer)?;
+            writer.newline()?;
             stack
                 .current(Inline(ListItem(kind, ItemBlock), attrs))
                 .and_data(data)
         }
         (Stacked(stack, Inline(ListItem(kind, state), attrs)), Start(Heading(level))) => {
             if state != StartItem {
-                writeln!(writer)?;
+                writer.newline()?;
                 write_indent(writer, attrs.indent)?;
             }
             // We deliberately don't mark headings which aren't top-level.
@@ -310,14 +404,13 @@ pub fn write_event<'a, W: Write>(
                 .push(Inline(ListItem(kind, ItemBlock), attrs))
                 .current(write_start_heading(
                     writer,
-                    &settings.terminal_capabilities,
                     style,
-                    level,
+                    &settings.stylesheet.heading(level),
                 )?)
                 .and_data(data)
         }
         (Stacked(stack, Inline(ListItem(kind, _), attrs)), Start(List(start))) => {
-            writeln!(writer)?;
+            writer.newline()?;
             let nested_kind = start.map_or(ListItemKind::Unordered, |start| {
                 ListItemKind::Ordered(start)
             });
@@ -327,25 +420,41 @@ pub fn write_event<'a, W: Write>(
                 .and_data(data)
         }
         (Stacked(stack, Inline(ListItem(kind, _), attrs)), Start(BlockQuote)) => {
-            writeln!(writer)?;
+            writer.newline()?;
             let block_quote = StyledBlockAttrs::from(&attrs)
                 .without_margin_before()
-                .block_quote();
+                .block_quote(&settings.stylesheet.quote);
             stack
                 .push(Inline(ListItem(kind, ItemBlock), attrs))
                 .current(block_quote.into())
                 .and_data(data)
         }
+        (Stacked(stack, Inline(ListItem(kind, _), attrs)), Start(Table(alignments))) => {
+            writer.newline()?;
+            let table = TableBlockAttrs::new(attrs.indent, attrs.style, alignments);
+            stack
+                .push(Inline(ListItem(kind, ItemBlock), attrs))
+                .current(table.into())
+                .and_data(data)
+        }
         (Stacked(stack, Inline(ListItem(kind, state), attrs)), End(Item)) => {
             let InlineAttrs { indent, style } = attrs;
             if state != ItemBlock {
                 // End the inline text of this item
-                writeln!(writer)?;
+                writer.newline()?;
             }
             // Decrease indent back to the level where we can write the next item bullet, and increment the list item number.
             let (indent, kind) = match kind {
-                ListItemKind::Unordered => (indent - 2, ListItemKind::Unordered),
-                ListItemKind::Ordered(no) => (indent - 4, ListItemKind::Ordered(no + 1)),
+                ListItemKind::Unordered => {
+                    let bullet = settings.stylesheet.bullet(stack.list_level());
+                    (
+                        indent.saturating_sub(bullet_width(&bullet)),
+                        ListItemKind::Unordered,
+                    )
+                }
+                ListItemKind::Ordered(no) => {
+                    (indent.saturating_sub(4), ListItemKind::Ordered(no + 1))
+                }
             };
             stack
                 .current(Inline(ListItem(kind, state), InlineAttrs { style, indent }))
@@ -356,7 +465,7 @@ pub fn write_event<'a, W: Write>(
         (Stacked(stack, LiteralBlock(attrs)), Text(text)) => {
             let LiteralBlockAttrs { indent, style } = attrs;
             for line in LinesWithEndings::from(&text) {
-                write_styled(writer, &settings.terminal_capabilities, &style, line)?;
+                writer.write_styled(&style, line)?;
                 if line.ends_with('\n') {
                     write_indent(writer, indent)?;
                 }
@@ -364,24 +473,20 @@ pub fn write_event<'a, W: Write>(
             stack.current(attrs.into()).and_data(data)
         }
         (Stacked(stack, LiteralBlock(_)), End(CodeBlock(_))) => {
-            write_border(
-                writer,
-                &settings.terminal_capabilities,
-                &settings.terminal_size,
-            )?;
+            write_border(writer, &settings.stylesheet.border, &settings.terminal_size)?;
             stack.pop().and_data(data)
         }
 
         // Highlighted code blocks
         (Stacked(stack, HighlightBlock(mut attrs)), Text(text)) => {
-            let highlighter = Highlighter::new(theme);
+            let highlighter = Highlighter::new(&settings.theme);
             for line in LinesWithEndings::from(&text) {
                 let ops = attrs.parse_state.parse_line(line, &settings.syntax_set);
-                highlighting::write_as_ansi(
-                    writer,
-                    attrs.ansi,
-                    HighlightIterator::new(&mut attrs.highlight_state, &ops, line, &highlighter),
-                )?;
+                for (style, region) in
+                    HighlightIterator::new(&mut attrs.highlight_state, &ops, line, &highlighter)
+                {
+                    writer.write_styled(&highlighting::to_ansi_style(style), region)?;
+                }
                 if text.ends_with('\n') {
                     write_indent(writer, attrs.indent)?;
                 }
@@ -389,10 +494,130 @@ pub fn write_event<'a, W: Write>(
             stack.current(attrs.into()).and_data(data)
         }
         (Stacked(stack, HighlightBlock(_)), End(CodeBlock(_))) => {
-            write_border(
+            write_border(writer, &settings.stylesheet.border, &settings.terminal_size)?;
+            stack.pop().and_data(data)
+        }
+
+        // Tables
+        (Stacked(stack, TableBlock(mut attrs)), Start(TableHead)) => {
+            let style = attrs.style.bold();
+            attrs.push_style(style);
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), End(TableHead)) => {
+            attrs.pop_style();
+            attrs.table.end_head();
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(attrs)), Start(TableRow)) => {
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), End(TableRow)) => {
+            attrs.table.end_row();
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(attrs)), Start(TableCell)) => {
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), End(TableCell)) => {
+            attrs.table.end_cell();
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), Start(Emphasis)) => {
+            let style = Style {
+                is_italic: !attrs.style.is_italic,
+                ..attrs.style
+            };
+            attrs.push_style(style);
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), Start(Strong)) => {
+            let style = attrs.style.bold();
+            attrs.push_style(style);
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), Start(Strikethrough)) => {
+            let style = attrs.style.strikethrough();
+            attrs.push_style(style);
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), End(Emphasis))
+        | (Stacked(stack, TableBlock(mut attrs)), End(Strong))
+        | (Stacked(stack, TableBlock(mut attrs)), End(Strikethrough)) => {
+            attrs.pop_style();
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), Text(text)) => {
+            attrs.table.push_text(attrs.style, text.into_string());
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), Code(code)) => {
+            attrs.table.push_text(
+                apply_style(attrs.style, &settings.stylesheet.code),
+                code.into_string(),
+            );
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), Html(html)) => {
+            attrs.table.push_text(
+                apply_style(attrs.style, &settings.stylesheet.html),
+                html.into_string(),
+            );
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), FootnoteReference(label)) => {
+            let (data, index) = data.footnote_index(label);
+            let style = apply_style(attrs.style, &settings.stylesheet.footnote);
+            attrs.table.push_text(style, superscript(index));
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), SoftBreak)
+        | (Stacked(stack, TableBlock(mut attrs)), HardBreak) => {
+            attrs.table.push_text(attrs.style, " ");
+            stack.current(attrs.into()).and_data(data)
+        }
+        // We cannot write inline links in a table, because we do not write
+        // table contents right away, so always use reference links.
+        (Stacked(stack, TableBlock(mut attrs)), Start(Link(_, _, _))) => {
+            let style = apply_style(attrs.style, &settings.stylesheet.link);
+            attrs.push_style(style);
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), End(Link(LinkType::Autolink, _, _)))
+        | (Stacked(stack, TableBlock(mut attrs)), End(Link(LinkType::Email, _, _))) => {
+            attrs.pop_style();
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), End(Link(_, target, title))) => {
+            let (data, reference) = link_reference(environment, data, target, title, settings);
+            attrs
+                .table
+                .push_text(attrs.style, format!("[{}]", reference));
+            attrs.pop_style();
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), Start(Image(_, _, _))) => {
+            let style = apply_style(attrs.style, &settings.stylesheet.image);
+            attrs.push_style(style);
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(mut attrs)), End(Image(_, target, title))) => {
+            let (data, index) = data.add_link(target, title, settings.stylesheet.image);
+            attrs.table.push_text(attrs.style, format!("[{}]", index));
+            attrs.pop_style();
+            stack.current(attrs.into()).and_data(data)
+        }
+        (Stacked(stack, TableBlock(attrs)), End(Table(_))) => {
+            write_table(
                 writer,
                 &settings.terminal_capabilities,
-                &settings.terminal_size,
+                &settings.stylesheet,
+                attrs.indent,
+                settings
+                    .terminal_size
+                    .columns
+                    .saturating_sub(attrs.indent as usize),
+                &attrs.table,
             )?;
             stack.pop().and_data(data)
         }
@@ -429,34 +654,40 @@ pub fn write_event<'a, W: Write>(
         }
         (Stacked(stack, Inline(_, _)), End(Strikethrough)) => (stack.pop(), data),
         (Stacked(stack, Inline(state, attrs)), Code(code)) => {
-            write_styled(
+            write_unbroken(
                 writer,
-                &settings.terminal_capabilities,
-                &attrs.style.fg(Colour::Yellow),
+                &apply_style(attrs.style, &settings.stylesheet.code),
+                attrs.indent,
+                settings.terminal_size.columns,
                 code,
             )?;
             (stack.current(Inline(state, attrs)), data)
         }
         (Stacked(stack, Inline(ListItem(kind, state), attrs)), TaskListMarker(checked)) => {
             let marker = if checked { "\u{2611} " } else { "\u{2610} " };
-            write_styled(
-                writer,
-                &settings.terminal_capabilities,
-                &attrs.style,
-                marker,
-            )?;
+            writer.write_styled(&attrs.style, marker)?;
             stack
                 .current(Inline(ListItem(kind, state), attrs))
                 .and_data(data)
         }
+        (Stacked(stack, Inline(state, attrs)), FootnoteReference(label)) => {
+            // Do not link references to their definitions: OSC 8 links open
+            // URLs, and terminals have no target to jump to within our output.
+            // The marks at the definitions let iTerm2 users jump there instead.
+            let (data, index) = data.footnote_index(label);
+            let style = apply_style(attrs.style, &settings.stylesheet.footnote);
+            writer.write_styled(&style, superscript(index))?;
+            stack.current(Inline(state, attrs)).and_data(data)
+        }
         // Inline line breaks
         (Stacked(stack, Inline(state, attrs)), SoftBreak) => {
-            writeln!(writer)?;
-            write_indent(writer, attrs.indent)?;
+            // Reflow soft breaks: Turn them into spaces and let the next word
+            // decide whether to wrap.
+            writer.defer_space(attrs.style);
             (stack.current(Inline(state, attrs)), data)
         }
         (Stacked(stack, Inline(state, attrs)), HardBreak) => {
-            writeln!(writer)?;
+            writer.newline()?;
             write_indent(writer, attrs.indent)?;
             (stack.current(Inline(state, attrs)), data)
         }
@@ -464,23 +695,36 @@ pub fn write_event<'a, W: Write>(
         (Stacked(stack, Inline(ListItem(kind, ItemBlock), attrs)), Text(text)) => {
             // Fresh text after a new block, so indent again.
             write_indent(writer, attrs.indent)?;
-            write_styled(writer, &settings.terminal_capabilities, &attrs.style, text)?;
+            write_wrapped(
+                writer,
+                &attrs.style,
+                attrs.indent,
+                settings.terminal_size.columns,
+                text,
+            )?;
             stack
                 .current(Inline(ListItem(kind, ItemText), attrs))
                 .and_data(data)
         }
         (Stacked(stack, Inline(state, attrs)), Text(text)) => {
-            write_styled(writer, &settings.terminal_capabilities, &attrs.style, text)?;
+            write_wrapped(
+                writer,
+                &attrs.style,
+                attrs.indent,
+                settings.terminal_size.columns,
+                text,
+            )?;
             (stack.current(Inline(state, attrs)), data)
         }
         // Inline HTML
         (Stacked(stack, Inline(ListItem(kind, ItemBlock), attrs)), Html(html)) => {
             // Fresh text after a new block, so indent again.
             write_indent(writer, attrs.indent)?;
-            write_styled(
+            write_unbroken(
                 writer,
-                &settings.terminal_capabilities,
-                &attrs.style.fg(Colour::Green),
+                &apply_style(attrs.style, &settings.stylesheet.html),
+                attrs.indent,
+                settings.terminal_size.columns,
                 html,
             )?;
             stack
@@ -488,21 +732,22 @@ pub fn write_event<'a, W: Write>(
                 .and_data(data)
         }
         (Stacked(stack, Inline(state, attrs)), Html(html)) => {
-            write_styled(
+            write_unbroken(
                 writer,
-                &settings.terminal_capabilities,
-                &attrs.style.fg(Colour::Green),
+                &apply_style(attrs.style, &settings.stylesheet.html),
+                attrs.indent,
+                settings.terminal_size.columns,
                 html,
             )?;
             (stack.current(Inline(state, attrs)), data)
         }
         // Ending inline text
         (Stacked(stack, Inline(_, _)), End(Paragraph)) => {
-            writeln!(writer)?;
+            writer.newline()?;
             (stack.pop(), data)
         }
         (Stacked(stack, Inline(_, _)), End(Heading(_))) => {
-            writeln!(writer)?;
+            writer.newline()?;
             (stack.pop(), data)
         }
 
@@ -511,24 +756,26 @@ pub fn write_event<'a, W: Write>(
         // Links need a bit more work than standard inline markup because we
         // need to keep track of link references if we can't write inline links.
         (Stacked(stack, Inline(state, attrs)), Start(Link(link_type, target, _))) => {
-            let link_state = settings
-                .terminal_capabilities
-                .links
-                .and_then(|link_capability| match link_capability {
-                    LinkCapability::Osc8(ref osc8) => {
-                        let url = if let LinkType::Email = link_type {
-                            // Turn email autolinks (i.e. <foo@example.com>) into mailto inline links
-                            Url::parse(&format!("mailto:{}", target)).ok()
-                        } else {
-                            environment.resolve_reference(&target)
-                        };
-                        url.and_then(|url| {
-                            osc8.set_link_url(writer, url, &environment.hostname).ok()
-                        })
-                        .and(Some(InlineLink(link_capability)))
-                    }
-                })
-                .unwrap_or(InlineText);
+            let url = if settings.terminal_capabilities.links.is_none()
+                // Refer to the anchor of documents in the same output, and to
+                // headings in the same document, instead
+                || environment.anchor(&target).is_some()
+                || data.outline.find_fragment(&target).is_some()
+            {
+                None
+            } else if let LinkType::Email = link_type {
+                // Turn email autolinks (i.e. <foo@example.com>) into mailto inline links
+                Url::parse(&format!("mailto:{}", target)).ok()
+            } else {
+                environment.resolve_reference(&target)
+            };
+            let link_state = match url {
+                Some(url) => {
+                    writer.start_link(&url)?;
+                    InlineLink
+                }
+                None => InlineText,
+            };
 
             let InlineAttrs { style, indent } = attrs;
             stack
@@ -537,17 +784,13 @@ pub fn write_event<'a, W: Write>(
                     link_state,
                     InlineAttrs {
                         indent,
-                        style: style.fg(Colour::Blue),
+                        style: apply_style(style, &settings.stylesheet.link),
                     },
                 ))
                 .and_data(data)
         }
-        (Stacked(stack, Inline(InlineLink(capability), _)), End(Link(_, _, _))) => {
-            match capability {
-                LinkCapability::Osc8(ref osc8) => {
-                    osc8.clear_link(writer)?;
-                }
-            }
+        (Stacked(stack, Inline(InlineLink, _)), End(Link(_, _, _))) => {
+            writer.end_link()?;
             (stack.pop(), data)
         }
         // When closing email or autolinks in inline text just return because link, being identical
@@ -559,12 +802,10 @@ pub fn write_event<'a, W: Write>(
             (stack.pop(), data)
         }
         (Stacked(stack, Inline(InlineText, attrs)), End(Link(_, target, title))) => {
-            let (data, index) = data.add_link(target, title, Colour::Blue);
-            write_styled(
-                writer,
-                &settings.terminal_capabilities,
-                &attrs.style.fg(Colour::Blue),
-                format!("[{}]", index),
+            let (data, reference) = link_reference(environment, data, target, title, settings);
+            writer.write_styled(
+                &apply_style(attrs.style, &settings.stylesheet.link),
+                format!("[{}]", reference),
             )?;
             (stack.pop(), data)
         }
@@ -572,63 +813,49 @@ pub fn write_event<'a, W: Write>(
         // Images
         (Stacked(stack, Inline(state, attrs)), Start(Image(_, link, _))) => {
             let InlineAttrs { style, indent } = attrs;
-            use ImageCapability::*;
-            let resolved_link = environment.resolve_reference(&link);
-            let image_state = match (settings.terminal_capabilities.image, resolved_link) {
-                (Some(Terminology(terminology)), Some(ref url)) => {
-                    terminology.write_inline_image(writer, settings.terminal_size, url)?;
-                    Some(RenderedImage)
-                }
-                (Some(ITerm2(iterm2)), Some(ref url)) => iterm2
-                    .read_and_render(url, settings.resource_access)
-                    .and_then(|contents| {
-                        // Use the last segment as file name for iterm2.
-                        let name = url.path_segments().and_then(|s| s.last());
-                        iterm2.write_inline_image(writer, name, &contents)?;
-                        Ok(RenderedImage)
-                    })
-                    .map(|_| RenderedImage)
-                    .ok(),
-                (Some(Kitty(kitty)), Some(ref url)) => settings
-                    .terminal_size
-                    .pixels
-                    .ok_or_else(|| anyhow!("Terminal pixel size not available"))
-                    .and_then(|size| {
-                        let image = kitty.read_and_render(url, settings.resource_access, size)?;
-                        kitty.write_inline_image(writer, image)?;
-                        Ok(RenderedImage)
-                    })
-                    .ok(),
-                (None, Some(url)) => {
-                    if let InlineLink(_) = state {
-                        None
-                    } else {
-                        match settings.terminal_capabilities.links {
-                            Some(capability) => match capability {
-                                LinkCapability::Osc8(osc8) => {
-                                    osc8.set_link_url(writer, url, &environment.hostname)?;
-                                    Some(Inline(
-                                        InlineLink(capability),
-                                        InlineAttrs {
-                                            indent,
-                                            style: style.fg(Colour::Purple),
-                                        },
-                                    ))
-                                }
-                            },
-                            None => None,
+            let image_state = match environment.resolve_reference(&link) {
+                Some(url) => {
+                    let image = InlineImage {
+                        url: &url,
+                        indent,
+                        column: writer.column(),
+                        terminal_size: settings.terminal_size,
+                        loader: ResourceLoader {
+                            policy: &settings.resource_policy,
+                            environment,
+                            cache: settings.cache.as_ref(),
+                            prefetched: &data.prefetched,
+                        },
+                    };
+                    match image_result(settings, writer.write_image(&image))? {
+                        Some(true) => Some(RenderedImage),
+                        // If we cannot show images link to the image instead,
+                        // unless we're inside a link already.
+                        Some(false)
+                            if state != InlineLink
+                                && settings.terminal_capabilities.links.is_some() =>
+                        {
+                            writer.start_link(&url)?;
+                            Some(Inline(
+                                InlineLink,
+                                InlineAttrs {
+                                    indent,
+                                    style: apply_style(style, &settings.stylesheet.image),
+                                },
+                            ))
                         }
+                        Some(false) | None => None,
                     }
                 }
-                (_, None) => None,
+                None => None,
             }
             .unwrap_or_else(|| {
-                // Inside an inline link keep the blue foreground colour; we cannot nest links so we
-                // should clarify that clicking the link follows the link target and not the image.
-                let style = if let InlineLink(_) = state {
+                // Inside an inline link keep the link style; we cannot nest links so we should
+                // clarify that clicking the link follows the link target and not the image.
+                let style = if let InlineLink = state {
                     style
                 } else {
-                    style.fg(Colour::Purple)
+                    apply_style(style, &settings.stylesheet.image)
                 };
                 Inline(InlineText, InlineAttrs { style, indent })
             });
@@ -640,21 +867,15 @@ pub fn write_event<'a, W: Write>(
         (Stacked(stack, RenderedImage), Text(_)) => (Stacked(stack, RenderedImage), data),
         (Stacked(stack, RenderedImage), End(Image(_, _, _))) => (stack.pop(), data),
         (Stacked(stack, Inline(state, attrs)), End(Image(_, target, title))) => {
-            if let InlineLink(capability) = state {
-                match capability {
-                    LinkCapability::Osc8(ref osc8) => {
-                        osc8.clear_link(writer)?;
-                    }
-                }
+            if let InlineLink = state {
+                writer.end_link()?;
                 (stack.pop(), data)
             } else {
-                let (data, index) = data.add_link(target, title, Colour::Purple);
-                write_styled(
-                    writer,
-                    &settings.terminal_capabilities,
-                    // Regardless of text style always colour the reference to make clear it points to
+                let (data, index) = data.add_link(target, title, settings.stylesheet.image);
+                writer.write_styled(
+                    // Regardless of text style always style the reference to make clear it points to
                     // an image
-                    &attrs.style.fg(Colour::Purple),
+                    &apply_style(attrs.style, &settings.stylesheet.image),
                     format!("[{}]", index),
                 )?;
                 (stack.pop(), data)
@@ -666,37 +887,192 @@ pub fn write_event<'a, W: Write>(
         (Stacked(stack, _), End(List(_))) => (stack.pop(), data),
 
         // Impossible events
-        (s, e) => panic!(
-            "Event {:?} impossible in state {:?}
+        (state, event) => {
+            let error = RenderError::ImpossibleEvent {
+                event: format!("{:?}", event),
+                state: format!("{:?}", state),
+            };
+            recover(writer, settings, state, data, event, error)?
+        }
+    }
+}
+
+/// Recover from a render `error` for `event` in `state`.
+///
+/// Fail with `error` or write the text of `event` and stay in `state`,
+/// according to the error recovery of `settings`.
+#[throws]
+fn recover<'a, R: Renderer>(
+    writer: &mut ColumnWriter<R>,
+    settings: &Settings,
+    state: State,
+    data: StateData<'a>,
+    event: Event<'a>,
+    error: RenderError,
+) -> (State, StateData<'a>) {
+    match settings.error_recovery {
+        ErrorRecovery::Fail => throw!(error),
+        ErrorRecovery::Degrade => {
+            settings.warn(Error::Render(error));
+            match event {
+                Text(text) | Code(text) | Html(text) => writer.write_plain(text)?,
+                FootnoteReference(label) => writer.write_plain(format!("[^{}]", label))?,
+                _ => {}
+            }
+            (state, data)
+        }
+    }
+}
 
-Please do report an issue at <https://github.com/lunaryorn/mdcat/issues/new> including
+/// Handle the `result` of showing an image.
+///
+/// Fail if we could not write output, and report all other errors to the
+/// diagnostics of `settings`, to fall back to the image link.
+#[throws]
+fn image_result(settings: &Settings, result: Result<bool, Error>) -> Option<bool> {
+    match result {
+        Ok(shown) => Some(shown),
+        Err(Error::Io(error)) => throw!(error),
+        Err(error) => {
+            settings.warn(error);
+            None
+        }
+    }
+}
 
-* a copy of this message, and
-* the markdown document which caused this error.",
-            e, s
-        ),
+/// The reference to write for a link to `target`.
+///
+/// Refer to the anchor of `target` if it is a document in the same output,
+/// otherwise add a pending link definition to `data` and refer to its index.
+fn link_reference<'a>(
+    environment: &Environment,
+    data: StateData<'a>,
+    target: CowStr<'a>,
+    title: CowStr<'a>,
+    settings: &Settings,
+) -> (StateData<'a>, String) {
+    match environment.anchor(&target) {
+        Some(anchor) => (data, anchor.to_string()),
+        None => {
+            let (data, index) = data.add_link(target, title, settings.stylesheet.link);
+            (data, index.to_string())
+        }
     }
 }
 
+/// The indent of the contents of a list item with the given `bullet`.
+fn bullet_width(bullet: &GlyphStyle) -> u16 {
+    bullet.glyph.width() as u16 + 1
+}
+
+/// Write all pending footnote definitions.
+///
+/// We render each definition like a list item, with the footnote marker as
+/// bullet.
 #[throws]
-pub fn finish<'a, W: Write>(
-    writer: &mut W,
+fn write_footnote_definitions<'a, R: Renderer>(
+    writer: &mut ColumnWriter<R>,
     settings: &Settings,
     environment: &Environment,
-    state: State,
     data: StateData<'a>,
-) -> () {
-    match state {
-        State::TopLevel(_) => {
-            write_link_refs(
-                writer,
+) -> StateData<'a> {
+    let (data, definitions) = data.take_footnote_definitions();
+    definitions.into_iter().try_fold(data, |data, definition| {
+        writer.newline()?;
+        writer.set_mark()?;
+        let marker = format!("{} ", superscript(definition.index));
+        writer.write_styled(&settings.stylesheet.footnote, &marker)?;
+        let state =
+            State::stack_onto(TopLevelAttrs::margin_before()).current(StackedState::Inline(
+                InlineState::ListItem(ListItemKind::Unordered, ListItemState::StartItem),
+                InlineAttrs {
+                    style: Style::new(),
+                    indent: marker.chars().count() as u16,
+                },
+            ));
+        definition
+            .events
+            .into_iter()
+            .try_fold((state, data), |(state, data), event| {
+                write_event(writer, settings, environment, state, data, event)
+            })
+            .map(|(_, data)| data)
+    })?
+}
+
+/// Fetch all remote images in `events` ahead of rendering.
+///
+/// Only fetch images if the terminal shows images by reading them, and only
+/// fetch remote images which `settings` permit, because local images are fast
+/// to read anyway.
+pub fn prefetch_images(
+    settings: &Settings,
+    environment: &Environment,
+    events: &[Event<'_>],
+) -> Prefetched {
+    use ImageCapability::*;
+    match settings.terminal_capabilities.image {
+        Some(ITerm2(_)) | Some(Kitty(_)) | Some(Sixel(_)) | Some(HalfBlocks(_)) => {
+            let mut urls: Vec<Url> = Vec::new();
+            for event in events {
+                if let Start(Image(_, link, _)) = event {
+                    if let Some(url) = environment.resolve_reference(link).filter(|url| {
+                        matches!(url.scheme(), "http" | "https")
+                            && settings.resource_policy.permits(url, &environment.base_url)
+                    }) {
+                        if !urls.contains(&url) {
+                            urls.push(url);
+                        }
+                    }
+                }
+            }
+            Prefetched::fetch(
+                urls,
                 environment,
-                &settings.terminal_capabilities,
-                data.pending_link_definitions,
-            )?;
+                &settings.resource_policy,
+                settings.cache.as_ref(),
+            )
         }
-        _ => {
-            panic!("Must finish in state TopLevel but got: {:?}", state);
+        _ => Prefetched::default(),
+    }
+}
+
+#[throws]
+pub fn finish<'a, R: Renderer>(
+    writer: &mut ColumnWriter<R>,
+    settings: &Settings,
+    environment: &Environment,
+    state: State,
+    data: StateData<'a>,
+) -> () {
+    if let State::Stacked(_, _) = state {
+        let error = RenderError::UnfinishedState {
+            state: format!("{:?}", state),
+        };
+        match settings.error_recovery {
+            ErrorRecovery::Fail => throw!(error),
+            ErrorRecovery::Degrade => {
+                settings.warn(Error::Render(error));
+                writer.newline()?
+            }
         }
     }
+    let (data, links) = data.take_links();
+    write_link_refs(
+        writer,
+        environment,
+        &settings.terminal_capabilities,
+        &data.outline,
+        links,
+    )?;
+    // Footnotes may contain links as well, so write link references
+    // again after footnotes.
+    let data = write_footnote_definitions(writer, settings, environment, data)?;
+    write_link_refs(
+        writer,
+        environment,
+        &settings.terminal_capabilities,
+        &data.outline,
+        data.pending_link_definitions,
+    )?;
 }
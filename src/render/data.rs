@@ -4,8 +4,11 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use ansi_term::Colour;
-use pulldown_cmark::CowStr;
+use ansi_term::Style;
+use pulldown_cmark::{CowStr, Event, Tag};
+
+use crate::resources::Prefetched;
+use crate::Outline;
 
 /// The definition of a reference link, i.e. a numeric index for a link.
 #[derive(Debug, PartialEq)]
@@ -16,8 +19,17 @@ pub struct LinkReferenceDefinition<'a> {
     pub(crate) target: CowStr<'a>,
     /// The link title as it appeared in Markdown.
     pub(crate) title: CowStr<'a>,
-    /// The colour to use for the link.
-    pub(crate) colour: Colour,
+    /// The style to use for the link.
+    pub(crate) style: Style,
+}
+
+/// A footnote definition, collected to write it at the end of the document.
+#[derive(Debug, PartialEq)]
+pub struct FootnoteDefinition<'a> {
+    /// The number of this footnote.
+    pub(crate) index: usize,
+    /// The events making up the contents of this footnote.
+    pub(crate) events: Vec<Event<'a>>,
 }
 
 /// Data associated with rendering state.
@@ -33,19 +45,45 @@ pub struct StateData<'a> {
     pub(super) pending_link_definitions: Vec<LinkReferenceDefinition<'a>>,
     /// The reference number for the next link.
     pub(super) next_link: u16,
+    /// The labels of all footnotes seen so far, in order of their number.
+    pub(super) footnote_labels: Vec<CowStr<'a>>,
+    /// Footnote definitions not yet written.
+    pub(super) pending_footnote_definitions: Vec<FootnoteDefinition<'a>>,
+    /// The footnote definition we're currently collecting events for, if any.
+    pub(super) current_footnote_definition: Option<FootnoteDefinition<'a>>,
+    /// Resources fetched ahead of rendering.
+    pub(super) prefetched: Prefetched,
+    /// The outline of the document, to resolve links to headings.
+    pub(super) outline: Outline,
+    /// The number of start events skipped because they nest too deeply, and
+    /// whose end events we must skip as well.
+    pub(super) skipped_starts: usize,
 }
 
 impl<'a> StateData<'a> {
+    /// Create state data with resources fetched ahead of rendering.
+    pub(crate) fn with_prefetched(prefetched: Prefetched) -> Self {
+        StateData {
+            prefetched,
+            ..StateData::default()
+        }
+    }
+
+    /// Add the `outline` of the document to the state data.
+    pub(crate) fn with_outline(self, outline: Outline) -> Self {
+        StateData { outline, ..self }
+    }
+
     /// Add a pending link to the state data.
     ///
     /// `target` is the link target, and `title` the link title to show after the URL.
-    /// `colour` is the colour to use for foreground text to differentiate between
+    /// `style` is the style to use for the reference to differentiate between
     /// different types of links.
     pub(crate) fn add_link(
         mut self,
         target: CowStr<'a>,
         title: CowStr<'a>,
-        colour: Colour,
+        style: Style,
     ) -> (Self, u16) {
         let index = self.next_link;
         self.next_link += 1;
@@ -53,11 +91,92 @@ impl<'a> StateData<'a> {
             index,
             target,
             title,
-            colour,
+            style,
         });
         (self, index)
     }
 
+    /// Get the number of the footnote with the given `label`.
+    ///
+    /// Footnotes get numbers in the order mdcat first sees them, either
+    /// through a reference or a definition.
+    pub(crate) fn footnote_index(mut self, label: CowStr<'a>) -> (Self, usize) {
+        match self.footnote_labels.iter().position(|l| *l == label) {
+            Some(position) => (self, position + 1),
+            None => {
+                self.footnote_labels.push(label);
+                let index = self.footnote_labels.len();
+                (self, index)
+            }
+        }
+    }
+
+    /// Start collecting events for a footnote definition with the given `label`.
+    pub(crate) fn start_footnote_definition(self, label: CowStr<'a>) -> Self {
+        let (data, index) = self.footnote_index(label);
+        StateData {
+            current_footnote_definition: Some(FootnoteDefinition {
+                index,
+                events: Vec::new(),
+            }),
+            ..data
+        }
+    }
+
+    /// Whether we're collecting events for a footnote definition.
+    pub(crate) fn is_collecting_footnote_definition(&self) -> bool {
+        self.current_footnote_definition.is_some()
+    }
+
+    /// Collect an `event` for the current footnote definition.
+    ///
+    /// At the end of the definition add it to the pending definitions.
+    pub(crate) fn collect_footnote_event(mut self, event: Event<'a>) -> Self {
+        if let Some(mut definition) = self.current_footnote_definition.take() {
+            if let Event::End(Tag::FootnoteDefinition(_)) = event {
+                self.pending_footnote_definitions.push(definition);
+            } else {
+                definition.events.push(event);
+                self.current_footnote_definition = Some(definition);
+            }
+        }
+        self
+    }
+
+    /// Take all pending footnote definitions, in order of their number.
+    pub(crate) fn take_footnote_definitions(self) -> (Self, Vec<FootnoteDefinition<'a>>) {
+        let mut definitions = self.pending_footnote_definitions;
+        definitions.sort_by_key(|definition| definition.index);
+        (
+            StateData {
+                pending_footnote_definitions: Vec::new(),
+                ..self
+            },
+            definitions,
+        )
+    }
+
+    /// Skip a start event, and remember to skip its end event as well.
+    pub(crate) fn skip_start(self) -> Self {
+        StateData {
+            skipped_starts: self.skipped_starts + 1,
+            ..self
+        }
+    }
+
+    /// Whether we skip the next end event, because we skipped its start.
+    pub(crate) fn is_skipping(&self) -> bool {
+        0 < self.skipped_starts
+    }
+
+    /// Skip the end event of a skipped start event.
+    pub(crate) fn skip_end(self) -> Self {
+        StateData {
+            skipped_starts: self.skipped_starts.saturating_sub(1),
+            ..self
+        }
+    }
+
     pub(crate) fn take_links(self) -> (Self, Vec<LinkReferenceDefinition<'a>>) {
         let links = self.pending_link_definitions;
         (
@@ -75,6 +194,12 @@ impl<'a> Default for StateData<'a> {
         StateData {
             pending_link_definitions: Vec::new(),
             next_link: 1,
+            footnote_labels: Vec::new(),
+            pending_footnote_definitions: Vec::new(),
+            current_footnote_definition: None,
+            prefetched: Prefetched::default(),
+            outline: Outline::default(),
+            skipped_starts: 0,
         }
     }
 }
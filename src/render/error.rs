@@ -0,0 +1,65 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Errors of the rendering algorithm.
+
+use std::fmt::{Display, Formatter};
+
+/// An error in the rendering algorithm.
+///
+/// mdcat fails with this error if it sees an event it cannot render in the
+/// current state; this indicates a bug in mdcat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenderError {
+    /// An event in a state which does not permit this event.
+    ImpossibleEvent {
+        /// The event, formatted for debugging.
+        event: String,
+        /// The state, formatted for debugging.
+        state: String,
+    },
+    /// A document nested deeper than mdcat supports.
+    NestingTooDeep {
+        /// The maximum level of nesting.
+        limit: usize,
+    },
+    /// The document ended in a state other than the top level.
+    UnfinishedState {
+        /// The state at the end of the document, formatted for debugging.
+        state: String,
+    },
+}
+
+impl Display for RenderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderError::ImpossibleEvent { event, state } => {
+                write!(f, "Event {} impossible in state {}", event, state)
+            }
+            RenderError::NestingTooDeep { limit } => {
+                write!(f, "More than {} levels of nesting reached", limit)
+            }
+            RenderError::UnfinishedState { state } => {
+                write!(f, "Must finish in state TopLevel but got: {}", state)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+/// What to do if mdcat cannot render an event.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ErrorRecovery {
+    /// Fail with a [`RenderError`].
+    #[default]
+    Fail,
+    /// Write the text of the offending event as plain text, and continue.
+    ///
+    /// The output may be garbled after this, but mdcat renders the whole
+    /// document.
+    Degrade,
+}
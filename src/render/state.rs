@@ -4,8 +4,10 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::{AnsiStyle, LinkCapability};
+use crate::render::table::Table;
+use crate::stylesheet::apply_style;
 use ansi_term::Style;
+use pulldown_cmark::Alignment;
 use std::borrow::Borrow;
 use syntect::highlighting::HighlightState;
 use syntect::parsing::ParseState;
@@ -77,9 +79,7 @@ pub enum InlineState {
     ///
     /// This state suppresses link references being written when reading a link
     /// end event.
-    ///
-    /// Contains the link capability used to render the link.
-    InlineLink(LinkCapability),
+    InlineLink,
     /// A list item.
     ///
     /// This is a hybrid between inline and block state because it can contain nested blocks as well
@@ -128,10 +128,10 @@ impl StyledBlockAttrs {
         }
     }
 
-    pub(super) fn block_quote(self) -> Self {
+    pub(super) fn block_quote(self, style: &Style) -> Self {
         StyledBlockAttrs {
             indent: self.indent + 4,
-            style: self.style.italic(),
+            style: apply_style(self.style, style),
             ..self
         }
     }
@@ -163,7 +163,6 @@ where
 /// Attributes for highlighted blocks, that is, code blocks.
 #[derive(Debug, PartialEq)]
 pub struct HighlightBlockAttrs {
-    pub(super) ansi: AnsiStyle,
     pub(super) parse_state: ParseState,
     pub(super) highlight_state: HighlightState,
     /// The indentation to apply to this code block.
@@ -181,6 +180,45 @@ pub struct LiteralBlockAttrs {
     pub(super) style: Style,
 }
 
+/// Attributes for tables.
+#[derive(Debug, PartialEq)]
+pub struct TableBlockAttrs {
+    /// The indent of this table.
+    pub(super) indent: u16,
+    /// The style of the current text in the table.
+    ///
+    /// The outer style of the table, plus any inline style of the current cell.
+    pub(super) style: Style,
+    /// The styles to restore after inline markup in table cells.
+    pub(super) styles: Vec<Style>,
+    /// The contents of the table.
+    pub(super) table: Table,
+}
+
+impl TableBlockAttrs {
+    pub(super) fn new(indent: u16, style: Style, alignments: Vec<Alignment>) -> Self {
+        TableBlockAttrs {
+            indent,
+            style,
+            styles: Vec::new(),
+            table: Table::new(alignments),
+        }
+    }
+
+    /// Start a nested inline style in a table cell.
+    pub(super) fn push_style(&mut self, style: Style) {
+        let outer = std::mem::replace(&mut self.style, style);
+        self.styles.push(outer);
+    }
+
+    /// Restore the style from before the current inline style.
+    pub(super) fn pop_style(&mut self) {
+        if let Some(style) = self.styles.pop() {
+            self.style = style;
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum StackedState {
     /// Styled block.
@@ -191,6 +229,10 @@ pub enum StackedState {
     HighlightBlock(HighlightBlockAttrs),
     /// A literal block without highlighting.
     LiteralBlock(LiteralBlockAttrs),
+    /// A table.
+    ///
+    /// We buffer all cells of a table and only write the table at its end.
+    TableBlock(TableBlockAttrs),
     /// A rendered inline image.
     ///
     /// We move to this state when we can render an image directly to the terminal, in order to
@@ -218,6 +260,12 @@ impl From<LiteralBlockAttrs> for StackedState {
     }
 }
 
+impl From<TableBlockAttrs> for StackedState {
+    fn from(attrs: TableBlockAttrs) -> Self {
+        StackedState::TableBlock(attrs)
+    }
+}
+
 /// State attributes for top level.
 #[derive(Debug, PartialEq)]
 pub struct TopLevelAttrs {
@@ -246,7 +294,10 @@ impl Default for TopLevelAttrs {
     }
 }
 
-const MAX_STATES: usize = 100;
+/// The maximum number of stacked states.
+///
+/// `write_event` refuses to nest deeper than this.
+pub(crate) const MAX_STATES: usize = 100;
 
 #[derive(Debug, PartialEq)]
 pub struct StateStack {
@@ -266,18 +317,7 @@ impl StateStack {
     }
 
     /// Push a new stacked state.
-    ///
-    /// Panics if the amount of stacked states is exceeded.
     pub(crate) fn push(mut self, state: StackedState) -> StateStack {
-        if MAX_STATES <= self.states.len() {
-            panic!(
-                "More than {} levels of nesting reached.
-
-Report an issue to https://github.com/lunaryorn/mdcat/issues
-including the document causing this panic.",
-                MAX_STATES
-            )
-        }
         self.states.push(state);
         self
     }
@@ -287,6 +327,16 @@ including the document causing this panic.",
         State::Stacked(self, state)
     }
 
+    /// The nesting level of lists on this stack.
+    ///
+    /// The outermost list has level 0.
+    pub(crate) fn list_level(&self) -> usize {
+        self.states
+            .iter()
+            .filter(|state| matches!(state, StackedState::Inline(InlineState::ListItem(_, _), _)))
+            .count()
+    }
+
     /// Pop a stacked state.
     ///
     /// Returns a stacked state with the last state on the stack and the rest of the stack if the
@@ -319,6 +369,14 @@ impl State {
     pub(super) fn and_data<T>(self, data: T) -> (Self, T) {
         (self, data)
     }
+
+    /// The number of stacked states in this state.
+    pub(super) fn depth(&self) -> usize {
+        match self {
+            State::TopLevel(_) => 0,
+            State::Stacked(stack, _) => stack.states.len() + 1,
+        }
+    }
 }
 
 impl Default for State {
@@ -0,0 +1,397 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Table layout.
+//!
+//! Tables need to know the contents of all cells before we can write the first
+//! line, so we buffer all cells of a table as styled fragments, and then
+//! compute column widths and wrap cell contents when the table ends.
+
+use ansi_term::Style;
+use pulldown_cmark::Alignment;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// A piece of styled text in a table cell.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Fragment {
+    /// The style of this fragment.
+    pub(super) style: Style,
+    /// The text of this fragment.
+    pub(super) text: String,
+}
+
+impl Fragment {
+    fn width(&self) -> usize {
+        self.text.width()
+    }
+}
+
+/// A single line of styled text.
+pub type Line = Vec<Fragment>;
+
+/// The contents of a table cell.
+pub type Cell = Vec<Fragment>;
+
+/// A table buffered for layout.
+#[derive(Debug, PartialEq)]
+pub struct Table {
+    /// The alignment of each column.
+    alignments: Vec<Alignment>,
+    /// The cells of the table head.
+    head: Vec<Cell>,
+    /// The rows of the table body.
+    rows: Vec<Vec<Cell>>,
+    /// The row we're currently reading cells for.
+    current_row: Vec<Cell>,
+    /// The cell we're currently reading fragments for.
+    current_cell: Cell,
+}
+
+impl Table {
+    /// Create a new empty table with the given column `alignments`.
+    pub(super) fn new(alignments: Vec<Alignment>) -> Self {
+        Table {
+            alignments,
+            head: Vec::new(),
+            rows: Vec::new(),
+            current_row: Vec::new(),
+            current_cell: Vec::new(),
+        }
+    }
+
+    /// Add `text` with the given `style` to the current cell.
+    pub(super) fn push_text<S: Into<String>>(&mut self, style: Style, text: S) {
+        let text = text.into();
+        if text.is_empty() {
+            return;
+        }
+        match self.current_cell.last_mut() {
+            Some(fragment) if fragment.style == style => fragment.text.push_str(&text),
+            _ => self.current_cell.push(Fragment { style, text }),
+        }
+    }
+
+    /// Finish the current cell and add it to the current row.
+    pub(super) fn end_cell(&mut self) {
+        let cell = std::mem::take(&mut self.current_cell);
+        self.current_row.push(cell);
+    }
+
+    /// Finish the current row as the table head.
+    pub(super) fn end_head(&mut self) {
+        self.head = std::mem::take(&mut self.current_row);
+    }
+
+    /// Finish the current row as a row in the table body.
+    pub(super) fn end_row(&mut self) {
+        let row = std::mem::take(&mut self.current_row);
+        self.rows.push(row);
+    }
+
+    /// The number of columns of this table.
+    pub(super) fn columns(&self) -> usize {
+        self.rows
+            .iter()
+            .map(Vec::len)
+            .chain(std::iter::once(self.head.len()))
+            .chain(std::iter::once(self.alignments.len()))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The alignment of the given `column`.
+    pub(super) fn alignment(&self, column: usize) -> Alignment {
+        self.alignments
+            .get(column)
+            .copied()
+            .unwrap_or(Alignment::None)
+    }
+
+    /// The cells of the table head.
+    pub(super) fn head(&self) -> &[Cell] {
+        &self.head
+    }
+
+    /// The rows of the table body.
+    pub(super) fn rows(&self) -> &[Vec<Cell>] {
+        &self.rows
+    }
+
+    fn all_rows(&self) -> impl Iterator<Item = &Vec<Cell>> {
+        std::iter::once(&self.head).chain(self.rows.iter())
+    }
+
+    /// Compute the width of every column such that the whole table fits into
+    /// `max_width` columns, including borders.
+    ///
+    /// If the natural width of all cells fits we use it; otherwise we first
+    /// shrink columns down to their longest word, and only then break words.
+    pub(super) fn column_widths(&self, max_width: usize) -> Vec<usize> {
+        let columns = self.columns();
+        if columns == 0 {
+            return Vec::new();
+        }
+        let mut natural = vec![0; columns];
+        let mut minimal = vec![0; columns];
+        for row in self.all_rows() {
+            for (column, cell) in row.iter().enumerate() {
+                let words = words(cell);
+                let cell_width = words.iter().map(|word| word.width).sum::<usize>()
+                    + words.len().saturating_sub(1);
+                let longest_word = words.iter().map(|word| word.width).max().unwrap_or(0);
+                natural[column] = natural[column].max(cell_width);
+                minimal[column] = minimal[column].max(longest_word);
+            }
+        }
+        // Make room for at least a single character in every column.
+        natural
+            .iter_mut()
+            .for_each(|width| *width = (*width).max(1));
+        minimal
+            .iter_mut()
+            .for_each(|width| *width = (*width).max(1));
+
+        // Every column takes a border and a space of padding at each side, plus
+        // a final border at the end of the line.
+        let available = max_width.saturating_sub(3 * columns + 1).max(columns);
+        if natural.iter().sum::<usize>() <= available {
+            natural
+        } else if minimal.iter().sum::<usize>() <= available {
+            let extra = available - minimal.iter().sum::<usize>();
+            distribute(&minimal, &natural, extra)
+        } else {
+            distribute(&vec![1; columns], &natural, available - columns)
+        }
+    }
+}
+
+/// Distribute `extra` space over columns of the given `minimal` widths
+/// according to how much the column lacks of its `natural` width.
+fn distribute(minimal: &[usize], natural: &[usize], extra: usize) -> Vec<usize> {
+    let wanted = minimal
+        .iter()
+        .zip(natural)
+        .map(|(min, nat)| nat.saturating_sub(*min))
+        .collect::<Vec<_>>();
+    let total_wanted = wanted.iter().sum::<usize>().max(1);
+    let mut widths = minimal
+        .iter()
+        .zip(&wanted)
+        .map(|(min, want)| min + want * extra / total_wanted)
+        .collect::<Vec<_>>();
+    // Hand out whatever remains after rounding to the columns which lack most.
+    let mut remaining = extra - (widths.iter().sum::<usize>() - minimal.iter().sum::<usize>());
+    while remaining > 0 {
+        let column = (0..widths.len())
+            .max_by_key(|&i| natural[i].saturating_sub(widths[i]))
+            .unwrap();
+        widths[column] += 1;
+        remaining -= 1;
+    }
+    widths
+}
+
+/// A word in a table cell, that is a sequence of fragments without whitespace.
+#[derive(Debug, PartialEq)]
+struct Word {
+    /// The fragments of this word.
+    fragments: Vec<Fragment>,
+    /// The style of whitespace before this word.
+    space: Option<Style>,
+    /// The display width of this word.
+    width: usize,
+}
+
+impl Word {
+    fn push(&mut self, style: Style, c: char) {
+        match self.fragments.last_mut() {
+            Some(fragment) if fragment.style == style => fragment.text.push(c),
+            _ => self.fragments.push(Fragment {
+                style,
+                text: c.to_string(),
+            }),
+        }
+        self.width += c.width().unwrap_or(0);
+    }
+}
+
+/// Split the contents of a `cell` into words, collapsing whitespace.
+fn words(cell: &[Fragment]) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut current: Option<Word> = None;
+    let mut space = None;
+    for fragment in cell {
+        for c in fragment.text.chars() {
+            if c.is_whitespace() {
+                if let Some(word) = current.take() {
+                    words.push(word);
+                }
+                space = Some(fragment.style);
+            } else {
+                current
+                    .get_or_insert_with(|| Word {
+                        fragments: Vec::new(),
+                        space: space.take(),
+                        width: 0,
+                    })
+                    .push(fragment.style, c);
+            }
+        }
+    }
+    words.extend(current);
+    words
+}
+
+/// Split a `word` which is longer than `width` into pieces of at most `width`.
+fn break_word(word: Word, width: usize) -> Vec<Word> {
+    let mut pieces = Vec::new();
+    let mut current = Word {
+        fragments: Vec::new(),
+        space: word.space,
+        width: 0,
+    };
+    for fragment in word.fragments {
+        for c in fragment.text.chars() {
+            let char_width = c.width().unwrap_or(0);
+            if 0 < current.width && width < current.width + char_width {
+                pieces.push(std::mem::replace(
+                    &mut current,
+                    Word {
+                        fragments: Vec::new(),
+                        space: None,
+                        width: 0,
+                    },
+                ));
+            }
+            current.push(fragment.style, c);
+        }
+    }
+    pieces.push(current);
+    pieces
+}
+
+/// Wrap the contents of `cell` into lines of at most `width` columns.
+///
+/// Always return at least one, possibly empty, line.
+pub(super) fn wrap_cell(cell: &[Fragment], width: usize) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut line: Line = Vec::new();
+    let mut line_width = 0;
+    for word in words(cell) {
+        let pieces = if width < word.width {
+            break_word(word, width)
+        } else {
+            vec![word]
+        };
+        for piece in pieces {
+            if 0 < line_width && width < line_width + 1 + piece.width {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            if 0 < line_width {
+                line.push(Fragment {
+                    style: piece.space.unwrap_or_default(),
+                    text: " ".to_string(),
+                });
+                line_width += 1;
+            }
+            line.extend(piece.fragments);
+            line_width += piece.width;
+        }
+    }
+    lines.push(line);
+    lines
+}
+
+/// The display width of a `line`.
+pub(super) fn line_width(line: &[Fragment]) -> usize {
+    line.iter().map(Fragment::width).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn plain(text: &str) -> Cell {
+        vec![Fragment {
+            style: Style::new(),
+            text: text.to_string(),
+        }]
+    }
+
+    fn texts(lines: Vec<Line>) -> Vec<String> {
+        lines
+            .into_iter()
+            .map(|line| line.into_iter().map(|f| f.text).collect())
+            .collect()
+    }
+
+    #[test]
+    fn wrap_cell_fits() {
+        assert_eq!(texts(wrap_cell(&plain("foo bar"), 10)), vec!["foo bar"]);
+    }
+
+    #[test]
+    fn wrap_cell_wraps_at_words() {
+        assert_eq!(
+            texts(wrap_cell(&plain("foo bar  baz"), 7)),
+            vec!["foo bar", "baz"]
+        );
+    }
+
+    #[test]
+    fn wrap_cell_breaks_long_words() {
+        assert_eq!(
+            texts(wrap_cell(&plain("abcdefgh ij"), 3)),
+            vec!["abc", "def", "gh", "ij"]
+        );
+    }
+
+    #[test]
+    fn wrap_cell_counts_wide_characters() {
+        assert_eq!(texts(wrap_cell(&plain("日本語"), 4)), vec!["日本", "語"]);
+    }
+
+    #[test]
+    fn wrap_cell_keeps_words_across_fragments() {
+        let cell = vec![
+            Fragment {
+                style: Style::new().bold(),
+                text: "foo".to_string(),
+            },
+            Fragment {
+                style: Style::new(),
+                text: "bar baz".to_string(),
+            },
+        ];
+        assert_eq!(texts(wrap_cell(&cell, 6)), vec!["foobar", "baz"]);
+    }
+
+    #[test]
+    fn column_widths_natural() {
+        let mut table = Table::new(vec![Alignment::None, Alignment::None]);
+        table.push_text(Style::new(), "foo");
+        table.end_cell();
+        table.push_text(Style::new(), "bar baz");
+        table.end_cell();
+        table.end_head();
+        assert_eq!(table.column_widths(80), vec![3, 7]);
+    }
+
+    #[test]
+    fn column_widths_shrink_to_fit() {
+        let mut table = Table::new(vec![Alignment::None, Alignment::None]);
+        table.push_text(Style::new(), "foo");
+        table.end_cell();
+        table.push_text(Style::new(), "lorem ipsum dolor sit amet");
+        table.end_cell();
+        table.end_head();
+        // 3 * 2 + 1 columns for borders and padding
+        let widths = table.column_widths(20);
+        assert_eq!(widths, vec![3, 10]);
+    }
+}
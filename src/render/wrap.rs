@@ -0,0 +1,370 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Word wrapping for inline text.
+
+use std::io::Result;
+
+use ansi_term::Style;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use url::Url;
+
+use crate::renderer::{Block, InlineImage, Renderer};
+use crate::Error;
+
+/// A writer which keeps track of the current column.
+///
+/// Write all output to a renderer, and measure the display width of all text
+/// written, counting wide characters with their actual width.
+///
+/// Additionally this writer can defer a space until the next write: If the
+/// next write starts a new line it drops the space, so that wrapped lines do
+/// not end with whitespace.
+#[derive(Debug)]
+pub struct ColumnWriter<R> {
+    inner: R,
+    column: usize,
+    deferred: Option<Style>,
+}
+
+impl<R: Renderer> ColumnWriter<R> {
+    /// Track the column of output written to `inner`.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            column: 0,
+            deferred: None,
+        }
+    }
+
+    /// The current column, not counting a deferred space.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Whether there's a deferred space.
+    pub fn has_deferred_space(&self) -> bool {
+        self.deferred.is_some()
+    }
+
+    /// Defer a space with the given `style` until the next write.
+    ///
+    /// Replace any previously deferred space.
+    pub fn defer_space(&mut self, style: Style) {
+        self.deferred = Some(style);
+    }
+
+    /// Write the deferred space, if any, unless the next write starts with a
+    /// newline.
+    fn write_deferred(&mut self, next: &str) -> Result<()> {
+        if let Some(style) = self.deferred.take() {
+            if !next.starts_with('\n') {
+                self.inner.write_styled(&style, " ")?;
+                self.column += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn track(&mut self, text: &str) {
+        let mut start = 0;
+        for (index, c) in text.char_indices() {
+            if c == '\n' || c == '\t' {
+                self.column += text[start..index].width();
+                self.column = match c {
+                    '\n' => 0,
+                    // Tabs go to the next multiple of eight
+                    _ => (self.column / 8 + 1) * 8,
+                };
+                start = index + 1;
+            }
+        }
+        self.column += text[start..].width();
+    }
+
+    /// Write `text` with `style`.
+    pub fn write_styled<S: AsRef<str>>(&mut self, style: &Style, text: S) -> Result<()> {
+        let text = text.as_ref();
+        self.write_deferred(text)?;
+        self.inner.write_styled(style, text)?;
+        self.track(text);
+        Ok(())
+    }
+
+    /// Write `text` without style.
+    pub fn write_plain<S: AsRef<str>>(&mut self, text: S) -> Result<()> {
+        self.write_styled(&Style::new(), text)
+    }
+
+    /// End the current line.
+    pub fn newline(&mut self) -> Result<()> {
+        self.write_plain("\n")
+    }
+
+    /// Link subsequent text to `url`.
+    pub fn start_link(&mut self, url: &Url) -> Result<()> {
+        self.write_deferred("")?;
+        self.inner.start_link(url)
+    }
+
+    /// End the current link.
+    pub fn end_link(&mut self) -> Result<()> {
+        self.write_deferred("")?;
+        self.inner.end_link()
+    }
+
+    /// Set a mark at the current position.
+    pub fn set_mark(&mut self) -> Result<()> {
+        self.write_deferred("")?;
+        self.inner.set_mark()
+    }
+
+    /// Start a `block`.
+    pub fn start_block(&mut self, block: Block) -> Result<()> {
+        self.inner.start_block(block)
+    }
+
+    /// End a `block`.
+    pub fn end_block(&mut self, block: Block) -> Result<()> {
+        self.inner.end_block(block)
+    }
+
+    /// Show an inline `image`, and continue at the column after the image.
+    ///
+    /// See [`Renderer::write_image`].
+    pub fn write_image(&mut self, image: &InlineImage<'_>) -> std::result::Result<bool, Error> {
+        self.write_deferred("")?;
+        match self.inner.write_image(image)? {
+            Some(column) => {
+                self.column = column;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Get the underlying renderer.
+    #[cfg(test)]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+/// A word in inline text.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Word<'a> {
+    /// Whether whitespace precedes this word.
+    pub space_before: bool,
+    /// Whether we may break the line before this word.
+    ///
+    /// We may break lines at whitespace and around wide characters, because
+    /// CJK scripts do not separate words with whitespace.
+    pub break_before: bool,
+    /// The text of this word.
+    ///
+    /// Empty for trailing whitespace.
+    pub text: &'a str,
+}
+
+impl<'a> Word<'a> {
+    /// The display width of this word.
+    pub fn width(&self) -> usize {
+        self.text.width()
+    }
+}
+
+fn is_wide(c: char) -> bool {
+    c.width().unwrap_or(0) > 1
+}
+
+/// Whether `c` is CJK punctuation which must not start a line.
+fn is_closing_punctuation(c: char) -> bool {
+    "、。，．：；！？）」』】〕〉》〙〛".contains(c)
+}
+
+fn is_breaking_whitespace(c: char) -> bool {
+    c.is_whitespace() && c != '\u{a0}'
+}
+
+/// Split `text` into words for wrapping.
+pub fn words(text: &str) -> Vec<Word<'_>> {
+    let mut words = Vec::new();
+    let mut space_before = false;
+    let mut current: Option<(usize, bool)> = None;
+    let mut previous_wide = false;
+    for (index, c) in text.char_indices() {
+        if is_breaking_whitespace(c) {
+            if let Some((start, break_before)) = current.take() {
+                words.push(Word {
+                    space_before,
+                    break_before,
+                    text: &text[start..index],
+                });
+            }
+            space_before = true;
+            continue;
+        }
+        let wide = is_wide(c);
+        match current {
+            None => {
+                current = Some((index, space_before || wide));
+            }
+            Some((start, break_before))
+                if (wide || previous_wide) && !is_closing_punctuation(c) =>
+            {
+                words.push(Word {
+                    space_before,
+                    break_before,
+                    text: &text[start..index],
+                });
+                space_before = false;
+                current = Some((index, true));
+            }
+            Some(_) => {}
+        }
+        previous_wide = wide;
+    }
+    match current {
+        Some((start, break_before)) => words.push(Word {
+            space_before,
+            break_before,
+            text: &text[start..],
+        }),
+        None if space_before => words.push(Word {
+            space_before,
+            break_before: true,
+            text: "",
+        }),
+        None => {}
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TerminalCapabilities, TerminalRenderer};
+    use ansi_term::Colour;
+    use pretty_assertions::assert_eq;
+
+    fn word(space_before: bool, break_before: bool, text: &str) -> Word<'_> {
+        Word {
+            space_before,
+            break_before,
+            text,
+        }
+    }
+
+    #[test]
+    fn words_split_at_whitespace() {
+        assert_eq!(
+            words("foo  bar\tbaz "),
+            vec![
+                word(false, false, "foo"),
+                word(true, true, "bar"),
+                word(true, true, "baz"),
+                word(true, true, ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn words_with_leading_whitespace() {
+        assert_eq!(words(" foo"), vec![word(true, true, "foo")]);
+    }
+
+    #[test]
+    fn words_do_not_split_at_non_breaking_space() {
+        assert_eq!(
+            words("foo\u{a0}bar"),
+            vec![word(false, false, "foo\u{a0}bar")]
+        );
+    }
+
+    #[test]
+    fn words_split_around_wide_characters() {
+        assert_eq!(
+            words("ab日本語cd"),
+            vec![
+                word(false, false, "ab"),
+                word(false, true, "日"),
+                word(false, true, "本"),
+                word(false, true, "語"),
+                word(false, true, "cd"),
+            ]
+        );
+    }
+
+    #[test]
+    fn words_do_not_split_before_closing_punctuation() {
+        assert_eq!(
+            words("日本。"),
+            vec![word(false, true, "日"), word(false, true, "本。")]
+        );
+    }
+
+    fn writer(capabilities: &TerminalCapabilities) -> ColumnWriter<TerminalRenderer<'_, Vec<u8>>> {
+        ColumnWriter::new(TerminalRenderer::new(Vec::new(), capabilities, "localhost"))
+    }
+
+    #[test]
+    fn column_ignores_styles() {
+        let capabilities = TerminalCapabilities::ansi();
+        let mut writer = writer(&capabilities);
+        writer
+            .write_styled(&Style::new().bold().fg(Colour::Blue), "foo")
+            .unwrap();
+        writer.write_plain(" ").unwrap();
+        writer
+            .start_link(&Url::parse("http://example.com").unwrap())
+            .unwrap();
+        writer.write_plain("bar").unwrap();
+        writer.end_link().unwrap();
+        assert_eq!(writer.column(), 7);
+    }
+
+    #[test]
+    fn column_counts_wide_characters() {
+        let capabilities = TerminalCapabilities::ansi();
+        let mut writer = writer(&capabilities);
+        writer.write_plain("日本語").unwrap();
+        assert_eq!(writer.column(), 6);
+    }
+
+    #[test]
+    fn column_resets_after_newline() {
+        let capabilities = TerminalCapabilities::ansi();
+        let mut writer = writer(&capabilities);
+        writer.write_plain("foo\nba").unwrap();
+        assert_eq!(writer.column(), 2);
+    }
+
+    #[test]
+    fn column_after_tabs() {
+        let capabilities = TerminalCapabilities::ansi();
+        let mut writer = writer(&capabilities);
+        writer.write_plain("foo\tba").unwrap();
+        assert_eq!(writer.column(), 10);
+    }
+
+    #[test]
+    fn deferred_space_dropped_at_newline() {
+        let capabilities = TerminalCapabilities::ansi();
+        let mut writer = writer(&capabilities);
+        writer.write_plain("foo").unwrap();
+        writer.defer_space(Style::new());
+        assert_eq!(writer.column(), 3);
+        writer.newline().unwrap();
+        writer.write_plain("bar").unwrap();
+        writer.defer_space(Style::new());
+        writer.write_plain("baz").unwrap();
+        assert_eq!(writer.column(), 7);
+        assert_eq!(
+            String::from_utf8(writer.into_inner().into_inner()).unwrap(),
+            "foo\nbar baz"
+        );
+    }
+}
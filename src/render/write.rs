@@ -4,142 +4,231 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::io::{Error, Result, Write};
+use std::io::{Error, Result};
 
-use ansi_term::{Colour, Style};
+use ansi_term::Style;
 use fehler::throws;
-use pulldown_cmark::CodeBlockKind;
-use syntect::highlighting::{HighlightState, Highlighter, Theme};
+use pulldown_cmark::{Alignment, CodeBlockKind};
+use syntect::highlighting::{HighlightState, Highlighter};
 use syntect::parsing::{ParseState, ScopeStack};
+use unicode_width::UnicodeWidthStr;
 
 use crate::references::*;
 use crate::render::data::LinkReferenceDefinition;
 use crate::render::state::*;
+use crate::render::table::*;
+use crate::render::wrap::*;
+use crate::renderer::Renderer;
+use crate::stylesheet::apply_style;
 use crate::{
-    Environment, MarkCapability, Settings, StyleCapability, TerminalCapabilities, TerminalSize,
+    Environment, GlyphStyle, HeadingStyle, Outline, Settings, StyleCapability, Stylesheet,
+    TableBorders, TerminalCapabilities, TerminalSize,
 };
 
 #[inline]
-pub fn write_indent<W: Write>(writer: &mut W, level: u16) -> std::io::Result<()> {
-    write!(writer, "{}", " ".repeat(level as usize))
+pub fn write_indent<R: Renderer>(writer: &mut ColumnWriter<R>, level: u16) -> Result<()> {
+    writer.write_plain(" ".repeat(level as usize))
 }
 
-#[inline]
-pub fn write_styled<W: Write, S: AsRef<str>>(
-    writer: &mut W,
-    capabilities: &TerminalCapabilities,
+/// Write inline `text` with `style`, wrapped at `max_columns`.
+///
+/// Continue wrapped lines at `indent`.  Do not break words which do not fit
+/// into a line, but leave them to the terminal instead.
+#[throws]
+pub fn write_wrapped<R: Renderer, S: AsRef<str>>(
+    writer: &mut ColumnWriter<R>,
     style: &Style,
+    indent: u16,
+    max_columns: usize,
     text: S,
-) -> Result<()> {
-    match capabilities.style {
-        None => write!(writer, "{}", text.as_ref()),
-        Some(StyleCapability::Ansi(ansi)) => ansi.write_styled(writer, style, text),
+) -> () {
+    // Collect words for the current line to write them all at once.
+    let mut line = String::new();
+    let mut column = writer.column();
+    let mut space = false;
+    for word in words(text.as_ref()) {
+        if word.space_before {
+            if line.is_empty() {
+                writer.defer_space(*style);
+            } else {
+                space = true;
+            }
+        }
+        if word.text.is_empty() {
+            continue;
+        }
+        let space_before = if line.is_empty() {
+            writer.has_deferred_space()
+        } else {
+            space
+        };
+        let space_width = if space_before { 1 } else { 0 };
+        let may_break = word.break_before || space_before;
+        if may_break
+            && (indent as usize) < column
+            && max_columns < column + space_width + word.width()
+        {
+            if !line.is_empty() {
+                writer.write_styled(style, &line)?;
+            }
+            writer.newline()?;
+            write_indent(writer, indent)?;
+            line.clear();
+            column = indent as usize;
+        } else {
+            if space && !line.is_empty() {
+                line.push(' ');
+            }
+            column += space_width;
+        }
+        space = false;
+        line.push_str(word.text);
+        column += word.width();
+    }
+    if !line.is_empty() {
+        writer.write_styled(style, &line)?;
+        if space {
+            writer.defer_space(*style);
+        }
     }
 }
 
-#[inline]
+/// Write inline `text` with `style` verbatim.
+///
+/// Keep `text` in one piece, but continue on a new line at `indent` if the
+/// first line of `text` does not fit into the current line, and if a space
+/// precedes `text`.
 #[throws]
-pub fn write_mark<W: Write>(writer: &mut W, capabilities: &TerminalCapabilities) -> () {
-    if let Some(mark) = capabilities.marks {
-        match mark {
-            MarkCapability::ITerm2(marks) => marks.set_mark(writer)?,
-        }
+pub fn write_unbroken<R: Renderer, S: AsRef<str>>(
+    writer: &mut ColumnWriter<R>,
+    style: &Style,
+    indent: u16,
+    max_columns: usize,
+    text: S,
+) -> () {
+    let text = text.as_ref();
+    let width = text.lines().next().map_or(0, |line| line.width());
+    if writer.has_deferred_space()
+        && (indent as usize) < writer.column()
+        && max_columns < writer.column() + 1 + width
+    {
+        writer.newline()?;
+        write_indent(writer, indent)?;
     }
+    writer.write_styled(style, text)?;
+}
+
+/// Repeat the glyph of `line` to fill `length` columns.
+fn repeat_glyph(line: &GlyphStyle, length: usize) -> String {
+    line.glyph.repeat(length / line.glyph.width().max(1))
 }
 
 #[inline]
-pub fn write_rule<W: Write>(
-    writer: &mut W,
-    capabilities: &TerminalCapabilities,
+pub fn write_rule<R: Renderer>(
+    writer: &mut ColumnWriter<R>,
+    rule: &GlyphStyle,
     length: usize,
-) -> std::io::Result<()> {
-    let rule = "\u{2550}".repeat(length);
-    let style = Style::new().fg(Colour::Green);
-    write_styled(writer, capabilities, &style, rule)
+) -> Result<()> {
+    writer.write_styled(&rule.style, repeat_glyph(rule, length))
 }
 
 #[inline]
-pub fn write_border<W: Write>(
-    writer: &mut W,
-    capabilities: &TerminalCapabilities,
+pub fn write_border<R: Renderer>(
+    writer: &mut ColumnWriter<R>,
+    border: &GlyphStyle,
     terminal_size: &TerminalSize,
-) -> std::io::Result<()> {
-    let separator = "\u{2500}".repeat(terminal_size.columns.min(20));
-    let style = Style::new().fg(Colour::Green);
-    write_styled(writer, capabilities, &style, separator)?;
-    writeln!(writer)
+) -> Result<()> {
+    let separator = repeat_glyph(border, terminal_size.columns.min(20));
+    writer.write_styled(&border.style, separator)?;
+    writer.newline()
 }
 
+/// Write the definitions of reference `links`.
+///
+/// For links to headings in the `outline` of the document write the text of
+/// the heading after the link target.
 #[throws]
-pub fn write_link_refs<W: Write>(
-    writer: &mut W,
+pub fn write_link_refs<R: Renderer>(
+    writer: &mut ColumnWriter<R>,
     environment: &Environment,
     capabilities: &TerminalCapabilities,
+    outline: &Outline,
     links: Vec<LinkReferenceDefinition>,
 ) -> () {
     if !links.is_empty() {
-        writeln!(writer)?;
+        writer.newline()?;
         for link in links {
-            let style = Style::new().fg(link.colour);
-            write_styled(writer, capabilities, &style, &format!("[{}]: ", link.index))?;
-
-            // If we can resolve the link try to write it as inline link to make the URL
-            // clickable.  This mostly helps images inside inline links which we had to write as
-            // reference links because we can't nest inline links.
-            if let Some(url) = environment.resolve_reference(&link.target) {
-                use crate::LinkCapability::*;
-                match &capabilities.links {
-                    Some(Osc8(links)) => {
-                        links.set_link_url(writer, url, &environment.hostname)?;
-                        write_styled(writer, capabilities, &style, link.target)?;
-                        links.clear_link(writer)?;
-                    }
-                    None => write_styled(writer, capabilities, &style, link.target)?,
-                };
+            let style = link.style;
+            writer.write_styled(&style, format!("[{}]: ", link.index))?;
+
+            let heading = outline.find_fragment(&link.target);
+            if let Some(heading) = heading {
+                // Links to headings in the same document have no useful URL
+                writer.write_styled(&style, format!("{} ({})", link.target, heading.text))?;
+            } else if let Some(url) = environment.resolve_reference(&link.target) {
+                // If we can resolve the link try to write it as inline link to make the URL
+                // clickable.  This mostly helps images inside inline links which we had to write
+                // as reference links because we can't nest inline links.
+                if capabilities.links.is_some() {
+                    writer.start_link(&url)?;
+                    writer.write_styled(&style, link.target)?;
+                    writer.end_link()?;
+                } else {
+                    writer.write_styled(&style, link.target)?;
+                }
             } else {
-                write_styled(writer, capabilities, &style, link.target)?;
+                writer.write_styled(&style, link.target)?;
             }
 
             if !link.title.is_empty() {
-                write_styled(writer, capabilities, &style, format!(" {}", link.title))?;
+                writer.write_styled(&style, format!(" {}", link.title))?;
             }
-            writeln!(writer)?;
+            writer.newline()?;
         }
     }
 }
 
+/// Format a footnote `index` with superscript digits.
+pub fn superscript(index: usize) -> String {
+    index
+        .to_string()
+        .chars()
+        .map(|digit| match digit {
+            '1' => '\u{00b9}',
+            '2' => '\u{00b2}',
+            '3' => '\u{00b3}',
+            // The remaining superscript digits live in a separate Unicode block
+            digit => std::char::from_u32(0x2070 + digit.to_digit(10).unwrap_or(0)).unwrap_or(digit),
+        })
+        .collect()
+}
+
 #[throws]
-pub fn write_start_code_block<'a, W: Write>(
-    writer: &mut W,
+pub fn write_start_code_block<'a, R: Renderer>(
+    writer: &mut ColumnWriter<R>,
     settings: &Settings,
     indent: u16,
     style: Style,
     block_kind: CodeBlockKind<'a>,
-    theme: &Theme,
 ) -> StackedState {
     write_indent(writer, indent)?;
-    write_border(
-        writer,
-        &settings.terminal_capabilities,
-        &settings.terminal_size,
-    )?;
+    write_border(writer, &settings.stylesheet.border, &settings.terminal_size)?;
     // And start the indent for the contents of the block
     write_indent(writer, indent)?;
 
     match (&settings.terminal_capabilities.style, block_kind) {
-        (Some(StyleCapability::Ansi(ansi)), CodeBlockKind::Fenced(name)) if !name.is_empty() => {
+        (Some(StyleCapability::Ansi(_)), CodeBlockKind::Fenced(name)) if !name.is_empty() => {
             match settings.syntax_set.find_syntax_by_token(&name) {
                 None => LiteralBlockAttrs {
                     indent,
-                    style: style.fg(Colour::Yellow),
+                    style: apply_style(style, &settings.stylesheet.code),
                 }
                 .into(),
                 Some(syntax) => {
                     let parse_state = ParseState::new(syntax);
                     let highlight_state =
-                        HighlightState::new(&Highlighter::new(theme), ScopeStack::new());
+                        HighlightState::new(&Highlighter::new(&settings.theme), ScopeStack::new());
                     HighlightBlockAttrs {
-                        ansi: *ansi,
                         indent,
                         highlight_state,
                         parse_state,
@@ -150,27 +239,141 @@ pub fn write_start_code_block<'a, W: Write>(
         }
         (_, _) => LiteralBlockAttrs {
             indent,
-            style: style.fg(Colour::Yellow),
+            style: apply_style(style, &settings.stylesheet.code),
         }
         .into(),
     }
 }
 
 #[throws]
-pub fn write_start_heading<W: Write>(
-    writer: &mut W,
-    capabilities: &TerminalCapabilities,
+pub fn write_start_heading<R: Renderer>(
+    writer: &mut ColumnWriter<R>,
     style: Style,
-    level: u32,
+    heading: &HeadingStyle,
 ) -> StackedState {
-    let style = style.fg(Colour::Blue).bold();
-    write_styled(
-        writer,
-        capabilities,
-        &style,
-        "\u{2504}".repeat(level as usize),
-    )?;
+    let style = apply_style(style, &heading.style);
+    writer.write_styled(&style, &heading.prefix)?;
 
     // Headlines never wrap, so indent doesn't matter
     StackedState::Inline(InlineState::InlineText, InlineAttrs { style, indent: 0 })
 }
+
+#[throws]
+fn write_table_border<R: Renderer>(
+    writer: &mut ColumnWriter<R>,
+    indent: u16,
+    borders: &TableBorders,
+    border_style: &Style,
+    [left, middle, right]: &[String; 3],
+    widths: &[usize],
+) -> () {
+    let line = widths
+        .iter()
+        .map(|width| borders.horizontal.repeat(width + 2))
+        .collect::<Vec<_>>()
+        .join(middle);
+    write_indent(writer, indent)?;
+    writer.write_styled(border_style, format!("{}{}{}", left, line, right))?;
+    writer.newline()?;
+}
+
+#[allow(clippy::too_many_arguments)]
+#[throws]
+fn write_table_row<R: Renderer>(
+    writer: &mut ColumnWriter<R>,
+    indent: u16,
+    borders: &TableBorders,
+    border_style: &Style,
+    table: &Table,
+    widths: &[usize],
+    row: &[Cell],
+) -> () {
+    let empty = Vec::new();
+    let cells = widths
+        .iter()
+        .enumerate()
+        .map(|(column, width)| wrap_cell(row.get(column).unwrap_or(&empty), *width))
+        .collect::<Vec<_>>();
+    let height = cells.iter().map(Vec::len).max().unwrap_or(1);
+    for index in 0..height {
+        write_indent(writer, indent)?;
+        writer.write_styled(border_style, &borders.vertical)?;
+        for (column, (lines, width)) in cells.iter().zip(widths).enumerate() {
+            let line = lines.get(index).map_or(&[] as &[Fragment], Vec::as_slice);
+            let padding = width.saturating_sub(line_width(line));
+            let (before, after) = match table.alignment(column) {
+                Alignment::None | Alignment::Left => (0, padding),
+                Alignment::Center => (padding / 2, padding - padding / 2),
+                Alignment::Right => (padding, 0),
+            };
+            write_indent(writer, 1 + before as u16)?;
+            for fragment in line {
+                writer.write_styled(&fragment.style, &fragment.text)?;
+            }
+            write_indent(writer, 1 + after as u16)?;
+            writer.write_styled(border_style, &borders.vertical)?;
+        }
+        writer.newline()?;
+    }
+}
+
+/// Write a buffered `table`.
+///
+/// Fit the table into `max_width` columns, and draw borders with the glyphs
+/// and style of `stylesheet`, unless the terminal does not support styling at
+/// all in which case we fall back to plain ASCII.
+#[throws]
+pub fn write_table<R: Renderer>(
+    writer: &mut ColumnWriter<R>,
+    capabilities: &TerminalCapabilities,
+    stylesheet: &Stylesheet,
+    indent: u16,
+    max_width: usize,
+    table: &Table,
+) -> () {
+    let ascii;
+    let borders = match capabilities.style {
+        Some(_) => &stylesheet.table_borders,
+        None => {
+            ascii = TableBorders::ascii();
+            &ascii
+        }
+    };
+    let border_style = &stylesheet.border.style;
+    let widths = table.column_widths(max_width);
+    if !widths.is_empty() {
+        write_table_border(writer, indent, borders, border_style, &borders.top, &widths)?;
+        if !table.head().is_empty() {
+            write_table_row(
+                writer,
+                indent,
+                borders,
+                border_style,
+                table,
+                &widths,
+                table.head(),
+            )?;
+            if !table.rows().is_empty() {
+                write_table_border(
+                    writer,
+                    indent,
+                    borders,
+                    border_style,
+                    &borders.middle,
+                    &widths,
+                )?;
+            }
+        }
+        for row in table.rows() {
+            write_table_row(writer, indent, borders, border_style, table, &widths, row)?;
+        }
+        write_table_border(
+            writer,
+            indent,
+            borders,
+            border_style,
+            &borders.bottom,
+            &widths,
+        )?;
+    }
+}
@@ -0,0 +1,174 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Output backends for the rendering algorithm.
+//!
+//! The rendering algorithm lays out a document, i.e. it wraps text, indents
+//! blocks and draws borders, and hands the result to a [`Renderer`] which
+//! turns it into actual output.
+
+use std::io::Result;
+
+use ansi_term::Style;
+use url::Url;
+
+use crate::resources::ResourceLoader;
+use crate::{Environment, Error, Resource, TerminalSize};
+
+/// A block of a document.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Block {
+    /// A paragraph.
+    Paragraph,
+    /// A heading of the given level.
+    Heading(u32),
+    /// A block quote.
+    BlockQuote,
+    /// A code block.
+    CodeBlock,
+    /// A list.
+    List,
+    /// An item of a list.
+    Item,
+    /// A table.
+    Table,
+    /// A horizontal rule.
+    Rule,
+}
+
+/// An image to show inline.
+#[derive(Debug)]
+pub struct InlineImage<'a> {
+    /// The URL of the image.
+    pub url: &'a Url,
+    /// The indent of the current block.
+    ///
+    /// Images which take lines of their own should start each line with this
+    /// indent.
+    pub indent: u16,
+    /// The column the image starts at.
+    pub column: usize,
+    /// The size of the terminal.
+    pub terminal_size: TerminalSize,
+    pub(crate) loader: ResourceLoader<'a>,
+}
+
+impl<'a> InlineImage<'a> {
+    /// The environment of the document.
+    pub fn environment(&self) -> &'a Environment {
+        self.loader.environment
+    }
+
+    /// Check whether the resource policy permits access to the image.
+    ///
+    /// Use this if the output reads the image by itself.
+    pub fn check_access(&self) -> std::result::Result<(), Error> {
+        self.loader
+            .policy
+            .check(self.url, &self.loader.environment.base_url)
+            .map(|_| ())
+    }
+
+    /// Read the image according to the resource policy.
+    ///
+    /// Use prefetched and cached resources if possible.
+    pub fn read(&self) -> std::result::Result<Resource, Error> {
+        self.loader.read(self.url)
+    }
+}
+
+/// An output backend for the rendering algorithm.
+///
+/// mdcat writes all output of the rendering algorithm to a renderer: Text with
+/// a style, links around text, marks, and images.  The rendering algorithm
+/// already wrapped and indented all text, so a renderer just needs to write
+/// what it gets.
+///
+/// See [`crate::TerminalRenderer`] for a renderer which writes to a terminal.
+pub trait Renderer {
+    /// Write `text` with `style`.
+    ///
+    /// `text` may contain newlines; the style extends over all lines.
+    fn write_styled(&mut self, style: &Style, text: &str) -> Result<()>;
+
+    /// Link all text until the next [`Renderer::end_link`] to `url`.
+    ///
+    /// Links do not nest.
+    fn start_link(&mut self, url: &Url) -> Result<()>;
+
+    /// End the current link.
+    fn end_link(&mut self) -> Result<()>;
+
+    /// Set a mark at the current position, e.g. for top-level headings.
+    fn set_mark(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Start a `block`.
+    ///
+    /// The rendering algorithm calls this before it writes anything for the
+    /// block, including the margin before the block.
+    fn start_block(&mut self, _block: Block) -> Result<()> {
+        Ok(())
+    }
+
+    /// End a `block`.
+    fn end_block(&mut self, _block: Block) -> Result<()> {
+        Ok(())
+    }
+
+    /// Show an inline `image`.
+    ///
+    /// Return the column after the image, or `None` if this renderer cannot
+    /// show images, in which case the rendering algorithm writes the
+    /// description of the image instead.  Images which take lines of their own
+    /// must start and end on a new line, and indent the following line by the
+    /// indent of the image.
+    ///
+    /// If the image cannot be shown return the error; mdcat then reports a
+    /// warning and writes the description of the image instead.  Only
+    /// [`Error::Io`] ends rendering, so return it for failures to write output
+    /// only.
+    fn write_image(
+        &mut self,
+        _image: &InlineImage<'_>,
+    ) -> std::result::Result<Option<usize>, Error> {
+        Ok(None)
+    }
+}
+
+impl<R: Renderer + ?Sized> Renderer for &mut R {
+    fn write_styled(&mut self, style: &Style, text: &str) -> Result<()> {
+        (**self).write_styled(style, text)
+    }
+
+    fn start_link(&mut self, url: &Url) -> Result<()> {
+        (**self).start_link(url)
+    }
+
+    fn end_link(&mut self) -> Result<()> {
+        (**self).end_link()
+    }
+
+    fn set_mark(&mut self) -> Result<()> {
+        (**self).set_mark()
+    }
+
+    fn start_block(&mut self, block: Block) -> Result<()> {
+        (**self).start_block(block)
+    }
+
+    fn end_block(&mut self, block: Block) -> Result<()> {
+        (**self).end_block(block)
+    }
+
+    fn write_image(
+        &mut self,
+        image: &InlineImage<'_>,
+    ) -> std::result::Result<Option<usize>, Error> {
+        (**self).write_image(image)
+    }
+}
@@ -8,64 +8,209 @@
 
 use anyhow::{anyhow, Context, Error, Result};
 use fehler::{throw, throws};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageOutputFormat};
+use mime::Mime;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
-use ureq::AgentBuilder;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
 use url::Url;
 
-/// What kind of resources mdcat may access when rendering.
+use crate::cache::{self, Cache, HttpMetadata};
+use crate::http::HttpClient;
+use crate::magic;
+use crate::svg::render_svg;
+use crate::terminal::size::PixelSize;
+use crate::Environment;
+
+/// Read size limit for resources.
+const RESOURCE_READ_LIMIT: u64 = 104_857_600;
+
+/// Timeout for a HTTP request, including reading the response.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What resources mdcat may access when rendering.
 ///
-/// This struct denotes whether mdcat shows inline images from remote URLs or
-/// just from local files.
-#[derive(Debug, Copy, Clone)]
-pub enum ResourceAccess {
-    /// Use only local files and prohibit remote resources.
-    LocalOnly,
-    /// Use local and remote resources alike.
-    RemoteAllowed,
-}
-
-impl ResourceAccess {
-    /// Whether the resource access permits access to the given `url`.
-    pub fn permits(self, url: &Url) -> bool {
-        match self {
-            ResourceAccess::LocalOnly if is_local(url) => true,
-            ResourceAccess::RemoteAllowed => true,
-            _ => false,
+/// The policy restricts the URL schemes mdcat may read from, the hosts it may
+/// contact, and the directory local files must be in, and limits the size of
+/// resources and the time to fetch remote resources.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourcePolicy {
+    /// The URL schemes mdcat may read from.
+    ///
+    /// mdcat only supports `file`, `http` and `https`.
+    pub schemes: Vec<String>,
+    /// The hosts mdcat may contact, including their subdomains.
+    ///
+    /// If empty permit all hosts not in `denied_hosts`.
+    pub allowed_hosts: Vec<String>,
+    /// The hosts mdcat must not contact, including their subdomains.
+    pub denied_hosts: Vec<String>,
+    /// The directory which local files must be in.
+    ///
+    /// If `None` local files must be in the directory of the document, i.e.
+    /// the directory of the base URL of the [`crate::Environment`].  If the
+    /// base URL is not a local directory deny access to all local files.
+    pub local_root: Option<PathBuf>,
+    /// The maximum size of a resource in bytes.
+    pub size_limit: u64,
+    /// The timeout for a HTTP request, including reading the response.
+    pub timeout: Duration,
+}
+
+impl Default for ResourcePolicy {
+    /// Permit local files in the directory of the document and remote
+    /// resources from all hosts.
+    fn default() -> Self {
+        ResourcePolicy {
+            schemes: vec!["file".to_string(), "http".to_string(), "https".to_string()],
+            allowed_hosts: Vec::new(),
+            denied_hosts: Vec::new(),
+            local_root: None,
+            size_limit: RESOURCE_READ_LIMIT,
+            timeout: HTTP_TIMEOUT,
         }
     }
 }
 
-/// Whether `url` is readable as local file.
-fn is_local(url: &Url) -> bool {
-    url.scheme() == "file" && url.to_file_path().is_ok()
+/// Whether `host` is `pattern` or a subdomain of `pattern`.
+fn host_matches(host: &str, pattern: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    let pattern = pattern.trim_start_matches('.').to_ascii_lowercase();
+    host == pattern || host.ends_with(&format!(".{}", pattern))
 }
 
-/// Read size limit for resources.
-static RESOURCE_READ_LIMIT: u64 = 104_857_600;
+impl ResourcePolicy {
+    /// A policy which permits only local files in the directory of the document.
+    pub fn local_only() -> Self {
+        ResourcePolicy {
+            schemes: vec!["file".to_string()],
+            ..ResourcePolicy::default()
+        }
+    }
 
-#[throws]
-fn fetch_http(url: &Url) -> Vec<u8> {
-    let proxy = match env_proxy::for_url(url).to_string() {
-        None => None,
-        Some(proxy_url) => {
-            let proxy = ureq::Proxy::new(&proxy_url).with_context(|| {
-                format!("Failed to set proxy for URL {} to {}", url, &proxy_url)
-            })?;
-            Some(proxy)
+    /// Whether this policy permits access to `url`.
+    ///
+    /// See [`ResourcePolicy::check`].
+    pub fn permits(&self, url: &Url, base_url: &Url) -> bool {
+        self.check(url, base_url).is_ok()
+    }
+
+    /// Check whether this policy permits access to `url`.
+    ///
+    /// Use `base_url` to find the directory of the document if this policy has
+    /// no explicit local root.  Return the local path for `file:` URLs.
+    ///
+    /// Fail with [`crate::Error::AccessDenied`] if this policy denies access
+    /// to `url`.
+    #[throws(crate::Error)]
+    pub fn check(&self, url: &Url, base_url: &Url) -> Option<PathBuf> {
+        let denied = |reason: String| crate::Error::AccessDenied {
+            url: url.clone(),
+            reason,
+        };
+        if !self
+            .schemes
+            .iter()
+            .any(|scheme| scheme.eq_ignore_ascii_case(url.scheme()))
+        {
+            throw!(denied(format!("scheme {} not permitted", url.scheme())));
+        }
+        if let Some(host) = url.host_str() {
+            if self
+                .denied_hosts
+                .iter()
+                .any(|pattern| host_matches(host, pattern))
+            {
+                throw!(denied(format!("host {} denied", host)));
+            }
+            if !self.allowed_hosts.is_empty()
+                && !self
+                    .allowed_hosts
+                    .iter()
+                    .any(|pattern| host_matches(host, pattern))
+            {
+                throw!(denied(format!("host {} not permitted", host)));
+            }
         }
-    };
+        if url.scheme() == "file" {
+            let path = url
+                .to_file_path()
+                .map_err(|_| denied("not a local file".to_string()))?;
+            let root = match &self.local_root {
+                Some(root) => root.clone(),
+                // The directory of the base URL
+                None => base_url
+                    .join(".")
+                    .ok()
+                    .filter(|directory| directory.scheme() == "file")
+                    .and_then(|directory| directory.to_file_path().ok())
+                    .ok_or_else(|| denied("document is not a local file".to_string()))?,
+            };
+            let root = root
+                .canonicalize()
+                .map_err(|error| denied(format!("{}: {}", root.display(), error)))?;
+            // Resolve symlinks and .. before checking the path, but let
+            // missing files through to fail when reading them.
+            let path = path.canonicalize().unwrap_or(path);
+            if !path.starts_with(&root) {
+                throw!(denied(format!("outside of {}", root.display())));
+            }
+            Some(path)
+        } else {
+            None
+        }
+    }
+}
 
-    let response = proxy
-        .map_or(AgentBuilder::new(), |proxy| {
-            AgentBuilder::new().proxy(proxy)
-        })
-        .build()
-        .request_url("GET", url)
-        .set("User-Agent", concat!("mdcat/", env!("CARGO_PKG_VERSION")))
-        .call()
-        .with_context(|| format!("Failed to GET {}", url))?;
+/// A resource read from a URL.
+#[derive(Debug, Clone)]
+pub struct Resource {
+    /// The contents of the resource.
+    pub contents: Vec<u8>,
+    /// A hint for the mime type of the resource.
+    ///
+    /// The `Content-Type` of a HTTP response, or a mime type guessed from the
+    /// file extension in the URL.
+    pub mime_type_hint: Option<Mime>,
+}
+
+impl Resource {
+    /// Detect the mime type of this resource.
+    ///
+    /// See [`magic::detect_mime_type`].
+    pub fn detect_mime_type(&self) -> Result<Mime> {
+        magic::detect_mime_type(&self.contents, self.mime_type_hint.as_ref())
+    }
+}
+
+/// Guess the mime type of `url` from the file extension in its path.
+fn mime_type_hint_from_url(url: &Url) -> Option<Mime> {
+    magic::mime_type_from_extension(url.path())
+}
+
+/// Get a hint for the mime type of a HTTP resource at `url`.
+///
+/// Use the `content_type` of the response if it denotes an image, otherwise
+/// guess from the extension of `url`.
+fn mime_type_hint_from_http(url: &Url, content_type: Option<&str>) -> Option<Mime> {
+    content_type
+        .and_then(|value| value.parse::<Mime>().ok())
+        .filter(|mime| mime.type_() == mime::IMAGE)
+        .or_else(|| mime_type_hint_from_url(url))
+}
+
+/// The maximum number of resources to prefetch at once.
+const PREFETCH_CONCURRENCY: usize = 8;
 
+/// Read the body of a HTTP `response` from `url`, up to `limit` bytes.
+#[throws]
+fn read_http_body(url: &Url, response: ureq::Response, limit: u64) -> Vec<u8> {
     match response.header("Content-Length") {
         // The server gave us no content size so read until the end of the stream, but not more than our read limit.
         None => {
@@ -74,16 +219,12 @@ fn fetch_http(url: &Url) -> Vec<u8> {
             // We read one byte more than our limit, so that we can differentiate between a regular EOF and one from hitting the limit.
             response
                 .into_reader()
-                .take(RESOURCE_READ_LIMIT + 1)
+                .take(limit + 1)
                 .read_to_end(&mut buffer)
                 .with_context(|| format!("Failed to read from {}", url))?;
 
-            if RESOURCE_READ_LIMIT < buffer.len() as u64 {
-                throw!(anyhow!(
-                    "Contents of {} exceeded {}, rejected",
-                    url,
-                    RESOURCE_READ_LIMIT
-                ))
+            if limit < buffer.len() as u64 {
+                throw!(anyhow!("Contents of {} exceeded {}, rejected", url, limit))
             } else {
                 buffer
             }
@@ -93,12 +234,12 @@ fn fetch_http(url: &Url) -> Vec<u8> {
             let size = value
                 .parse::<usize>()
                 .with_context(|| format!("{} reports invalid content size {}", url, value))?;
-            if RESOURCE_READ_LIMIT < size as u64 {
+            if limit < size as u64 {
                 throw!(anyhow!(
                     "{} reports size {} which exceeds limit {}, refusing to read",
                     url,
                     size,
-                    RESOURCE_READ_LIMIT
+                    limit
                 ))
             }
 
@@ -106,7 +247,7 @@ fn fetch_http(url: &Url) -> Vec<u8> {
             response
                 .into_reader()
                 // Just to be on the safe side limit the read operation explicitly, just in case we got the above check wrong
-                .take(RESOURCE_READ_LIMIT)
+                .take(limit)
                 .read_exact(buffer.as_mut_slice())
                 .with_context(|| format!("Failed to read from {}", url))?;
 
@@ -115,123 +256,665 @@ fn fetch_http(url: &Url) -> Vec<u8> {
     }
 }
 
+/// Fetch `url` over HTTP with `client`.
+///
+/// If `cache` has a response for `url` revalidate the cached response with its
+/// `ETag` or `Last-Modified` header, and use the cached contents if the server
+/// says that the resource didn't change.  Put responses with any of these
+/// headers into `cache`.
+///
+/// Use the timeout and the size limit of `policy`, and only follow redirects
+/// to URLs which `policy` permits for `base_url`.
+#[throws]
+fn fetch_http(
+    url: &Url,
+    client: &HttpClient,
+    policy: &ResourcePolicy,
+    base_url: &Url,
+    cache: Option<&Cache>,
+) -> Resource {
+    // Ignore cached contents which exceed the size limit; we may have cached
+    // them under a looser limit
+    let cached = cache
+        .and_then(|cache| cache.http_response(url))
+        .filter(|(metadata, contents)| {
+            metadata.has_validator() && contents.len() as u64 <= policy.size_limit
+        });
+
+    let mut headers = Vec::new();
+    if let Some((metadata, _)) = &cached {
+        if let Some(etag) = &metadata.etag {
+            headers.push(("If-None-Match", etag.as_str()));
+        }
+        if let Some(last_modified) = &metadata.last_modified {
+            headers.push(("If-Modified-Since", last_modified.as_str()));
+        }
+    }
+    let response = client.get(url, &headers, policy.timeout, |target| {
+        policy.check(target, base_url).map(|_| ())
+    })?;
+
+    match cached {
+        Some((metadata, contents)) if response.status() == 304 => Resource {
+            contents,
+            mime_type_hint: mime_type_hint_from_http(url, metadata.content_type.as_deref()),
+        },
+        _ => {
+            let header = |name| response.header(name).map(ToString::to_string);
+            let metadata = HttpMetadata {
+                url: url.to_string(),
+                etag: header("ETag"),
+                last_modified: header("Last-Modified"),
+                content_type: header("Content-Type"),
+            };
+            let contents = read_http_body(url, response, policy.size_limit)?;
+            if let Some(cache) = cache.filter(|_| metadata.has_validator()) {
+                // The cache is optional, so don't fail if we can't write to it
+                let _ = cache.put_http_response(url, &metadata, &contents);
+            }
+            Resource {
+                contents,
+                mime_type_hint: mime_type_hint_from_http(url, metadata.content_type.as_deref()),
+            }
+        }
+    }
+}
+
 /// Read the contents of the given `url` if supported.
 ///
 /// Fail if
 ///
+/// - `policy` denies access to `url`,
 /// - we don’t know how to read from `url`, i.e. the scheme's not supported,
 /// - if we fail to read from URL, or
-/// - if contents of the URL exceed an internal hard-coded size limit (currently 100 MiB).
+/// - if contents of the URL exceed the size limit of `policy`.
 ///
 /// We currently support `file:` URLs which the underlying operation system can
-/// read (local on UNIX, UNC paths on Windows), and HTTP(S) URLs.
-pub fn read_url(url: &Url, access: ResourceAccess) -> Result<Vec<u8>> {
-    if !access.permits(url) {
-        throw!(anyhow!(
-            "Access denied to URL {} by policy {:?}",
-            url,
-            access
-        ))
-    }
-    match url.scheme() {
-        "file" => match url.to_file_path() {
-            Ok(path) => {
-                let mut buffer = Vec::new();
-                File::open(path)
-                    .with_context(|| format!("Failed to open file at {}", url))?
-                    // Read a byte more than the limit differentiate an expected EOF from hitting the limit
-                    .take(RESOURCE_READ_LIMIT + 1)
-                    .read_to_end(&mut buffer)
-                    .with_context(|| format!("Failed to read from file at {}", url))?;
-
-                if RESOURCE_READ_LIMIT < buffer.len() as u64 {
-                    Err(anyhow!(
-                        "Contents of {} exceeded {}, rejected",
-                        url,
-                        RESOURCE_READ_LIMIT
-                    ))
-                } else {
-                    Ok(buffer)
-                }
+/// read (local on UNIX, UNC paths on Windows), and HTTP(S) URLs.  Use `cache`
+/// for HTTP(S) URLs, if given.
+///
+/// Use the HTTP client of `environment` for HTTP(S) URLs, and its base URL to
+/// restrict local files to the directory of the document; see
+/// [`ResourcePolicy::local_root`].
+///
+/// Fail with [`crate::Error::AccessDenied`] if `policy` denies access to
+/// `url`, and with [`crate::Error::Fetch`] otherwise.
+pub fn read_url(
+    url: &Url,
+    environment: &Environment,
+    policy: &ResourcePolicy,
+    cache: Option<&Cache>,
+) -> std::result::Result<Resource, crate::Error> {
+    let local_path = policy.check(url, &environment.base_url)?;
+    fetch_url(url, local_path, environment, policy, cache).map_err(|source| {
+        // The policy may also deny access to the target of a redirect
+        source
+            .downcast::<crate::Error>()
+            .unwrap_or_else(|source| crate::Error::Fetch {
+                url: url.clone(),
+                source,
+            })
+    })
+}
+
+/// Fetch the contents of `url` after `policy` permitted access.
+///
+/// `local_path` is the path of a `file:` URL.
+fn fetch_url(
+    url: &Url,
+    local_path: Option<PathBuf>,
+    environment: &Environment,
+    policy: &ResourcePolicy,
+    cache: Option<&Cache>,
+) -> Result<Resource> {
+    match (url.scheme(), local_path) {
+        ("file", Some(path)) => {
+            let limit = policy.size_limit;
+            let mut buffer = Vec::new();
+            File::open(path)
+                .with_context(|| format!("Failed to open file at {}", url))?
+                // Read a byte more than the limit differentiate an expected EOF from hitting the limit
+                .take(limit + 1)
+                .read_to_end(&mut buffer)
+                .with_context(|| format!("Failed to read from file at {}", url))?;
+
+            if limit < buffer.len() as u64 {
+                Err(anyhow!("Contents of {} exceeded {}, rejected", url, limit))
+            } else {
+                Ok(Resource {
+                    contents: buffer,
+                    mime_type_hint: mime_type_hint_from_url(url),
+                })
             }
-            Err(_) => Err(anyhow!("Cannot convert URL {} to file path", url)),
-        },
-        "http" | "https" => fetch_http(url),
-        _ => Err(anyhow!("Cannot read from URL {}, protocol not supported")),
+        }
+        ("http", _) | ("https", _) => {
+            fetch_http(url, &environment.http, policy, &environment.base_url, cache)
+        }
+        _ => Err(anyhow!(
+            "Cannot read from URL {}, protocol not supported",
+            url
+        )),
+    }
+}
+
+/// Resources fetched ahead of rendering.
+#[derive(Debug, Default)]
+pub struct Prefetched {
+    /// The fetched resources, or the error if fetching failed.
+    resources: HashMap<Url, std::result::Result<Resource, crate::Error>>,
+}
+
+impl Prefetched {
+    /// Fetch all `urls` concurrently.
+    ///
+    /// Fetch at most a fixed number of URLs at once, to not overload servers or
+    /// our own connection.
+    ///
+    /// See [`read_url`].
+    pub fn fetch(
+        urls: Vec<Url>,
+        environment: &Environment,
+        policy: &ResourcePolicy,
+        cache: Option<&Cache>,
+    ) -> Prefetched {
+        let workers = urls.len().min(PREFETCH_CONCURRENCY);
+        let pending = Mutex::new(urls.into_iter());
+        let resources = Mutex::new(HashMap::new());
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    // Release the lock before fetching, to let other workers continue
+                    let next = pending.lock().ok().and_then(|mut urls| urls.next());
+                    match next {
+                        None => break,
+                        Some(url) => {
+                            let resource = read_url(&url, environment, policy, cache);
+                            if let Ok(mut resources) = resources.lock() {
+                                resources.insert(url, resource);
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        Prefetched {
+            resources: resources.into_inner().unwrap_or_default(),
+        }
+    }
+
+    /// Get the prefetched resource for `url`, if any.
+    fn get(&self, url: &Url) -> Option<std::result::Result<Resource, crate::Error>> {
+        use crate::Error::*;
+        self.resources.get(url).map(|resource| match resource {
+            Ok(resource) => Ok(resource.clone()),
+            // Errors are not clonable, so copy what we can.
+            Err(AccessDenied { url, reason }) => Err(AccessDenied {
+                url: url.clone(),
+                reason: reason.clone(),
+            }),
+            Err(error) => Err(Fetch {
+                url: url.clone(),
+                source: anyhow!("{}", error),
+            }),
+        })
+    }
+}
+
+/// Reads resources for rendering.
+///
+/// Take resources fetched ahead of rendering from a [`Prefetched`] set, and
+/// read all other resources on demand.
+#[derive(Debug, Clone, Copy)]
+pub struct ResourceLoader<'a> {
+    /// What resources we may access.
+    pub policy: &'a ResourcePolicy,
+    /// The environment of the document.
+    pub environment: &'a Environment,
+    /// The cache to use, if any.
+    pub cache: Option<&'a Cache>,
+    /// Resources fetched ahead of rendering.
+    pub prefetched: &'a Prefetched,
+}
+
+impl ResourceLoader<'_> {
+    /// Whether we may access the resource at `url`.
+    pub fn permits(&self, url: &Url) -> bool {
+        self.policy.permits(url, &self.environment.base_url)
+    }
+
+    /// Read the resource at `url`.
+    ///
+    /// See [`read_url`].
+    pub fn read(&self, url: &Url) -> std::result::Result<Resource, crate::Error> {
+        self.prefetched
+            .get(url)
+            .unwrap_or_else(|| read_url(url, self.environment, self.policy, self.cache))
+    }
+}
+
+/// Render the SVG image in `contents` to PNG, using `cache` if given.
+///
+/// See [`render_svg`].
+pub fn render_svg_cached(
+    contents: &[u8],
+    size: Option<PixelSize>,
+    cache: Option<&Cache>,
+) -> std::io::Result<Vec<u8>> {
+    cache::rendered(cache, "svg", contents, size, || render_svg(contents, size))
+}
+
+/// Get the dimensions of the pixel image in `contents` without decoding it.
+pub fn image_dimensions(contents: &[u8]) -> std::io::Result<(u32, u32)> {
+    image::io::Reader::new(Cursor::new(contents))
+        .with_guessed_format()?
+        .into_dimensions()
+        .map_err(std::io::Error::other)
+}
+
+/// Whether an image of the given `dimensions` needs to be scaled down to fit into `size`.
+fn exceeds(dimensions: (u32, u32), size: Option<PixelSize>) -> bool {
+    size.is_some_and(|size| {
+        !matches!(
+            PixelSize::from_xy(dimensions).partial_cmp(&size),
+            Some(Ordering::Less) | Some(Ordering::Equal)
+        )
+    })
+}
+
+/// Read an image from `url` with `loader`.
+///
+/// See [`load_image`].
+pub fn read_image(
+    url: &Url,
+    loader: &ResourceLoader,
+    size: Option<PixelSize>,
+) -> Result<DynamicImage> {
+    load_image(url, &loader.read(url)?, loader.cache, size)
+}
+
+/// Load an image from the `resource` read from `url`.
+///
+/// Detect the format of the image from its contents, and render SVG images to
+/// pixels.  If the image exceeds `size` scale it down to fit into `size`
+/// (preserving aspect ratio).
+///
+/// Use `cache` to cache rendered or scaled images.
+pub fn load_image(
+    url: &Url,
+    resource: &Resource,
+    cache: Option<&Cache>,
+    size: Option<PixelSize>,
+) -> Result<DynamicImage> {
+    let mime = resource
+        .detect_mime_type()
+        .with_context(|| format!("Failed to detect mime type for URL {}", url))?;
+    let contents = &resource.contents;
+    if magic::is_svg(&mime) {
+        image::load_from_memory(
+            &render_svg_cached(contents, size, cache)
+                .with_context(|| format!("Failed to render SVG at {} to PNG", url))?,
+        )
+        .with_context(|| format!("Failed to load SVG rendered from {}", url))
+    } else {
+        let dimensions = image_dimensions(contents)
+            .with_context(|| format!("Failed to load image from URL {}", url))?;
+        match size.filter(|_| exceeds(dimensions, size)) {
+            None => image::load_from_memory(contents)
+                .with_context(|| format!("Failed to load image from URL {}", url)),
+            Some(size) => {
+                let scaled = cache::rendered(cache, "scaled", contents, Some(size), || {
+                    let image = image::load_from_memory(contents)
+                        .map_err(std::io::Error::other)?
+                        .resize(size.x, size.y, FilterType::Triangle);
+                    let mut png = Vec::new();
+                    image
+                        .write_to(&mut png, ImageOutputFormat::Png)
+                        .map_err(std::io::Error::other)?;
+                    Ok(png)
+                })
+                .with_context(|| format!("Failed to scale image from URL {}", url))?;
+                image::load_from_memory(&scaled)
+                    .with_context(|| format!("Failed to load image scaled from {}", url))
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::http::test_server::{TestResponse, TestServer};
     use pretty_assertions::assert_eq;
 
+    fn sample_url(name: &str) -> Url {
+        let path = std::env::current_dir().unwrap().join("sample").join(name);
+        Url::from_file_path(path).unwrap()
+    }
+
+    fn sample_dir() -> Url {
+        Url::from_directory_path(std::env::current_dir().unwrap().join("sample")).unwrap()
+    }
+
+    fn sample_environment() -> Environment {
+        Environment::for_local_directory(&std::env::current_dir().unwrap().join("sample")).unwrap()
+    }
+
+    #[test]
+    fn policy_permits_local_files_in_document_directory() {
+        let policy = ResourcePolicy::default();
+        let logo = sample_url("rust-logo.svg");
+        assert!(policy.permits(&logo, &sample_dir()));
+        assert!(ResourcePolicy::local_only().permits(&logo, &sample_dir()));
+
+        let base_url = sample_url("showcase.md");
+        assert!(policy.permits(&logo, &base_url));
+        let readme = Url::from_file_path(std::env::current_dir().unwrap().join("README.md"));
+        assert!(!policy.permits(&readme.unwrap(), &sample_dir()));
+    }
+
     #[test]
     #[cfg(unix)]
-    fn resource_access_permits_local_resource() {
-        let resource = Url::parse("file:///foo/bar").unwrap();
-        assert!(ResourceAccess::LocalOnly.permits(&resource));
-        assert!(ResourceAccess::RemoteAllowed.permits(&resource));
+    fn policy_denies_local_files_outside_of_local_root() {
+        let url = Url::parse("file:///etc/passwd").unwrap();
+        let error = ResourcePolicy::default()
+            .check(&url, &sample_dir())
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Access denied to URL file:///etc/passwd: outside of"),
+            "Unexpected error: {}",
+            error
+        );
+
+        let policy = ResourcePolicy {
+            local_root: Some("/etc".into()),
+            ..ResourcePolicy::default()
+        };
+        assert!(policy.permits(&url, &sample_dir()));
+    }
+
+    #[test]
+    fn policy_denies_local_files_for_remote_documents() {
+        let base_url = Url::parse("https://example.com/README.md").unwrap();
+        let error = ResourcePolicy::default()
+            .check(&sample_url("rust-logo.svg"), &base_url)
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .ends_with(": document is not a local file"),
+            "Unexpected error: {}",
+            error
+        );
     }
 
     #[test]
     #[cfg(unix)]
-    fn resource_access_permits_remote_file_url() {
-        let resource = Url::parse("file://example.com/foo/bar").unwrap();
-        assert!(!ResourceAccess::LocalOnly.permits(&resource));
-        assert!(ResourceAccess::RemoteAllowed.permits(&resource));
+    fn policy_denies_remote_file_url() {
+        let url = Url::parse("file://example.com/foo/bar").unwrap();
+        assert!(!ResourcePolicy::default().permits(&url, &sample_dir()));
     }
 
     #[test]
-    fn resource_access_permits_https_url() {
-        let resource = Url::parse("https:///foo/bar").unwrap();
-        assert!(!ResourceAccess::LocalOnly.permits(&resource));
-        assert!(ResourceAccess::RemoteAllowed.permits(&resource));
+    fn policy_permits_schemes() {
+        let url = Url::parse("https://example.com/foo/bar").unwrap();
+        assert!(ResourcePolicy::default().permits(&url, &sample_dir()));
+        let error = ResourcePolicy::local_only()
+            .check(&url, &sample_dir())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Access denied to URL https://example.com/foo/bar: scheme https not permitted"
+        );
+        let ftp = Url::parse("ftp://example.com/foo/bar").unwrap();
+        assert!(!ResourcePolicy::default().permits(&ftp, &sample_dir()));
     }
 
     #[test]
-    fn read_url_with_http_url_fails_if_local_only_access() {
-        let url = "https://eu.httpbin.org/status/404"
-            .parse::<url::Url>()
-            .unwrap();
-        let error = read_url(&url, ResourceAccess::LocalOnly)
+    fn policy_permits_hosts() {
+        let base_url = sample_dir();
+        let url = |s: &str| Url::parse(s).unwrap();
+        let policy = ResourcePolicy {
+            allowed_hosts: vec!["example.com".to_string()],
+            denied_hosts: vec!["evil.example.com".to_string()],
+            ..ResourcePolicy::default()
+        };
+        assert!(policy.permits(&url("https://example.com/foo"), &base_url));
+        assert!(policy.permits(&url("https://images.Example.com/foo"), &base_url));
+        assert!(!policy.permits(&url("https://notexample.com/foo"), &base_url));
+        assert!(!policy.permits(&url("https://evil.example.com/foo"), &base_url));
+        assert!(!policy.permits(&url("https://a.evil.example.com/foo"), &base_url));
+        assert!(policy.permits(&sample_url("rust-logo.svg"), &base_url));
+    }
+
+    #[test]
+    fn read_url_respects_size_limit() {
+        let policy = ResourcePolicy {
+            size_limit: 10,
+            ..ResourcePolicy::default()
+        };
+        let url = sample_url("rust-logo.svg");
+        let error = read_url(&url, &sample_environment(), &policy, None)
             .unwrap_err()
             .to_string();
+        assert_eq!(error, format!("Contents of {} exceeded 10, rejected", url));
+    }
+
+    #[test]
+    fn prefetch_resources() {
+        let logo = sample_url("rust-logo-128x128.png");
+        let missing = sample_url("does-not-exist.png");
+        let prefetched = Prefetched::fetch(
+            vec![logo.clone(), missing.clone()],
+            &sample_environment(),
+            &ResourcePolicy::local_only(),
+            None,
+        );
+        let resource = prefetched.get(&logo).unwrap().unwrap();
+        assert_eq!(
+            resource.contents,
+            std::fs::read("sample/rust-logo-128x128.png").unwrap()
+        );
+        let error = prefetched.get(&missing).unwrap().unwrap_err();
+        assert!(
+            error.to_string().starts_with("Failed to open file at"),
+            "Unexpected error: {}",
+            error
+        );
+        assert!(prefetched.get(&sample_url("rust-logo.svg")).is_none());
+    }
+
+    #[test]
+    fn resource_loader_prefers_prefetched_resources() {
+        let logo = sample_url("rust-logo-128x128.png");
+        let svg = sample_url("rust-logo.svg");
+        let mut prefetched = Prefetched::default();
+        prefetched.resources.insert(
+            logo.clone(),
+            Err(crate::Error::Fetch {
+                url: logo.clone(),
+                source: anyhow!("Prefetching failed"),
+            }),
+        );
+        let policy = ResourcePolicy::local_only();
+        let environment = sample_environment();
+        let loader = ResourceLoader {
+            policy: &policy,
+            environment: &environment,
+            cache: None,
+            prefetched: &prefetched,
+        };
+        assert_eq!(
+            loader.read(&logo).unwrap_err().to_string(),
+            "Prefetching failed"
+        );
+        assert!(loader.read(&svg).is_ok());
+    }
+
+    #[test]
+    fn read_url_with_http_url_fails_if_local_only_access() {
+        let server = TestServer::start(|_| TestResponse::status(200));
+        let url = server.url("/");
+        let error = read_url(
+            &url,
+            &sample_environment(),
+            &ResourcePolicy::local_only(),
+            None,
+        )
+        .unwrap_err()
+        .to_string();
         assert_eq!(
             error,
-            "Access denied to URL https://eu.httpbin.org/status/404 by policy LocalOnly"
+            format!("Access denied to URL {}: scheme http not permitted", url)
         );
+        assert!(server.requests().is_empty());
     }
 
     #[test]
     fn read_url_with_http_url_fails_when_status_404() {
-        let url = "https://eu.httpbin.org/status/404"
-            .parse::<url::Url>()
-            .unwrap();
-        let result = read_url(&url, ResourceAccess::RemoteAllowed);
+        let server = TestServer::start(|_| TestResponse::status(404));
+        let url = server.url("/status/404");
+        let result = read_url(
+            &url,
+            &sample_environment(),
+            &ResourcePolicy::default(),
+            None,
+        );
         assert!(result.is_err(), "Unexpected success: {:?}", result);
         let error = format!("{:#}", result.unwrap_err());
-        assert_eq!(error, "Failed to GET https://eu.httpbin.org/status/404: https://eu.httpbin.org/status/404: status code 404")
+        assert_eq!(
+            error,
+            format!("Failed to GET {0}: {0}: status code 404", url)
+        )
     }
 
     #[test]
     fn read_url_with_http_url_returns_content_when_status_200() {
-        let url = "https://eu.httpbin.org/bytes/100"
-            .parse::<url::Url>()
-            .unwrap();
-        let result = read_url(&url, ResourceAccess::RemoteAllowed);
+        let server =
+            TestServer::start(|_| TestResponse::ok("application/octet-stream", vec![42; 100]));
+        let url = server.url("/bytes/100");
+        let result = read_url(
+            &url,
+            &sample_environment(),
+            &ResourcePolicy::default(),
+            None,
+        );
         assert!(result.is_ok(), "Unexpected error: {:?}", result);
-        assert_eq!(result.unwrap().len(), 100);
+        assert_eq!(result.unwrap().contents.len(), 100);
     }
 
     #[test]
     fn read_url_with_http_url_fails_when_size_limit_is_exceeded() {
-        let url = "https://eu.httpbin.org/response-headers?content-length=115343400"
-            .parse::<url::Url>()
-            .unwrap();
-        let result = read_url(&url, ResourceAccess::RemoteAllowed);
+        let server = TestServer::start(|_| {
+            TestResponse::status(200).with_header("Content-Length", "115343400")
+        });
+        let url = server.url("/large");
+        let result = read_url(
+            &url,
+            &sample_environment(),
+            &ResourcePolicy::default(),
+            None,
+        );
         assert!(result.is_err(), "Unexpected success: {:?}", result);
         let error = format!("{:#}", result.unwrap_err());
-        assert_eq!(error, "https://eu.httpbin.org/response-headers?content-length=115343400 reports size 115343400 which exceeds limit 104857600, refusing to read")
+        assert_eq!(
+            error,
+            format!(
+                "{} reports size 115343400 which exceeds limit 104857600, refusing to read",
+                url
+            )
+        )
+    }
+
+    #[test]
+    fn read_url_with_http_url_follows_redirects() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/moved" => TestResponse::redirect("/image.png"),
+            _ => TestResponse::ok("image/png", b"png".to_vec()),
+        });
+        let resource = read_url(
+            &server.url("/moved"),
+            &sample_environment(),
+            &ResourcePolicy::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(resource.contents, b"png");
+        assert_eq!(resource.mime_type_hint, Some(mime::IMAGE_PNG));
+    }
+
+    #[test]
+    fn read_url_with_http_url_checks_redirect_targets() {
+        let denied = TestServer::start(|_| TestResponse::ok("image/png", b"png".to_vec()));
+        let mut target = denied.url("/image.png");
+        target.set_host(Some("localhost")).unwrap();
+        let location = target.to_string();
+        let server = TestServer::start(move |_| TestResponse::redirect(&location));
+        let policy = ResourcePolicy {
+            denied_hosts: vec!["localhost".to_string()],
+            ..ResourcePolicy::default()
+        };
+        let error =
+            read_url(&server.url("/moved"), &sample_environment(), &policy, None).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("Access denied to URL {}: host localhost denied", target)
+        );
+        assert_eq!(server.requests().len(), 1);
+        assert!(denied.requests().is_empty());
+    }
+
+    #[test]
+    fn read_url_revalidates_cached_http_responses() {
+        let server = TestServer::start(|request| match request.header("if-none-match") {
+            Some("\"v1\"") => TestResponse::status(304),
+            _ => TestResponse::ok("image/png", b"png".to_vec()).with_header("ETag", "\"v1\""),
+        });
+        let directory = std::env::temp_dir().join(format!(
+            "mdcat-resources-test-revalidate-{}",
+            std::process::id()
+        ));
+        let cache = Cache::new(directory);
+        let url = server.url("/image.png");
+        let environment = sample_environment();
+        let policy = ResourcePolicy::default();
+        let first = read_url(&url, &environment, &policy, Some(&cache)).unwrap();
+        let second = read_url(&url, &environment, &policy, Some(&cache)).unwrap();
+        cache.clear().unwrap();
+        assert_eq!(first.contents, b"png");
+        assert_eq!(second.contents, b"png");
+        assert_eq!(second.mime_type_hint, Some(mime::IMAGE_PNG));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].header("if-none-match"), None);
+        assert_eq!(requests[1].header("if-none-match"), Some("\"v1\""));
+    }
+
+    #[test]
+    fn read_url_applies_size_limit_to_cached_http_responses() {
+        let server = TestServer::start(|request| match request.header("if-none-match") {
+            Some("\"v1\"") => TestResponse::status(304),
+            _ => TestResponse::ok("image/png", vec![42; 100]).with_header("ETag", "\"v1\""),
+        });
+        let directory = std::env::temp_dir().join(format!(
+            "mdcat-resources-test-cached-size-limit-{}",
+            std::process::id()
+        ));
+        let cache = Cache::new(directory);
+        let url = server.url("/image.png");
+        let environment = sample_environment();
+        let first = read_url(&url, &environment, &ResourcePolicy::default(), Some(&cache));
+        let strict = ResourcePolicy {
+            size_limit: 10,
+            ..ResourcePolicy::default()
+        };
+        let second = read_url(&url, &environment, &strict, Some(&cache));
+        cache.clear().unwrap();
+        assert_eq!(first.unwrap().contents.len(), 100);
+        let error = format!("{:#}", second.unwrap_err());
+        assert!(error.contains("exceeds limit 10"), "{}", error);
+        // Do not revalidate cached contents which exceed the limit
+        assert_eq!(server.requests()[1].header("if-none-match"), None);
     }
 }
@@ -0,0 +1,208 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Render to lines of styled spans.
+//!
+//! This lets terminal UI toolkits show Markdown rendered by mdcat in their own
+//! widgets.
+
+use std::io::Result;
+
+use ansi_term::Style;
+use fehler::throws;
+use pulldown_cmark::Event;
+use url::Url;
+
+use crate::renderer::Renderer;
+use crate::{push_events, Environment, Error, Settings};
+
+/// A piece of text with a style.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    /// The text of this span.
+    ///
+    /// Never contains newlines.
+    pub text: String,
+    /// The style of this span, i.e. foreground and background colour, and
+    /// attributes.
+    ///
+    /// Colours are exactly as given in the stylesheet; a renderer should
+    /// reduce them to the colours it supports.
+    pub style: Style,
+    /// The URL this span links to, if any.
+    pub link: Option<Url>,
+}
+
+/// A line of styled spans.
+pub type Line = Vec<Span>;
+
+/// A renderer which collects lines of styled spans.
+#[derive(Debug, Default)]
+pub struct SpanRenderer {
+    lines: Vec<Line>,
+    current_line: Line,
+    link: Option<Url>,
+}
+
+impl SpanRenderer {
+    /// Create a new renderer without any lines.
+    pub fn new() -> Self {
+        SpanRenderer::default()
+    }
+
+    /// Get all lines rendered so far.
+    pub fn into_lines(mut self) -> Vec<Line> {
+        if !self.current_line.is_empty() {
+            self.lines.push(self.current_line);
+        }
+        self.lines
+    }
+
+    fn push_span(&mut self, style: &Style, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        match self.current_line.last_mut() {
+            Some(span) if span.style == *style && span.link == self.link => {
+                span.text.push_str(text)
+            }
+            _ => self.current_line.push(Span {
+                text: text.to_string(),
+                style: *style,
+                link: self.link.clone(),
+            }),
+        }
+    }
+}
+
+impl Renderer for SpanRenderer {
+    fn write_styled(&mut self, style: &Style, text: &str) -> Result<()> {
+        let mut lines = text.split('\n');
+        if let Some(first) = lines.next() {
+            self.push_span(style, first);
+        }
+        for line in lines {
+            let finished = std::mem::take(&mut self.current_line);
+            self.lines.push(finished);
+            self.push_span(style, line);
+        }
+        Ok(())
+    }
+
+    fn start_link(&mut self, url: &Url) -> Result<()> {
+        self.link = Some(url.clone());
+        Ok(())
+    }
+
+    fn end_link(&mut self) -> Result<()> {
+        self.link = None;
+        Ok(())
+    }
+}
+
+/// Render markdown `events` to lines of styled spans.
+///
+/// Lay out `events` like [`crate::push_tty`] would, using the given `settings`
+/// and `environment` for rendering and resource access, and return the
+/// rendered lines.
+///
+/// Use terminal capabilities with styles in `settings`, e.g.
+/// [`crate::TerminalCapabilities::ansi`], to get styled spans and highlighted
+/// code, and capabilities with links, e.g.
+/// [`crate::TerminalCapabilities::vte50`], to link spans to URLs instead of
+/// writing reference links.  Spans cannot show images, so images appear as
+/// their description.
+#[throws(Error)]
+pub fn render_spans<'e, I>(settings: &Settings, environment: &Environment, events: I) -> Vec<Line>
+where
+    I: Iterator<Item = Event<'e>>,
+{
+    let mut renderer = SpanRenderer::new();
+    push_events(settings, environment, &mut renderer, events)?;
+    renderer.into_lines()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_settings;
+    use crate::{TerminalCapabilities, TerminalSize};
+    use ansi_term::Colour;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::Parser;
+
+    fn span(text: &str, style: Style, link: Option<&str>) -> Span {
+        Span {
+            text: text.to_string(),
+            style,
+            link: link.map(|url| Url::parse(url).unwrap()),
+        }
+    }
+
+    fn render(markup: &str, columns: usize) -> Vec<Line> {
+        let env = Environment::for_localhost(Url::parse("http://example.com/").unwrap()).unwrap();
+        let settings = Settings {
+            terminal_size: TerminalSize {
+                columns,
+                ..TerminalSize::default()
+            },
+            ..test_settings(TerminalCapabilities::vte50())
+        };
+        render_spans(&settings, &env, Parser::new(markup)).unwrap()
+    }
+
+    #[test]
+    fn split_lines_and_merge_spans() {
+        let mut renderer = SpanRenderer::new();
+        let bold = Style::new().bold();
+        renderer.write_styled(&bold, "foo").unwrap();
+        renderer.write_styled(&bold, " bar\n").unwrap();
+        renderer.write_styled(&Style::new(), "\nbaz").unwrap();
+        assert_eq!(
+            renderer.into_lines(),
+            vec![
+                vec![span("foo bar", bold, None)],
+                vec![],
+                vec![span("baz", Style::new(), None)]
+            ]
+        );
+    }
+
+    #[test]
+    fn link_spans() {
+        let mut renderer = SpanRenderer::new();
+        renderer.write_styled(&Style::new(), "a ").unwrap();
+        renderer
+            .start_link(&Url::parse("http://example.com").unwrap())
+            .unwrap();
+        renderer.write_styled(&Style::new(), "link").unwrap();
+        renderer.end_link().unwrap();
+        assert_eq!(
+            renderer.into_lines(),
+            vec![vec![
+                span("a ", Style::new(), None),
+                span("link", Style::new(), Some("http://example.com")),
+            ]]
+        );
+    }
+
+    #[test]
+    fn render_markdown_to_spans() {
+        let blue = Style::new().fg(Colour::Blue);
+        assert_eq!(
+            render("*Hello* [world](world.html), this wraps.", 20),
+            vec![
+                vec![
+                    span("Hello", Style::new().italic(), None),
+                    span(" ", Style::new(), None),
+                    span("world", blue, Some("http://example.com/world.html")),
+                    span(", this", Style::new(), None),
+                ],
+                vec![span("wraps.", Style::new(), None)],
+            ]
+        );
+    }
+}
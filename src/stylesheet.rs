@@ -0,0 +1,548 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Stylesheets for rendering.
+
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use ansi_term::{Colour, Style};
+use fehler::throws;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The style and prefix of a heading.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeadingStyle {
+    /// The text to write in front of the heading.
+    #[serde(default)]
+    pub prefix: String,
+    /// The style of the heading, including the prefix.
+    #[serde(default, with = "style_serde")]
+    pub style: Style,
+}
+
+impl HeadingStyle {
+    /// The default style of a heading at `level`.
+    ///
+    /// Blue and bold, with a dashed line per level in front.
+    pub fn default_for_level(level: u32) -> HeadingStyle {
+        HeadingStyle {
+            prefix: "\u{2504}".repeat(level as usize),
+            style: Style::new().fg(Colour::Blue).bold(),
+        }
+    }
+}
+
+/// A glyph with a style, e.g. a bullet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GlyphStyle {
+    /// The glyph.
+    pub glyph: String,
+    /// The style of the glyph.
+    #[serde(default, with = "style_serde")]
+    pub style: Style,
+}
+
+impl GlyphStyle {
+    fn new<S: Into<String>>(glyph: S, style: Style) -> GlyphStyle {
+        GlyphStyle {
+            glyph: glyph.into(),
+            style,
+        }
+    }
+}
+
+/// Glyphs to draw table borders with.
+///
+/// Each corner and crossing is a list of the glyphs at the left, in the
+/// middle, and at the right of a table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TableBorders {
+    /// The glyph of horizontal borders.
+    pub horizontal: String,
+    /// The glyph of vertical borders.
+    pub vertical: String,
+    /// The glyphs of the top border.
+    pub top: [String; 3],
+    /// The glyphs of the border between the header and the rows.
+    pub middle: [String; 3],
+    /// The glyphs of the bottom border.
+    pub bottom: [String; 3],
+}
+
+impl TableBorders {
+    fn new(horizontal: &str, vertical: &str, corners: [[&str; 3]; 3]) -> TableBorders {
+        let [top, middle, bottom] = corners;
+        TableBorders {
+            horizontal: horizontal.to_string(),
+            vertical: vertical.to_string(),
+            top: top.map(str::to_string),
+            middle: middle.map(str::to_string),
+            bottom: bottom.map(str::to_string),
+        }
+    }
+
+    /// Borders drawn with plain ASCII characters.
+    pub fn ascii() -> TableBorders {
+        Self::new(
+            "-",
+            "|",
+            [["+", "+", "+"], ["+", "+", "+"], ["+", "+", "+"]],
+        )
+    }
+}
+
+impl Default for TableBorders {
+    /// Borders drawn with box-drawing characters.
+    fn default() -> Self {
+        Self::new(
+            "\u{2500}",
+            "\u{2502}",
+            [
+                ["\u{250c}", "\u{252c}", "\u{2510}"],
+                ["\u{251c}", "\u{253c}", "\u{2524}"],
+                ["\u{2514}", "\u{2534}", "\u{2518}"],
+            ],
+        )
+    }
+}
+
+/// A stylesheet for rendering.
+///
+/// Control the colours, text attributes and glyphs mdcat uses for the
+/// elements of a Markdown document.
+///
+/// Styles of inline elements such as links or code apply on top of the style
+/// of the surrounding text: Colours in these styles replace the colours of the
+/// surrounding text, and text attributes add to the attributes of the
+/// surrounding text.
+///
+/// See [`Stylesheet::from_toml`] for the TOML format of stylesheets.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Stylesheet {
+    /// Styles for headings, starting at level 1.
+    ///
+    /// Headings at deeper levels use the last style.
+    pub headings: Vec<HeadingStyle>,
+    /// The style of link text and link references.
+    #[serde(with = "style_serde")]
+    pub link: Style,
+    /// The style of image titles and image references.
+    #[serde(with = "style_serde")]
+    pub image: Style,
+    /// The style of inline code and code blocks without highlighting.
+    #[serde(with = "style_serde")]
+    pub code: Style,
+    /// The style of HTML.
+    #[serde(with = "style_serde")]
+    pub html: Style,
+    /// The style of block quotes.
+    #[serde(with = "style_serde")]
+    pub quote: Style,
+    /// The style of footnote references and footnote markers.
+    #[serde(with = "style_serde")]
+    pub footnote: Style,
+    /// Bullets of unordered lists, starting at the outermost list.
+    ///
+    /// Nested lists deeper than the number of bullets start over at the first
+    /// bullet.
+    pub bullets: Vec<GlyphStyle>,
+    /// The glyph and style of horizontal rules.
+    pub rule: GlyphStyle,
+    /// The glyph and style of borders around code blocks.
+    ///
+    /// Tables use the style of this border.
+    pub border: GlyphStyle,
+    /// The glyphs of table borders.
+    ///
+    /// Terminals without any styling use [`TableBorders::ascii`] instead.
+    pub table_borders: TableBorders,
+}
+
+impl Default for Stylesheet {
+    fn default() -> Self {
+        Stylesheet {
+            headings: (1..=6).map(HeadingStyle::default_for_level).collect(),
+            link: Style::new().fg(Colour::Blue),
+            image: Style::new().fg(Colour::Purple),
+            code: Style::new().fg(Colour::Yellow),
+            html: Style::new().fg(Colour::Green),
+            quote: Style::new().italic(),
+            footnote: Style::new().fg(Colour::Blue),
+            bullets: vec![GlyphStyle::new("\u{2022}", Style::new())],
+            rule: GlyphStyle::new("\u{2550}", Style::new().fg(Colour::Green)),
+            border: GlyphStyle::new("\u{2500}", Style::new().fg(Colour::Green)),
+            table_borders: TableBorders::default(),
+        }
+    }
+}
+
+impl Stylesheet {
+    /// Parse a stylesheet from TOML.
+    ///
+    /// All keys are optional; missing keys keep their default style.  A
+    /// style is a table with the optional keys `foreground` and `background`
+    /// for colours, and `bold`, `dimmed`, `italic`, `underline` and
+    /// `strikethrough` for text attributes.  A colour is the name of a basic
+    /// ANSI colour (e.g. `blue` or `bright-blue`), an index into the 256
+    /// colour palette (e.g. `"208"`), or a RGB colour (e.g. `"#ff8700"`).
+    ///
+    /// ```toml
+    /// link = { foreground = "cyan", underline = true }
+    /// rule = { glyph = "-", style = { foreground = "#808080" } }
+    ///
+    /// [[headings]]
+    /// prefix = "# "
+    /// style = { foreground = "red", bold = true }
+    ///
+    /// [[bullets]]
+    /// glyph = "*"
+    ///
+    /// [table_borders]
+    /// horizontal = "="
+    /// top = ["+", "+", "+"]
+    /// ```
+    ///
+    /// Fail with `ErrorKind::InvalidData` if `toml` is no valid stylesheet.
+    #[throws]
+    pub fn from_toml(toml: &str) -> Stylesheet {
+        toml::from_str(toml).map_err(|error| Error::new(ErrorKind::InvalidData, error))?
+    }
+
+    /// Load a stylesheet from a TOML file at `path`.
+    ///
+    /// See [`Stylesheet::from_toml`].
+    #[throws]
+    pub fn load<P: AsRef<Path>>(path: P) -> Stylesheet {
+        let toml = std::fs::read_to_string(path.as_ref())?;
+        Self::from_toml(&toml).map_err(|error| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: {}", path.as_ref().display(), error),
+            )
+        })?
+    }
+
+    /// Get the style for a heading at `level`.
+    pub fn heading(&self, level: u32) -> HeadingStyle {
+        if self.headings.is_empty() {
+            HeadingStyle::default_for_level(level)
+        } else {
+            let index = (level as usize).max(1).min(self.headings.len()) - 1;
+            self.headings[index].clone()
+        }
+    }
+
+    /// Get the bullet for unordered lists at the given nesting `level`.
+    ///
+    /// The outermost list has level 0.
+    pub fn bullet(&self, level: usize) -> GlyphStyle {
+        if self.bullets.is_empty() {
+            GlyphStyle::new("\u{2022}", Style::new())
+        } else {
+            self.bullets[level % self.bullets.len()].clone()
+        }
+    }
+}
+
+/// Apply `style` on top of a `base` style.
+///
+/// Take colours from `style` if set, and add text attributes of `style` to
+/// `base`.
+pub(crate) fn apply_style(base: Style, style: &Style) -> Style {
+    Style {
+        foreground: style.foreground.or(base.foreground),
+        background: style.background.or(base.background),
+        is_bold: base.is_bold || style.is_bold,
+        is_dimmed: base.is_dimmed || style.is_dimmed,
+        is_italic: base.is_italic || style.is_italic,
+        is_underline: base.is_underline || style.is_underline,
+        is_blink: base.is_blink || style.is_blink,
+        is_reverse: base.is_reverse || style.is_reverse,
+        is_hidden: base.is_hidden || style.is_hidden,
+        is_strikethrough: base.is_strikethrough || style.is_strikethrough,
+    }
+}
+
+const COLOUR_NAMES: [(&str, Colour); 16] = [
+    ("black", Colour::Black),
+    ("red", Colour::Red),
+    ("green", Colour::Green),
+    ("yellow", Colour::Yellow),
+    ("blue", Colour::Blue),
+    ("purple", Colour::Purple),
+    ("cyan", Colour::Cyan),
+    ("white", Colour::White),
+    ("bright-black", Colour::Fixed(8)),
+    ("bright-red", Colour::Fixed(9)),
+    ("bright-green", Colour::Fixed(10)),
+    ("bright-yellow", Colour::Fixed(11)),
+    ("bright-blue", Colour::Fixed(12)),
+    ("bright-purple", Colour::Fixed(13)),
+    ("bright-cyan", Colour::Fixed(14)),
+    ("bright-white", Colour::Fixed(15)),
+];
+
+/// A colour in a stylesheet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ColourSpec(Colour);
+
+impl TryFrom<String> for ColourSpec {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let name = value.to_lowercase().replace("magenta", "purple");
+        if let Some((_, colour)) = COLOUR_NAMES.iter().find(|(n, _)| *n == name) {
+            Ok(ColourSpec(*colour))
+        } else if let Ok(index) = value.parse::<u8>() {
+            Ok(ColourSpec(Colour::Fixed(index)))
+        } else if value.len() == 7 && value.starts_with('#') && value.is_ascii() {
+            let component = |i: usize| u8::from_str_radix(&value[i..i + 2], 16);
+            match (component(1), component(3), component(5)) {
+                (Ok(r), Ok(g), Ok(b)) => Ok(ColourSpec(Colour::RGB(r, g, b))),
+                _ => Err(format!("Invalid RGB colour: {}", value)),
+            }
+        } else {
+            Err(format!("Invalid colour: {}", value))
+        }
+    }
+}
+
+impl From<ColourSpec> for String {
+    fn from(spec: ColourSpec) -> Self {
+        match spec.0 {
+            Colour::RGB(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+            colour => COLOUR_NAMES
+                .iter()
+                .find(|(_, c)| *c == colour)
+                .map(|(name, _)| name.to_string())
+                .unwrap_or_else(|| match colour {
+                    Colour::Fixed(index) => index.to_string(),
+                    // All other colours have a name
+                    _ => unreachable!(),
+                }),
+        }
+    }
+}
+
+impl Serialize for ColourSpec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&String::from(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for ColourSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        ColourSpec::try_from(value).map_err(serde::de::Error::custom)
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// A style in a stylesheet.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct StyleSpec {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    foreground: Option<ColourSpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    background: Option<ColourSpec>,
+    #[serde(skip_serializing_if = "is_false")]
+    bold: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    dimmed: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    italic: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    underline: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    strikethrough: bool,
+}
+
+impl From<StyleSpec> for Style {
+    fn from(spec: StyleSpec) -> Self {
+        Style {
+            foreground: spec.foreground.map(|c| c.0),
+            background: spec.background.map(|c| c.0),
+            is_bold: spec.bold,
+            is_dimmed: spec.dimmed,
+            is_italic: spec.italic,
+            is_underline: spec.underline,
+            is_strikethrough: spec.strikethrough,
+            ..Style::new()
+        }
+    }
+}
+
+impl From<&Style> for StyleSpec {
+    fn from(style: &Style) -> Self {
+        StyleSpec {
+            foreground: style.foreground.map(ColourSpec),
+            background: style.background.map(ColourSpec),
+            bold: style.is_bold,
+            dimmed: style.is_dimmed,
+            italic: style.is_italic,
+            underline: style.is_underline,
+            strikethrough: style.is_strikethrough,
+        }
+    }
+}
+
+/// Serialize ANSI styles as style specs.
+mod style_serde {
+    use super::StyleSpec;
+    use ansi_term::Style;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(style: &Style, serializer: S) -> Result<S::Ok, S::Error> {
+        StyleSpec::from(style).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Style, D::Error> {
+        StyleSpec::deserialize(deserializer).map(Style::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn empty_toml_is_default() {
+        assert_eq!(Stylesheet::from_toml("").unwrap(), Stylesheet::default());
+    }
+
+    #[test]
+    fn parse_styles() {
+        let stylesheet = Stylesheet::from_toml(
+            r##"
+link = { foreground = "bright-cyan", underline = true }
+code = { foreground = "208", background = "#102030" }
+rule = { glyph = "-", style = { foreground = "magenta" } }
+
+[[headings]]
+prefix = "# "
+style = { foreground = "red", bold = true }
+
+[[bullets]]
+glyph = "*"
+
+[[bullets]]
+glyph = "+"
+style = { dimmed = true }
+"##,
+        )
+        .unwrap();
+        assert_eq!(
+            stylesheet.link,
+            Style::new().fg(Colour::Fixed(14)).underline()
+        );
+        assert_eq!(
+            stylesheet.code,
+            Style::new()
+                .fg(Colour::Fixed(208))
+                .on(Colour::RGB(0x10, 0x20, 0x30))
+        );
+        assert_eq!(
+            stylesheet.rule,
+            GlyphStyle::new("-", Style::new().fg(Colour::Purple))
+        );
+        assert_eq!(
+            stylesheet.heading(3),
+            HeadingStyle {
+                prefix: "# ".to_string(),
+                style: Style::new().fg(Colour::Red).bold()
+            }
+        );
+        assert_eq!(stylesheet.bullet(0), GlyphStyle::new("*", Style::new()));
+        assert_eq!(
+            stylesheet.bullet(3),
+            GlyphStyle::new("+", Style::new().dimmed())
+        );
+        // Other styles keep their defaults
+        assert_eq!(stylesheet.image, Stylesheet::default().image);
+    }
+
+    #[test]
+    fn parse_table_borders() {
+        let stylesheet = Stylesheet::from_toml(
+            r##"
+[table_borders]
+horizontal = "="
+top = ["+", "+", "+"]
+"##,
+        )
+        .unwrap();
+        let borders = stylesheet.table_borders;
+        assert_eq!(borders.horizontal, "=");
+        assert_eq!(borders.top, ["+", "+", "+"].map(str::to_string));
+        // Other glyphs keep their defaults
+        assert_eq!(borders.vertical, TableBorders::default().vertical);
+        assert_eq!(borders.bottom, TableBorders::default().bottom);
+    }
+
+    #[test]
+    fn reject_invalid_colours() {
+        let error = Stylesheet::from_toml("link = { foreground = \"#12345\" }").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("Invalid colour"), "{}", error);
+    }
+
+    #[test]
+    fn reject_unknown_keys() {
+        let error = Stylesheet::from_toml("links = { foreground = \"red\" }").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn roundtrip_through_toml() {
+        let stylesheet = Stylesheet {
+            code: Style::new()
+                .fg(Colour::RGB(0xff, 0x87, 0x00))
+                .on(Colour::Fixed(236)),
+            ..Stylesheet::default()
+        };
+        let toml = toml::to_string(&stylesheet).unwrap();
+        assert_eq!(Stylesheet::from_toml(&toml).unwrap(), stylesheet);
+    }
+
+    #[test]
+    fn heading_styles_for_deeper_levels() {
+        let stylesheet = Stylesheet::default();
+        assert_eq!(stylesheet.heading(1).prefix, "\u{2504}");
+        assert_eq!(stylesheet.heading(6).prefix, "\u{2504}".repeat(6));
+        assert_eq!(stylesheet.heading(7).prefix, "\u{2504}".repeat(6));
+    }
+
+    #[test]
+    fn default_heading_styles_without_headings() {
+        let stylesheet = Stylesheet::from_toml("headings = []").unwrap();
+        assert_eq!(stylesheet.heading(0), HeadingStyle::default_for_level(0));
+        assert_eq!(stylesheet.heading(2), HeadingStyle::default_for_level(2));
+    }
+
+    #[test]
+    fn apply_style_on_top() {
+        let base = Style::new().fg(Colour::Red).italic();
+        assert_eq!(
+            apply_style(base, &Style::new().fg(Colour::Blue).bold()),
+            Style::new().fg(Colour::Blue).italic().bold()
+        );
+        assert_eq!(
+            apply_style(base, &Style::new().underline()),
+            base.underline()
+        );
+    }
+}
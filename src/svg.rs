@@ -5,16 +5,77 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 //! SVG "rendering" for mdcat.
+//!
+//! With the `resvg` feature render SVG images in process, and fall back to
+//! `rsvg-convert` if resvg fails to render an image.  Without the feature
+//! always use `rsvg-convert`.
 
 use std::io::prelude::*;
-use std::io::{Error, ErrorKind, Result};
+use std::io::{Error, Result};
 use std::process::{Command, Stdio};
 
+use crate::terminal::size::PixelSize;
+
 /// Render an SVG image to a PNG pixel graphic for display.
-pub fn render_svg(svg: &[u8]) -> Result<Vec<u8>> {
+///
+/// Render the image at its own size, but if the image exceeds `size` in
+/// either dimension scale it down to fit into `size` (preserving aspect ratio).
+/// Without a `size` render the image at its own size.
+#[cfg(feature = "resvg")]
+pub fn render_svg(svg: &[u8], size: Option<PixelSize>) -> Result<Vec<u8>> {
+    render_svg_with_resvg(svg, size).or_else(|error| {
+        render_svg_with_rsvg_convert(svg).map_err(|fallback_error| {
+            Error::other(format!(
+                "{}; fallback to rsvg-convert failed: {}",
+                error, fallback_error
+            ))
+        })
+    })
+}
+
+/// Render an SVG image to a PNG pixel graphic for display.
+///
+/// `rsvg-convert` renders the image at its own size, regardless of `size`.
+#[cfg(not(feature = "resvg"))]
+pub fn render_svg(svg: &[u8], _size: Option<PixelSize>) -> Result<Vec<u8>> {
     render_svg_with_rsvg_convert(svg)
 }
 
+/// The scale factor to fit an image of `width` and `height` into `size`.
+///
+/// Never scale up, i.e. return 1 if the image already fits.
+#[cfg(feature = "resvg")]
+fn scale_to_fit(width: f32, height: f32, size: Option<PixelSize>) -> f32 {
+    size.map_or(1.0, |size| {
+        (size.x as f32 / width).min(size.y as f32 / height).min(1.0)
+    })
+}
+
+/// Render an SVG image with resvg.
+#[cfg(feature = "resvg")]
+fn render_svg_with_resvg(svg: &[u8], size: Option<PixelSize>) -> Result<Vec<u8>> {
+    use resvg::{tiny_skia, usvg};
+
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = usvg::Tree::from_data(svg, &options)
+        .map_err(|error| Error::other(format!("Failed to parse SVG: {}", error)))?;
+    let tree_size = tree.size();
+    let scale = scale_to_fit(tree_size.width(), tree_size.height(), size);
+    let width = (tree_size.width() * scale).round().max(1.0) as u32;
+    let height = (tree_size.height() * scale).round().max(1.0) as u32;
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| Error::other(format!("Invalid size for SVG image: {}x{}", width, height)))?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    pixmap
+        .encode_png()
+        .map_err(|error| Error::other(format!("Failed to encode SVG as PNG: {}", error)))
+}
+
 /// Render an SVG file with `rsvg-convert`.
 fn render_svg_with_rsvg_convert(svg: &[u8]) -> Result<Vec<u8>> {
     let mut process = Command::new("rsvg-convert")
@@ -36,13 +97,51 @@ fn render_svg_with_rsvg_convert(svg: &[u8]) -> Result<Vec<u8>> {
     if output.status.success() {
         Ok(output.stdout)
     } else {
-        Err(Error::new(
-            ErrorKind::Other,
-            format!(
-                "rsvg-convert failed with status {}: {}",
-                output.status,
-                String::from_utf8_lossy(&output.stderr)
-            ),
-        ))
+        Err(Error::other(format!(
+            "rsvg-convert failed with status {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        )))
+    }
+}
+
+#[cfg(all(test, feature = "resvg"))]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn scale_down_to_fit() {
+        assert_eq!(scale_to_fit(200.0, 100.0, None), 1.0);
+        assert_eq!(
+            scale_to_fit(200.0, 100.0, Some(PixelSize::from_xy((400, 400)))),
+            1.0
+        );
+        assert_eq!(
+            scale_to_fit(200.0, 100.0, Some(PixelSize::from_xy((100, 400)))),
+            0.5
+        );
+        assert_eq!(
+            scale_to_fit(200.0, 100.0, Some(PixelSize::from_xy((400, 25)))),
+            0.25
+        );
+    }
+
+    #[test]
+    fn render_with_resvg() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="40" height="20">
+<rect width="40" height="20" fill="red"/>
+</svg>"#;
+        let png = render_svg_with_resvg(svg, Some(PixelSize::from_xy((20, 20)))).unwrap();
+        let image = image::load_from_memory(&png).unwrap().into_rgba8();
+        assert_eq!(image.dimensions(), (20, 10));
+        assert_eq!(image.get_pixel(10, 5).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn render_sample_logo() {
+        let svg = std::fs::read("sample/rust-logo.svg").unwrap();
+        let png = render_svg(&svg, None).unwrap();
+        assert!(image::load_from_memory(&png).is_ok());
     }
 }
@@ -10,14 +10,19 @@
 
 mod ansi;
 pub mod highlighting;
-mod size;
+pub(crate) mod size;
 
+mod halfblock;
 mod iterm2;
 mod kitty;
 mod osc;
+mod renderer;
+mod sixel;
 mod terminology;
 
-pub use self::ansi::AnsiStyle;
+pub(crate) use self::ansi::colour_to_rgb;
+pub use self::ansi::{AnsiStyle, ColourLevel};
+pub use self::renderer::TerminalRenderer;
 pub use self::size::TerminalSize;
 
 /// The capability of basic styling.
@@ -52,6 +57,37 @@ pub enum ImageCapability {
     ITerm2(self::iterm2::ITerm2Images),
     /// The terminal understands the Kitty way of inline images.
     Kitty(self::kitty::KittyImages),
+    /// The terminal understands Sixel graphics.
+    Sixel(self::sixel::SixelImages),
+    /// Draw images with Unicode half blocks, for any terminal with colours.
+    HalfBlocks(self::halfblock::HalfBlockImages),
+}
+
+impl ImageCapability {
+    /// Inline images the Terminology way.
+    pub fn terminology() -> ImageCapability {
+        ImageCapability::Terminology(self::terminology::TerminologyImages)
+    }
+
+    /// Inline images the iTerm2 way.
+    pub fn iterm2() -> ImageCapability {
+        ImageCapability::ITerm2(self::iterm2::ITerm2Images)
+    }
+
+    /// Inline images the Kitty way.
+    pub fn kitty() -> ImageCapability {
+        ImageCapability::Kitty(self::kitty::KittyImages)
+    }
+
+    /// Inline images with Sixel graphics.
+    pub fn sixel() -> ImageCapability {
+        ImageCapability::Sixel(self::sixel::SixelImages)
+    }
+
+    /// Draw images with Unicode half blocks.
+    pub fn half_blocks() -> ImageCapability {
+        ImageCapability::HalfBlocks(self::halfblock::HalfBlockImages)
+    }
 }
 
 /// The capabilities of a terminal.
@@ -82,7 +118,7 @@ fn get_vte_version() -> Option<(u8, u8)> {
 
 /// Checks if the current terminal is WezTerm.
 fn is_wezterm() -> bool {
-    std::env::var("TERM_PROGRAM").map_or(false, |value| value == "WezTerm")
+    std::env::var("TERM_PROGRAM").is_ok_and(|value| value == "WezTerm")
 }
 
 impl TerminalCapabilities {
@@ -98,10 +134,12 @@ impl TerminalCapabilities {
     }
 
     /// A terminal with basic ANSI formatting only.
+    ///
+    /// Assume that the terminal supports the basic 16 colours only.
     pub fn ansi() -> TerminalCapabilities {
         TerminalCapabilities {
             name: "Ansi".to_string(),
-            style: Some(StyleCapability::Ansi(AnsiStyle)),
+            style: Some(StyleCapability::Ansi(AnsiStyle::new(ColourLevel::Ansi16))),
             links: None,
             image: None,
             marks: None,
@@ -112,7 +150,9 @@ impl TerminalCapabilities {
     pub fn iterm2() -> TerminalCapabilities {
         TerminalCapabilities {
             name: "iTerm2".to_string(),
-            style: Some(StyleCapability::Ansi(AnsiStyle)),
+            style: Some(StyleCapability::Ansi(AnsiStyle::new(
+                ColourLevel::TrueColour,
+            ))),
             links: Some(LinkCapability::Osc8(self::osc::Osc8Links)),
             image: Some(ImageCapability::ITerm2(self::iterm2::ITerm2Images)),
             marks: Some(MarkCapability::ITerm2(self::iterm2::ITerm2Marks)),
@@ -123,7 +163,7 @@ impl TerminalCapabilities {
     pub fn terminology() -> TerminalCapabilities {
         TerminalCapabilities {
             name: "Terminology".to_string(),
-            style: Some(StyleCapability::Ansi(AnsiStyle)),
+            style: Some(StyleCapability::Ansi(AnsiStyle::new(ColourLevel::Ansi256))),
             links: Some(LinkCapability::Osc8(self::osc::Osc8Links)),
             image: Some(ImageCapability::Terminology(
                 self::terminology::TerminologyImages,
@@ -136,7 +176,9 @@ impl TerminalCapabilities {
     pub fn kitty() -> TerminalCapabilities {
         TerminalCapabilities {
             name: "Kitty".to_string(),
-            style: Some(StyleCapability::Ansi(AnsiStyle)),
+            style: Some(StyleCapability::Ansi(AnsiStyle::new(
+                ColourLevel::TrueColour,
+            ))),
             links: Some(LinkCapability::Osc8(self::osc::Osc8Links)),
             image: Some(ImageCapability::Kitty(self::kitty::KittyImages)),
             marks: None,
@@ -147,7 +189,9 @@ impl TerminalCapabilities {
     pub fn vte50() -> TerminalCapabilities {
         TerminalCapabilities {
             name: "VTE 50".to_string(),
-            style: Some(StyleCapability::Ansi(AnsiStyle)),
+            style: Some(StyleCapability::Ansi(AnsiStyle::new(
+                ColourLevel::TrueColour,
+            ))),
             links: Some(LinkCapability::Osc8(self::osc::Osc8Links)),
             image: None,
             marks: None,
@@ -164,15 +208,88 @@ impl TerminalCapabilities {
     pub fn wezterm() -> TerminalCapabilities {
         TerminalCapabilities {
             name: "WezTerm".to_string(),
-            style: Some(StyleCapability::Ansi(AnsiStyle)),
+            style: Some(StyleCapability::Ansi(AnsiStyle::new(
+                ColourLevel::TrueColour,
+            ))),
             links: Some(LinkCapability::Osc8(self::osc::Osc8Links)),
             image: Some(ImageCapability::ITerm2(self::iterm2::ITerm2Images)),
             marks: None,
         }
     }
 
+    /// Capabilities for HTML output with [`crate::HtmlRenderer`].
+    ///
+    /// HTML shows all colours and inline links.
+    pub fn html() -> TerminalCapabilities {
+        TerminalCapabilities {
+            name: "HTML".to_string(),
+            style: Some(StyleCapability::Ansi(AnsiStyle::new(
+                ColourLevel::TrueColour,
+            ))),
+            links: Some(LinkCapability::Osc8(self::osc::Osc8Links)),
+            image: None,
+            marks: None,
+        }
+    }
+
+    /// Use the given level of `colours` for styling.
+    ///
+    /// Has no effect if the terminal does not support styling.
+    pub fn with_colours(self, colours: ColourLevel) -> TerminalCapabilities {
+        TerminalCapabilities {
+            style: self
+                .style
+                .map(|StyleCapability::Ansi(_)| StyleCapability::Ansi(AnsiStyle::new(colours))),
+            ..self
+        }
+    }
+
+    /// Use the given `image` capability for inline images.
+    ///
+    /// Disable inline images if `image` is `None`.
+    pub fn with_image(self, image: Option<ImageCapability>) -> TerminalCapabilities {
+        TerminalCapabilities { image, ..self }
+    }
+
     /// Detect the capabilities of the current terminal.
+    ///
+    /// Detect the terminal first, and then take the colours from the
+    /// environment if it indicates more colours than the terminal supports by
+    /// default, see [`ColourLevel::detect`].
+    ///
+    /// Only look at the environment; see [`TerminalCapabilities::detect_sixel`]
+    /// to ask the terminal itself whether it supports Sixel graphics.
     pub fn detect() -> TerminalCapabilities {
+        let terminal = Self::detect_terminal();
+        match (terminal.style, ColourLevel::detect()) {
+            (Some(StyleCapability::Ansi(ansi)), Some(colours)) if ansi.colours() < colours => {
+                terminal.with_colours(colours)
+            }
+            _ => terminal,
+        }
+    }
+
+    /// Ask the terminal whether it supports Sixel graphics.
+    ///
+    /// If these capabilities cannot show images, query the primary device
+    /// attributes of the controlling terminal, and show images with Sixel
+    /// graphics if the terminal reports support for Sixel graphics.
+    ///
+    /// The query writes to `/dev/tty` and waits up to 100ms for the reply of
+    /// the terminal; only use this if standard input and output are a
+    /// terminal.
+    pub fn detect_sixel(self) -> TerminalCapabilities {
+        if self.image.is_none() && self::sixel::is_sixel_terminal() {
+            TerminalCapabilities {
+                name: format!("{} with Sixel", self.name),
+                ..self.with_image(Some(ImageCapability::sixel()))
+            }
+        } else {
+            self
+        }
+    }
+
+    fn detect_terminal() -> TerminalCapabilities {
         if self::iterm2::is_iterm2() {
             Self::iterm2()
         } else if self::terminology::is_terminology() {
@@ -6,21 +6,298 @@
 
 //! Standard ANSI styling.
 
-use ansi_term::Style;
+use ansi_term::{Colour, Style};
 use std::io::{Result, Write};
 
+/// The colours a terminal supports.
+///
+/// Levels are ordered, i.e. every level includes the colours of all lower
+/// levels.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub enum ColourLevel {
+    /// The 16 basic ANSI colours, i.e. 8 colours and their bright variants.
+    Ansi16,
+    /// The 256 colours of the xterm palette.
+    Ansi256,
+    /// 24 bit RGB colours.
+    TrueColour,
+}
+
+impl ColourLevel {
+    /// Get the colour level from the values of `$COLORTERM` and `$TERM`.
+    ///
+    /// `$COLORTERM` set to `truecolor` or `24bit` indicates 24 bit colours; a
+    /// `$TERM` ending in `256color` indicates 256 colours.  Return `None` if
+    /// neither variable tells about the colours of the terminal.
+    pub fn from_env_values(colorterm: Option<&str>, term: Option<&str>) -> Option<ColourLevel> {
+        match (colorterm, term) {
+            (Some("truecolor"), _) | (Some("24bit"), _) => Some(ColourLevel::TrueColour),
+            (_, Some(term)) if term.ends_with("256color") => Some(ColourLevel::Ansi256),
+            _ => None,
+        }
+    }
+
+    /// Detect the colour level of the current terminal from the environment.
+    ///
+    /// See [`ColourLevel::from_env_values`].
+    pub fn detect() -> Option<ColourLevel> {
+        let colorterm = std::env::var("COLORTERM").ok();
+        let term = std::env::var("TERM").ok();
+        Self::from_env_values(colorterm.as_deref(), term.as_deref())
+    }
+}
+
+/// RGB values of the 16 basic ANSI colours.
+///
+/// Terminals use different values for these colours; we use the xterm
+/// defaults.
+const ANSI16_RGB: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xcd, 0x00, 0x00),
+    (0x00, 0xcd, 0x00),
+    (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee),
+    (0xcd, 0x00, 0xcd),
+    (0x00, 0xcd, 0xcd),
+    (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f),
+    (0xff, 0x00, 0x00),
+    (0x00, 0xff, 0x00),
+    (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff),
+    (0xff, 0x00, 0xff),
+    (0x00, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
+/// The levels of each component in the 6x6x6 colour cube of the xterm palette.
+const CUBE_LEVELS: [u8; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// The RGB value of the xterm palette colour with the given `index`.
+fn ansi256_to_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI16_RGB[index as usize],
+        16..=231 => {
+            let cube = index - 16;
+            (
+                CUBE_LEVELS[(cube / 36) as usize],
+                CUBE_LEVELS[((cube / 6) % 6) as usize],
+                CUBE_LEVELS[(cube % 6) as usize],
+            )
+        }
+        _ => {
+            let grey = 8 + (index - 232) * 10;
+            (grey, grey, grey)
+        }
+    }
+}
+
+/// The index of the closest level in the colour cube.
+fn closest_cube_level(value: u8) -> u8 {
+    (0..6)
+        .min_by_key(|&i| (i32::from(CUBE_LEVELS[i as usize]) - i32::from(value)).abs())
+        .unwrap_or(0)
+}
+
+/// The closest colour to `rgb` in the xterm palette, not including the basic
+/// 16 colours whose actual values depend on the terminal.
+fn rgb_to_ansi256(rgb: (u8, u8, u8)) -> u8 {
+    let (r, g, b) = rgb;
+    let cube = 16 + 36 * closest_cube_level(r) + 6 * closest_cube_level(g) + closest_cube_level(b);
+    let grey = (232..=255)
+        .min_by_key(|&index| distance(ansi256_to_rgb(index), rgb))
+        .unwrap_or(232);
+    if distance(ansi256_to_rgb(grey), rgb) < distance(ansi256_to_rgb(cube), rgb) {
+        grey
+    } else {
+        cube
+    }
+}
+
+/// The closest basic ANSI colour to `rgb`.
+fn rgb_to_ansi16(rgb: (u8, u8, u8)) -> Colour {
+    let index = (0..16)
+        .min_by_key(|&index| distance(ANSI16_RGB[index as usize], rgb))
+        .unwrap_or(0);
+    match index {
+        0 => Colour::Black,
+        1 => Colour::Red,
+        2 => Colour::Green,
+        3 => Colour::Yellow,
+        4 => Colour::Blue,
+        5 => Colour::Purple,
+        6 => Colour::Cyan,
+        7 => Colour::White,
+        bright => Colour::Fixed(bright),
+    }
+}
+
+/// Reduce `colour` to the closest colour available at `level`.
+fn downsample(colour: Colour, level: ColourLevel) -> Colour {
+    match (colour, level) {
+        (Colour::RGB(r, g, b), ColourLevel::Ansi256) => Colour::Fixed(rgb_to_ansi256((r, g, b))),
+        (Colour::RGB(r, g, b), ColourLevel::Ansi16) => rgb_to_ansi16((r, g, b)),
+        (Colour::Fixed(index), ColourLevel::Ansi16) if 16 <= index => {
+            rgb_to_ansi16(ansi256_to_rgb(index))
+        }
+        (colour, _) => colour,
+    }
+}
+
+/// The RGB value of `colour`.
+///
+/// Use the xterm defaults for the basic colours.
+pub(crate) fn colour_to_rgb(colour: Colour) -> (u8, u8, u8) {
+    match colour {
+        Colour::Black => ANSI16_RGB[0],
+        Colour::Red => ANSI16_RGB[1],
+        Colour::Green => ANSI16_RGB[2],
+        Colour::Yellow => ANSI16_RGB[3],
+        Colour::Blue => ANSI16_RGB[4],
+        Colour::Purple => ANSI16_RGB[5],
+        Colour::Cyan => ANSI16_RGB[6],
+        Colour::White => ANSI16_RGB[7],
+        Colour::Fixed(index) => ansi256_to_rgb(index),
+        Colour::RGB(r, g, b) => (r, g, b),
+    }
+}
+
 /// Access to a terminal’s basic ANSI styling functionality.
 #[derive(PartialEq, Debug, Clone, Copy)]
-pub struct AnsiStyle;
+pub struct AnsiStyle {
+    colours: ColourLevel,
+}
 
 impl AnsiStyle {
+    /// ANSI styles with the given level of `colours`.
+    pub fn new(colours: ColourLevel) -> AnsiStyle {
+        AnsiStyle { colours }
+    }
+
+    /// The colours this style supports.
+    pub fn colours(self) -> ColourLevel {
+        self.colours
+    }
+
     /// Write styled text to the given writer.
+    ///
+    /// Reduce the colours of `style` to the closest colours available.
     pub fn write_styled<W: Write, V: AsRef<str>>(
         self,
         write: &mut W,
         style: &Style,
         text: V,
     ) -> Result<()> {
+        let style = Style {
+            foreground: style.foreground.map(|c| downsample(c, self.colours)),
+            background: style.background.map(|c| downsample(c, self.colours)),
+            ..*style
+        };
         write!(write, "{}", style.paint(text.as_ref()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn colour_level_from_colorterm() {
+        assert_eq!(
+            ColourLevel::from_env_values(Some("truecolor"), Some("xterm")),
+            Some(ColourLevel::TrueColour)
+        );
+        assert_eq!(
+            ColourLevel::from_env_values(Some("24bit"), None),
+            Some(ColourLevel::TrueColour)
+        );
+    }
+
+    #[test]
+    fn colour_level_from_term() {
+        assert_eq!(
+            ColourLevel::from_env_values(None, Some("xterm-256color")),
+            Some(ColourLevel::Ansi256)
+        );
+        assert_eq!(ColourLevel::from_env_values(Some("1"), Some("xterm")), None);
+        assert_eq!(ColourLevel::from_env_values(None, None), None);
+    }
+
+    #[test]
+    fn downsample_rgb_to_ansi256() {
+        assert_eq!(
+            downsample(Colour::RGB(0xff, 0x00, 0x00), ColourLevel::Ansi256),
+            Colour::Fixed(196)
+        );
+        assert_eq!(
+            downsample(Colour::RGB(0x80, 0x80, 0x80), ColourLevel::Ansi256),
+            Colour::Fixed(244)
+        );
+        assert_eq!(
+            downsample(Colour::RGB(0x5f, 0x87, 0xaf), ColourLevel::Ansi256),
+            Colour::Fixed(67)
+        );
+    }
+
+    #[test]
+    fn downsample_to_ansi16() {
+        assert_eq!(
+            downsample(Colour::RGB(0xd0, 0x10, 0x10), ColourLevel::Ansi16),
+            Colour::Red
+        );
+        assert_eq!(
+            downsample(Colour::RGB(0xfa, 0xfa, 0x40), ColourLevel::Ansi16),
+            Colour::Fixed(11)
+        );
+        assert_eq!(
+            downsample(Colour::Fixed(21), ColourLevel::Ansi16),
+            Colour::Blue
+        );
+        assert_eq!(
+            downsample(Colour::Fixed(9), ColourLevel::Ansi16),
+            Colour::Fixed(9)
+        );
+    }
+
+    #[test]
+    fn keep_colours_with_true_colour() {
+        assert_eq!(
+            downsample(Colour::RGB(0x12, 0x34, 0x56), ColourLevel::TrueColour),
+            Colour::RGB(0x12, 0x34, 0x56)
+        );
+    }
+
+    #[test]
+    fn colour_to_rgb_uses_xterm_palette() {
+        assert_eq!(colour_to_rgb(Colour::Blue), (0x00, 0x00, 0xee));
+        assert_eq!(colour_to_rgb(Colour::Fixed(9)), (0xff, 0x00, 0x00));
+        assert_eq!(colour_to_rgb(Colour::Fixed(67)), (0x5f, 0x87, 0xaf));
+        assert_eq!(colour_to_rgb(Colour::Fixed(244)), (0x80, 0x80, 0x80));
+        assert_eq!(
+            colour_to_rgb(Colour::RGB(0x12, 0x34, 0x56)),
+            (0x12, 0x34, 0x56)
+        );
+    }
+
+    #[test]
+    fn write_styled_downsamples() {
+        let mut buffer = Vec::new();
+        AnsiStyle::new(ColourLevel::Ansi256)
+            .write_styled(
+                &mut buffer,
+                &Style::new().fg(Colour::RGB(0xff, 0, 0)),
+                "foo",
+            )
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "\x1b[38;5;196mfoo\x1b[0m"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Images drawn with Unicode half blocks.
+//!
+//! This works in every terminal with colours: Each character cell shows two
+//! pixels on top of each other, the upper one in the foreground colour of an
+//! upper half block `▀`, and the lower one in the background colour.
+
+use crate::resources::{read_image, ResourceLoader};
+use crate::terminal::size::PixelSize;
+use crate::terminal::AnsiStyle;
+use ansi_term::{Colour, Style};
+use anyhow::Error;
+use fehler::throws;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use std::io::Write;
+use url::Url;
+
+/// Pixels with less alpha are transparent.
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// Provides access to drawing images with half blocks.
+#[derive(Debug, Copy, Clone)]
+pub struct HalfBlockImages;
+
+impl HalfBlockImages {
+    /// Write an image drawn with half blocks.
+    ///
+    /// Start every line of the image with `indent` spaces, and end it with a
+    /// newline.  Use `ansi` to write colours, to reduce the colours of the
+    /// image to the colours the terminal supports.
+    #[throws(std::io::Error)]
+    pub fn write_inline_image<W: Write>(
+        self,
+        writer: &mut W,
+        ansi: AnsiStyle,
+        indent: usize,
+        image: HalfBlockImage,
+    ) -> () {
+        for line in image.lines {
+            write!(writer, "{}", " ".repeat(indent))?;
+            for (style, block) in line {
+                ansi.write_styled(writer, &style, block.to_string())?;
+            }
+            writeln!(writer)?;
+        }
+    }
+
+    /// Read the image from the given URL and draw it with half blocks.
+    ///
+    /// Scale the image down to fit into `columns` and `rows` of the terminal,
+    /// preserving aspect ratio.
+    #[throws]
+    pub fn read_and_render(
+        self,
+        url: &Url,
+        loader: &ResourceLoader,
+        columns: usize,
+        rows: usize,
+    ) -> HalfBlockImage {
+        // Render SVG images to one pixel per half block
+        let size = PixelSize::from_xy((columns as u32, (rows * 2) as u32));
+        self.render(read_image(url, loader, Some(size))?, columns, rows)
+    }
+
+    /// Draw `image` with half blocks.
+    ///
+    /// Scale the image down to fit into `columns` and `rows` of the terminal,
+    /// preserving aspect ratio.
+    fn render(self, image: DynamicImage, columns: usize, rows: usize) -> HalfBlockImage {
+        let (width, height) = image.dimensions();
+        let max_width = columns.max(1) as u32;
+        let max_height = (rows.max(1) * 2) as u32;
+        let image = if width <= max_width && height <= max_height {
+            image
+        } else {
+            image.resize(max_width, max_height, FilterType::Triangle)
+        };
+        HalfBlockImage {
+            lines: draw(&image.into_rgba8()),
+        }
+    }
+}
+
+/// An image drawn with half blocks.
+pub struct HalfBlockImage {
+    /// The lines of the image, as styled blocks.
+    lines: Vec<Vec<(Style, char)>>,
+}
+
+/// The colour of `pixel`, or `None` if the pixel is transparent.
+fn colour_of(pixel: Option<&Rgba<u8>>) -> Option<Colour> {
+    pixel
+        .filter(|p| ALPHA_THRESHOLD <= p[3])
+        .map(|p| Colour::RGB(p[0], p[1], p[2]))
+}
+
+/// Draw `image` with half blocks.
+///
+/// Leave cells with two transparent pixels empty to show the background of the
+/// terminal, and use a lower half block if only the upper pixel is transparent.
+fn draw(image: &RgbaImage) -> Vec<Vec<(Style, char)>> {
+    let (width, height) = image.dimensions();
+    (0..height)
+        .step_by(2)
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    let upper = colour_of(Some(image.get_pixel(x, y)));
+                    let lower = colour_of(
+                        Some(y + 1)
+                            .filter(|y| *y < height)
+                            .map(|y| image.get_pixel(x, y)),
+                    );
+                    match (upper, lower) {
+                        (Some(upper), lower) => (
+                            Style {
+                                foreground: Some(upper),
+                                background: lower,
+                                ..Style::default()
+                            },
+                            '▀',
+                        ),
+                        (None, Some(lower)) => (Style::new().fg(lower), '▄'),
+                        (None, None) => (Style::default(), ' '),
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::ColourLevel;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn draw_pixel_pairs() {
+        let mut image = RgbaImage::from_pixel(3, 2, Rgba([255, 0, 0, 255]));
+        image.put_pixel(0, 1, Rgba([0, 0, 255, 255]));
+        image.put_pixel(1, 0, Rgba([0, 0, 0, 0]));
+        image.put_pixel(2, 0, Rgba([0, 0, 0, 0]));
+        image.put_pixel(2, 1, Rgba([0, 0, 0, 0]));
+        assert_eq!(
+            draw(&image),
+            vec![vec![
+                (
+                    Style::new()
+                        .fg(Colour::RGB(255, 0, 0))
+                        .on(Colour::RGB(0, 0, 255)),
+                    '▀'
+                ),
+                (Style::new().fg(Colour::RGB(255, 0, 0)), '▄'),
+                (Style::default(), ' '),
+            ]]
+        );
+    }
+
+    #[test]
+    fn draw_odd_height() {
+        let image = RgbaImage::from_pixel(1, 3, Rgba([0, 255, 0, 255]));
+        let green = Colour::RGB(0, 255, 0);
+        assert_eq!(
+            draw(&image),
+            vec![
+                vec![(Style::new().fg(green).on(green), '▀')],
+                vec![(Style::new().fg(green), '▀')],
+            ]
+        );
+    }
+
+    #[test]
+    fn scale_to_terminal_size() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 20, Rgba([0, 0, 0, 255])));
+        let blocks = HalfBlockImages.render(image, 20, 24);
+        assert_eq!(blocks.lines.len(), 5);
+        assert!(blocks.lines.iter().all(|line| line.len() == 20));
+
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(10, 40, Rgba([0, 0, 0, 255])));
+        let blocks = HalfBlockImages.render(image, 80, 10);
+        assert_eq!(blocks.lines.len(), 10);
+        assert!(blocks.lines.iter().all(|line| line.len() == 5));
+    }
+
+    #[test]
+    fn write_indented_lines() {
+        let image = RgbaImage::from_pixel(2, 2, Rgba([255, 255, 255, 255]));
+        let image = HalfBlockImage {
+            lines: draw(&image),
+        };
+        let mut buffer = Vec::new();
+        HalfBlockImages
+            .write_inline_image(&mut buffer, AnsiStyle::new(ColourLevel::Ansi256), 2, image)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "  \x1b[48;5;231;38;5;231m▀\x1b[0m\x1b[48;5;231;38;5;231m▀\x1b[0m\n"
+        );
+    }
+}
@@ -12,14 +12,12 @@
 //! See <https://www.iterm2.com> for more information.
 
 use super::osc::write_osc;
-use crate::resources::read_url;
-use crate::{magic, ResourceAccess};
+use crate::magic;
+use crate::resources::{render_svg_cached, ResourceLoader};
 use anyhow::{Context, Result};
 use std::io::{self, Write};
 use url::Url;
 
-use super::super::svg;
-
 /// Whether we run inside iTerm2 or not.
 pub fn is_iterm2() -> bool {
     std::env::var("TERM_PROGRAM")
@@ -72,12 +70,14 @@ impl ITerm2Images {
     ///
     /// Render the binary content of the (rendered) image or an IO error if
     /// reading or rendering failed.
-    pub fn read_and_render(self, url: &Url, access: ResourceAccess) -> Result<Vec<u8>> {
-        let contents = read_url(url, access)?;
-        let mimetype = magic::detect_mime_type(&contents)
+    pub fn read_and_render(self, url: &Url, loader: &ResourceLoader) -> Result<Vec<u8>> {
+        let resource = loader.read(url)?;
+        let mimetype = resource
+            .detect_mime_type()
             .with_context(|| format!("Failed to guess mime type for URL {}", url))?;
+        let contents = resource.contents;
         if magic::is_svg(&mimetype) {
-            svg::render_svg(&contents)
+            render_svg_cached(&contents, None, loader.cache)
                 .with_context(|| format!("Failed to render SVG at URL {}", url))
         } else {
             Ok(contents)
@@ -19,17 +19,15 @@
 //!
 //! See <https://sw.kovidgoyal.net/kitty/> for more information.
 
-use crate::resources::read_url;
-use crate::svg::render_svg;
+use crate::magic;
+use crate::resources::{image_dimensions, load_image, ResourceLoader};
 use crate::terminal::size::PixelSize;
-use crate::{magic, ResourceAccess};
 use anyhow::{Context, Error};
 use fehler::throws;
 use image::imageops::FilterType;
 use image::ColorType;
 use image::{DynamicImage, GenericImageView};
 use std::io::Write;
-use std::str;
 use url::Url;
 
 /// Whether we run in Kitty or not.
@@ -45,7 +43,7 @@ pub struct KittyImages;
 
 impl KittyImages {
     /// Write an inline image for kitty.
-    #[throws]
+    #[throws(std::io::Error)]
     pub fn write_inline_image<W: Write>(self, writer: &mut W, image: KittyImage) -> () {
         // Kitty's escape sequence is like: Put the command key/value pairs together like "{}={}(,*)"
         // and write them along with the image bytes in 4096 bytes chunks to the stdout.
@@ -103,7 +101,7 @@ impl KittyImages {
             let cmd = format!(
                 "\x1b_G{};{}\x1b\\",
                 cmd_header.join(","),
-                str::from_utf8(data)?
+                String::from_utf8_lossy(data)
             );
             writer.write_all(cmd.as_bytes())?;
             writer.flush()?;
@@ -120,26 +118,21 @@ impl KittyImages {
     pub fn read_and_render(
         self,
         url: &Url,
-        access: ResourceAccess,
+        loader: &ResourceLoader,
         terminal_size: PixelSize,
     ) -> KittyImage {
-        let contents = read_url(url, access)?;
-        let mime = magic::detect_mime_type(&contents)
+        let resource = loader.read(url)?;
+        let mime = resource
+            .detect_mime_type()
             .with_context(|| format!("Failed to detect mime type for URL {}", url))?;
-        let image = if magic::is_svg(&mime) {
-            image::load_from_memory(
-                &render_svg(&contents)
-                    .with_context(|| format!("Failed to render SVG at {} to PNG", url))?,
-            )
-            .with_context(|| format!("Failed to load SVG rendered from {}", url))?
-        } else {
-            image::load_from_memory(&contents)
-                .with_context(|| format!("Failed to load image from URL {}", url))?
+        let fits = || {
+            image_dimensions(&resource.contents)
+                .is_ok_and(|dimensions| PixelSize::from_xy(dimensions) <= terminal_size)
         };
-
-        if magic::is_png(&mime) && PixelSize::from_xy(image.dimensions()) <= terminal_size {
-            self.render_as_png(contents)
+        if magic::is_png(&mime) && fits() {
+            self.render_as_png(resource.contents)
         } else {
+            let image = load_image(url, &resource, loader.cache, Some(terminal_size))?;
             self.render_as_rgb_or_rgba(image, terminal_size)
         }
     }
@@ -174,11 +167,7 @@ impl KittyImages {
         let image = if PixelSize::from_xy(image.dimensions()) <= terminal_size {
             image
         } else {
-            image.resize(
-                terminal_size.x as u32,
-                terminal_size.y as u32,
-                FilterType::Nearest,
-            )
+            image.resize(terminal_size.x, terminal_size.y, FilterType::Nearest)
         };
 
         let size = PixelSize::from_xy(image.dimensions());
@@ -0,0 +1,174 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Render to a terminal.
+
+use std::io::{Result, Write};
+
+use ansi_term::Style;
+use anyhow::anyhow;
+use url::Url;
+
+use super::*;
+use crate::renderer::{InlineImage, Renderer};
+use crate::Error;
+
+/// A renderer which writes to a terminal.
+///
+/// Write styles, links, marks and images with the escape sequences the
+/// terminal supports according to its capabilities.
+#[derive(Debug)]
+pub struct TerminalRenderer<'c, W> {
+    writer: W,
+    capabilities: &'c TerminalCapabilities,
+    hostname: String,
+}
+
+impl<'c, W: Write> TerminalRenderer<'c, W> {
+    /// Write to a terminal with the given `capabilities` through `writer`.
+    ///
+    /// `hostname` is the name of this host, for `file://` links.
+    pub fn new(writer: W, capabilities: &'c TerminalCapabilities, hostname: &str) -> Self {
+        TerminalRenderer {
+            writer,
+            capabilities,
+            hostname: hostname.to_string(),
+        }
+    }
+
+    /// Get the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Turn a failure to show the image at `url` into an mdcat error.
+fn image_error(url: &Url, error: anyhow::Error) -> Error {
+    error
+        .downcast::<Error>()
+        .unwrap_or_else(|source| Error::Decode {
+            url: url.clone(),
+            source,
+        })
+}
+
+impl<W: Write> Renderer for TerminalRenderer<'_, W> {
+    fn write_styled(&mut self, style: &Style, text: &str) -> Result<()> {
+        match self.capabilities.style {
+            None => write!(self.writer, "{}", text),
+            Some(StyleCapability::Ansi(ansi)) => ansi.write_styled(&mut self.writer, style, text),
+        }
+    }
+
+    fn start_link(&mut self, url: &Url) -> Result<()> {
+        match self.capabilities.links {
+            Some(LinkCapability::Osc8(osc8)) => {
+                osc8.set_link_url(&mut self.writer, url.clone(), &self.hostname)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn end_link(&mut self) -> Result<()> {
+        match self.capabilities.links {
+            Some(LinkCapability::Osc8(osc8)) => osc8.clear_link(&mut self.writer),
+            None => Ok(()),
+        }
+    }
+
+    fn set_mark(&mut self) -> Result<()> {
+        match self.capabilities.marks {
+            Some(MarkCapability::ITerm2(marks)) => marks.set_mark(&mut self.writer),
+            None => Ok(()),
+        }
+    }
+
+    fn write_image(
+        &mut self,
+        image: &InlineImage<'_>,
+    ) -> std::result::Result<Option<usize>, Error> {
+        use ImageCapability::*;
+        let url = image.url;
+        let loader = &image.loader;
+        let pixels = || {
+            image
+                .terminal_size
+                .pixels
+                .ok_or_else(|| image_error(url, anyhow!("Terminal pixel size not available")))
+        };
+        match self.capabilities.image {
+            Some(Terminology(terminology)) => {
+                // Terminology reads the image itself, so check our policy first
+                image.check_access()?;
+                terminology.write_inline_image(&mut self.writer, image.terminal_size, url)?;
+                Ok(Some(image.column))
+            }
+            Some(ITerm2(iterm2)) => {
+                let contents = iterm2
+                    .read_and_render(url, loader)
+                    .map_err(|error| image_error(url, error))?;
+                // Use the last segment as file name for iterm2.
+                let name = url.path_segments().and_then(|mut s| s.next_back());
+                iterm2.write_inline_image(&mut self.writer, name, &contents)?;
+                Ok(Some(image.column))
+            }
+            Some(Kitty(kitty)) => {
+                let rendered = kitty
+                    .read_and_render(url, loader, pixels()?)
+                    .map_err(|error| image_error(url, error))?;
+                // Images start on a line of their own
+                let indent = image.indent as usize;
+                if indent < image.column {
+                    writeln!(self.writer)?;
+                }
+                kitty.write_inline_image(&mut self.writer, rendered)?;
+                // Kitty leaves the cursor right of the last row of the image,
+                // so move it to the next line
+                writeln!(self.writer)?;
+                write!(self.writer, "{}", " ".repeat(indent))?;
+                Ok(Some(indent))
+            }
+            Some(Sixel(sixel)) => {
+                let rendered = sixel
+                    .read_and_render(url, loader, pixels()?)
+                    .map_err(|error| image_error(url, error))?;
+                // Images start on a line of their own
+                let indent = image.indent as usize;
+                if indent < image.column {
+                    writeln!(self.writer)?;
+                }
+                // Sixel graphics leave the cursor at the start of the line
+                // below the image
+                sixel.write_inline_image(&mut self.writer, rendered)?;
+                write!(self.writer, "{}", " ".repeat(indent))?;
+                Ok(Some(indent))
+            }
+            Some(HalfBlocks(blocks)) => match self.capabilities.style {
+                Some(StyleCapability::Ansi(ansi)) => {
+                    let indent = image.indent as usize;
+                    let rendered = blocks
+                        .read_and_render(
+                            url,
+                            loader,
+                            image.terminal_size.columns.saturating_sub(indent),
+                            image.terminal_size.rows,
+                        )
+                        .map_err(|error| image_error(url, error))?;
+                    // Images start on a line of their own
+                    if indent < image.column {
+                        writeln!(self.writer)?;
+                    }
+                    blocks.write_inline_image(&mut self.writer, ansi, indent, rendered)?;
+                    write!(self.writer, "{}", " ".repeat(indent))?;
+                    Ok(Some(indent))
+                }
+                // Without colours we cannot draw images
+                None => Ok(None),
+            },
+            None => Ok(None),
+        }
+    }
+}
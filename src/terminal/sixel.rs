@@ -0,0 +1,347 @@
+// Copyright 2020 Sebastian Wiesner <sebastian@swsnr.de>
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Sixel graphics.
+//!
+//! Sixel is an old bitmap graphics format of DEC terminals which many modern
+//! terminals support, e.g. xterm, foot, mlterm, or Windows Terminal.
+//!
+//! See <https://vt100.net/docs/vt3xx-gp/chapter14.html> for the format.
+
+use crate::resources::{read_image, ResourceLoader};
+use crate::terminal::size::PixelSize;
+use anyhow::Error;
+use color_quant::NeuQuant;
+use fehler::throws;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use std::collections::hash_map::{Entry, HashMap};
+use std::fmt::Write as FmtWrite;
+use std::io::Write;
+use url::Url;
+
+/// The maximum number of colours in a Sixel palette.
+const MAX_COLOURS: usize = 256;
+
+/// Pixels with less alpha are transparent.
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// Whether the primary device attributes in `response` include Sixel graphics.
+///
+/// `response` is the reply of a terminal to a DA1 query, i.e. `CSI ? Ps ; … c`
+/// where each `Ps` denotes a feature of the terminal; 4 denotes Sixel graphics.
+pub fn da1_supports_sixel(response: &str) -> bool {
+    response
+        .rsplit("\x1b[?")
+        .next()
+        .and_then(|attributes| attributes.strip_suffix('c'))
+        .is_some_and(|attributes| attributes.split(';').any(|a| a == "4"))
+}
+
+/// Query the primary device attributes of the controlling terminal.
+///
+/// Only query if standard output is a terminal, because otherwise we'd
+/// write to a terminal which doesn't show our output anyway.
+#[cfg(unix)]
+fn query_da1() -> Option<String> {
+    use std::time::{Duration, Instant};
+
+    let timeout = Duration::from_millis(100);
+    unsafe {
+        if libc::isatty(libc::STDOUT_FILENO) != 1 {
+            return None;
+        }
+        let fd = libc::open(
+            b"/dev/tty\0".as_ptr() as *const libc::c_char,
+            libc::O_RDWR | libc::O_NOCTTY,
+        );
+        if fd < 0 {
+            return None;
+        }
+        let mut original: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(fd, &mut original) != 0 {
+            libc::close(fd);
+            return None;
+        }
+        // Disable canonical mode and echo to read the response right away, and
+        // to not show it to the user.
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 0;
+        libc::tcsetattr(fd, libc::TCSANOW, &raw);
+
+        let query = b"\x1b[c";
+        let mut response = Vec::new();
+        if libc::write(fd, query.as_ptr() as *const libc::c_void, query.len())
+            == query.len() as isize
+        {
+            let deadline = Instant::now() + timeout;
+            let mut buffer = [0u8; 64];
+            while !response.ends_with(b"c") {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let mut poll_fd = libc::pollfd {
+                    fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                if remaining.as_millis() == 0
+                    || libc::poll(&mut poll_fd, 1, remaining.as_millis() as libc::c_int) <= 0
+                {
+                    break;
+                }
+                let read = libc::read(fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len());
+                if read <= 0 {
+                    break;
+                }
+                response.extend_from_slice(&buffer[..read as usize]);
+            }
+        }
+
+        libc::tcsetattr(fd, libc::TCSANOW, &original);
+        libc::close(fd);
+        String::from_utf8(response).ok()
+    }
+}
+
+#[cfg(not(unix))]
+fn query_da1() -> Option<String> {
+    None
+}
+
+/// Whether the terminal supports Sixel graphics.
+///
+/// Query the primary device attributes of the terminal, see
+/// [`da1_supports_sixel`].
+pub fn is_sixel_terminal() -> bool {
+    query_da1().is_some_and(|response| da1_supports_sixel(&response))
+}
+
+/// Provides access to printing images with Sixel graphics.
+#[derive(Debug, Copy, Clone)]
+pub struct SixelImages;
+
+impl SixelImages {
+    /// Write an inline image with Sixel graphics.
+    #[throws(std::io::Error)]
+    pub fn write_inline_image<W: Write>(self, writer: &mut W, image: SixelImage) -> () {
+        writer.write_all(image.data.as_bytes())?;
+        writer.flush()?;
+    }
+
+    /// Read the image from the given URL and encode it as Sixel graphics.
+    ///
+    /// If the image size exceeds `terminal_size` in either dimension scale the
+    /// image down to `terminal_size` (preserving aspect ratio).
+    #[throws]
+    pub fn read_and_render(
+        self,
+        url: &Url,
+        loader: &ResourceLoader,
+        terminal_size: PixelSize,
+    ) -> SixelImage {
+        self.render(read_image(url, loader, Some(terminal_size))?, terminal_size)
+    }
+
+    /// Encode `image` as Sixel graphics.
+    ///
+    /// If the image size exceeds `terminal_size` in either dimension scale the
+    /// image down to `terminal_size` (preserving aspect ratio).
+    fn render(self, image: DynamicImage, terminal_size: PixelSize) -> SixelImage {
+        let image = if PixelSize::from_xy(image.dimensions()) <= terminal_size {
+            image
+        } else {
+            image.resize(terminal_size.x, terminal_size.y, FilterType::Triangle)
+        };
+        SixelImage {
+            data: encode(&image.into_rgba8()),
+        }
+    }
+}
+
+/// An image encoded as Sixel graphics.
+pub struct SixelImage {
+    data: String,
+}
+
+/// A palette of colours for an image.
+struct Palette {
+    colours: Vec<[u8; 3]>,
+    /// The index of each colour, for an exact palette.
+    indices: HashMap<[u8; 3], usize>,
+    /// The quantizer, for a palette with quantized colours.
+    quantizer: Option<NeuQuant>,
+}
+
+impl Palette {
+    /// Create a palette for the opaque pixels of `image`.
+    ///
+    /// Use the exact colours of the image if they fit into a Sixel palette,
+    /// otherwise quantize the colours of the image.
+    fn for_image(image: &RgbaImage) -> Palette {
+        let mut colours: Vec<[u8; 3]> = Vec::new();
+        let mut indices = HashMap::new();
+        for pixel in image.pixels().filter(|p| ALPHA_THRESHOLD <= p[3]) {
+            let colour = [pixel[0], pixel[1], pixel[2]];
+            if let Entry::Vacant(entry) = indices.entry(colour) {
+                if colours.len() == MAX_COLOURS {
+                    return Self::quantized(image);
+                }
+                entry.insert(colours.len());
+                colours.push(colour);
+            }
+        }
+        Palette {
+            colours,
+            indices,
+            quantizer: None,
+        }
+    }
+
+    fn quantized(image: &RgbaImage) -> Palette {
+        let quantizer = NeuQuant::new(10, MAX_COLOURS, image.as_raw());
+        let colours = quantizer
+            .color_map_rgb()
+            .chunks(3)
+            .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+            .collect();
+        Palette {
+            colours,
+            indices: HashMap::new(),
+            quantizer: Some(quantizer),
+        }
+    }
+
+    /// The palette index for the given `rgba` pixel.
+    fn index_of(&self, rgba: &[u8]) -> usize {
+        match &self.quantizer {
+            Some(quantizer) => quantizer.index_of(rgba),
+            None => self
+                .indices
+                .get(&[rgba[0], rgba[1], rgba[2]])
+                .copied()
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// Append `count` repetitions of the sixel `bits` to `data`.
+fn push_run(data: &mut String, bits: u8, count: usize) {
+    let sixel = char::from(0x3f + bits);
+    if count > 3 {
+        // Writing to a String never fails
+        let _ = write!(data, "!{}{}", count, sixel);
+    } else {
+        data.extend(std::iter::repeat_n(sixel, count));
+    }
+}
+
+/// Encode `image` as Sixel graphics.
+///
+/// Leave transparent pixels unset to show the background of the terminal.
+fn encode(image: &RgbaImage) -> String {
+    let (width, height) = image.dimensions();
+    let palette = Palette::for_image(image);
+    let indices: Vec<Option<usize>> = image
+        .pixels()
+        .map(|p| Some(p).filter(|p| ALPHA_THRESHOLD <= p[3]))
+        .map(|p| p.map(|p| palette.index_of(&p.0)))
+        .collect();
+
+    // Start sixel mode with transparent background, and set the image size
+    let mut data = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+    for (index, [r, g, b]) in palette.colours.iter().enumerate() {
+        let percent = |value: &u8| (u32::from(*value) * 100 + 127) / 255;
+        let _ = write!(
+            data,
+            "#{};2;{};{};{}",
+            index,
+            percent(r),
+            percent(g),
+            percent(b)
+        );
+    }
+
+    let width = width as usize;
+    let mut bands: Vec<Option<Vec<u8>>> = vec![None; palette.colours.len()];
+    for band_start in (0..height as usize).step_by(6) {
+        let band_end = (band_start + 6).min(height as usize);
+        for y in band_start..band_end {
+            let bit = 1 << (y - band_start);
+            for x in 0..width {
+                if let Some(index) = indices[y * width + x] {
+                    bands[index].get_or_insert_with(|| vec![0; width])[x] |= bit;
+                }
+            }
+        }
+        let mut first = true;
+        for (index, band) in bands.iter_mut().enumerate() {
+            if let Some(sixels) = band.take() {
+                if !first {
+                    // Return to the start of the band for the next colour
+                    data.push('$');
+                }
+                first = false;
+                let _ = write!(data, "#{}", index);
+                let used = sixels.iter().rposition(|s| *s != 0).map_or(0, |i| i + 1);
+                let mut runs = sixels[..used].iter().peekable();
+                while let Some(bits) = runs.next() {
+                    let mut count = 1;
+                    while runs.next_if_eq(&bits).is_some() {
+                        count += 1;
+                    }
+                    push_run(&mut data, *bits, count);
+                }
+            }
+        }
+        // Move to the next band
+        data.push('-');
+    }
+    data.push_str("\x1b\\");
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn sixel_in_da1_response() {
+        assert!(da1_supports_sixel("\x1b[?62;4;6;22c"));
+        assert!(da1_supports_sixel("\x1b[?63;1;2;4c"));
+        assert!(!da1_supports_sixel("\x1b[?62;1;6;22c"));
+        assert!(!da1_supports_sixel("\x1b[?64;14c"));
+        assert!(!da1_supports_sixel(""));
+    }
+
+    #[test]
+    fn encode_image_with_two_colours() {
+        let mut image = RgbaImage::from_pixel(5, 7, Rgba([255, 0, 0, 255]));
+        image.put_pixel(4, 0, Rgba([0, 0, 255, 255]));
+        image.put_pixel(4, 6, Rgba([0, 0, 0, 0]));
+        assert_eq!(
+            encode(&image),
+            "\x1bP0;1;0q\"1;1;5;7#0;2;100;0;0#1;2;0;0;100#0!4~}$#1!4?@-#0!4@-\x1b\\"
+        );
+    }
+
+    #[test]
+    fn quantize_many_colours() {
+        let image = RgbaImage::from_fn(32, 32, |x, y| Rgba([x as u8 * 8, y as u8 * 8, 0, 255]));
+        let palette = Palette::for_image(&image);
+        assert_eq!(palette.colours.len(), MAX_COLOURS);
+        assert!(palette.quantizer.is_some());
+    }
+
+    #[test]
+    fn scale_to_terminal_size() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 20, Rgba([0, 0, 0, 255])));
+        let sixel = SixelImages.render(image, PixelSize::from_xy((20, 20)));
+        assert!(sixel.data.starts_with("\x1bP0;1;0q\"1;1;20;10#"));
+    }
+}
@@ -27,7 +27,7 @@ mod cli {
 
     #[test]
     fn show_help() {
-        let output = run_cargo_mdcat(&["--help"]);
+        let output = run_cargo_mdcat(["--help"]);
         let stdout = std::str::from_utf8(&output.stdout).unwrap();
         assert!(
             output.status.success(),
@@ -38,9 +38,705 @@ mod cli {
         assert!(stdout.contains("See 'man 1 mdcat' for more information."));
     }
 
+    #[test]
+    fn list_themes() {
+        let output = run_cargo_mdcat(["--list-themes"]);
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success());
+        assert!(stdout.lines().any(|line| line == "Solarized (dark)"));
+        assert!(stdout.lines().any(|line| line == "InspiredGitHub"));
+    }
+
+    #[test]
+    fn list_user_themes() {
+        let config_home = std::env::temp_dir().join(format!("mdcat-cli-{}", std::process::id()));
+        let themes = config_home.join("mdcat").join("themes");
+        std::fs::create_dir_all(&themes).unwrap();
+        std::fs::write(
+            themes.join("mdcat-test.tmTheme"),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>name</key>
+    <string>mdcat test</string>
+    <key>settings</key>
+    <array>
+        <dict>
+            <key>settings</key>
+            <dict>
+                <key>foreground</key>
+                <string>#123456</string>
+            </dict>
+        </dict>
+    </array>
+</dict>
+</plist>"#,
+        )
+        .unwrap();
+        let output = cargo_mdcat()
+            .env("XDG_CONFIG_HOME", &config_home)
+            .arg("--list-themes")
+            .output()
+            .unwrap();
+        std::fs::remove_dir_all(&config_home).unwrap();
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success());
+        assert!(
+            stdout.lines().any(|line| line == "mdcat-test"),
+            "Stdout: {}",
+            stdout
+        );
+        assert!(stdout.lines().any(|line| line == "Solarized (dark)"));
+    }
+
+    #[test]
+    fn highlight_with_theme_in_true_colour() {
+        let output = cargo_mdcat()
+            .env("COLORTERM", "truecolor")
+            // Paginate to get plain ANSI formatting, but disable the pager
+            .env("MDCAT_PAGER", "")
+            .args([
+                "--paginate",
+                "--theme",
+                "InspiredGitHub",
+                "sample/common-mark.md",
+            ])
+            .output()
+            .unwrap();
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success());
+        // InspiredGitHub has no Solarized colours, so we must get 24 bit colours
+        assert!(stdout.contains("\x1b[38;2;"), "Stdout: {}", stdout);
+    }
+
+    #[test]
+    fn highlight_with_theme_in_ansi_colours() {
+        let output = run_cargo_mdcat([
+            "--ansi-only",
+            "--theme",
+            "InspiredGitHub",
+            "sample/common-mark.md",
+        ]);
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success());
+        assert!(!stdout.contains("\x1b[38;2;"), "Stdout: {}", stdout);
+        assert!(!stdout.contains("\x1b[38;5;"), "Stdout: {}", stdout);
+    }
+
+    #[test]
+    fn unknown_theme() {
+        let output = run_cargo_mdcat(["--theme", "does-not-exist", "sample/common-mark.md"]);
+        let stderr = std::str::from_utf8(&output.stderr).unwrap();
+        assert!(!output.status.success());
+        assert!(
+            stderr.contains("Unknown theme: does-not-exist"),
+            "Stderr: {}",
+            stderr
+        );
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    fn custom_stylesheet() {
+        let stylesheet =
+            std::env::temp_dir().join(format!("mdcat-stylesheet-{}.toml", std::process::id()));
+        std::fs::write(
+            &stylesheet,
+            r##"
+link = { foreground = "#ff8700", underline = true }
+
+[[headings]]
+prefix = "> "
+style = { foreground = "red" }
+"##,
+        )
+        .unwrap();
+        let output = cargo_mdcat()
+            .env("COLORTERM", "truecolor")
+            .env("MDCAT_PAGER", "")
+            .arg("--paginate")
+            .arg("--stylesheet")
+            .arg(&stylesheet)
+            .arg("sample/common-mark.md")
+            .output()
+            .unwrap();
+        std::fs::remove_file(&stylesheet).unwrap();
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success());
+        assert!(
+            stdout.contains("\x1b[31m> \x1b[0m\x1b[31mCommonMark sample document"),
+            "Stdout: {}",
+            stdout
+        );
+        assert!(
+            stdout.contains("\x1b[4;38;2;255;135;0m"),
+            "Stdout: {}",
+            stdout
+        );
+    }
+
+    #[test]
+    fn invalid_stylesheet() {
+        let output = run_cargo_mdcat(["--stylesheet", "Cargo.toml", "sample/common-mark.md"]);
+        let stderr = std::str::from_utf8(&output.stderr).unwrap();
+        assert!(!output.status.success());
+        assert!(stderr.contains("Error: Cargo.toml:"), "Stderr: {}", stderr);
+        assert!(output.stdout.is_empty());
+    }
+
+    fn write_config(name: &str, contents: &str) -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!("mdcat-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let config = directory.join("config.toml");
+        std::fs::write(&config, contents).unwrap();
+        config
+    }
+
+    #[test]
+    fn print_config_merges_config_file_and_arguments() {
+        let config = write_config(
+            "merge",
+            r#"
+columns = 42
+local = true
+theme = "InspiredGitHub"
+stylesheet = "style.toml"
+pager = "most"
+"#,
+        );
+        let output = cargo_mdcat()
+            .env_remove("MDCAT_PAGER")
+            .env_remove("PAGER")
+            .arg("--config")
+            .arg(&config)
+            .args(["--columns", "60", "--no-colour", "--print-config"])
+            .output()
+            .unwrap();
+        std::fs::remove_dir_all(config.parent().unwrap()).unwrap();
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success());
+        let lines: Vec<_> = stdout.lines().collect();
+        assert!(lines.contains(&"columns = 60"), "Stdout: {}", stdout);
+        assert!(lines.contains(&"local = true"), "Stdout: {}", stdout);
+        assert!(lines.contains(&"paginate = false"), "Stdout: {}", stdout);
+        assert!(lines.contains(&"pager = \"most\""), "Stdout: {}", stdout);
+        assert!(
+            lines.contains(&"theme = \"InspiredGitHub\""),
+            "Stdout: {}",
+            stdout
+        );
+        assert!(
+            lines.contains(&"capabilities = \"none\""),
+            "Stdout: {}",
+            stdout
+        );
+        // Stylesheets are relative to the configuration file
+        let stylesheet = config.with_file_name("style.toml");
+        assert!(
+            stdout.contains(&format!(
+                "stylesheet = {:?}",
+                stylesheet.display().to_string()
+            )),
+            "Stdout: {}",
+            stdout
+        );
+    }
+
+    #[test]
+    fn mdcat_pager_overrides_config_file() {
+        let config = write_config("pager", "pager = \"most\"\n");
+        let output = cargo_mdcat()
+            .env("MDCAT_PAGER", "more")
+            .env("PAGER", "less")
+            .arg("--config")
+            .arg(&config)
+            .arg("--print-config")
+            .output()
+            .unwrap();
+        std::fs::remove_dir_all(config.parent().unwrap()).unwrap();
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success());
+        assert!(
+            stdout.lines().any(|line| line == "pager = \"more\""),
+            "Stdout: {}",
+            stdout
+        );
+    }
+
+    #[test]
+    fn config_file_in_config_home() {
+        let config = write_config("home", "theme = \"does-not-exist\"\n");
+        let config_home = config.parent().unwrap().join("xdg");
+        std::fs::create_dir_all(config_home.join("mdcat")).unwrap();
+        std::fs::rename(&config, config_home.join("mdcat").join("config.toml")).unwrap();
+        let output = cargo_mdcat()
+            .env("XDG_CONFIG_HOME", &config_home)
+            .arg("sample/common-mark.md")
+            .output()
+            .unwrap();
+        std::fs::remove_dir_all(config.parent().unwrap()).unwrap();
+        let stderr = std::str::from_utf8(&output.stderr).unwrap();
+        assert!(!output.status.success());
+        assert!(
+            stderr.contains("Unknown theme: does-not-exist"),
+            "Stderr: {}",
+            stderr
+        );
+    }
+
+    #[test]
+    fn invalid_config_file() {
+        let config = write_config("invalid", "colums = 42\n");
+        let output = cargo_mdcat()
+            .arg("--config")
+            .arg(&config)
+            .arg("sample/common-mark.md")
+            .output()
+            .unwrap();
+        std::fs::remove_dir_all(config.parent().unwrap()).unwrap();
+        let stderr = std::str::from_utf8(&output.stderr).unwrap();
+        assert!(!output.status.success());
+        assert!(
+            stderr.contains("Invalid configuration in") && stderr.contains("colums"),
+            "Stderr: {}",
+            stderr
+        );
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    fn no_cache_disables_cache() {
+        let output = cargo_mdcat()
+            .args(["--no-cache", "--print-config"])
+            .output()
+            .unwrap();
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success());
+        assert!(
+            stdout.lines().any(|line| line == "cache = false"),
+            "Stdout: {}",
+            stdout
+        );
+    }
+
+    #[test]
+    fn print_resource_policy() {
+        let config = write_config("policy", "local-root = \"images\"\ntimeout = 10\n");
+        let output = cargo_mdcat()
+            .arg("--config")
+            .arg(&config)
+            .args(["--schemes", "file,https", "--allow-host", "example.com"])
+            .args([
+                "--deny-host",
+                "a.example.com",
+                "--deny-host",
+                "b.example.com",
+            ])
+            .args(["--size-limit", "2M", "--retries", "0", "--print-config"])
+            .output()
+            .unwrap();
+        std::fs::remove_dir_all(config.parent().unwrap()).unwrap();
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success());
+        let lines: Vec<_> = stdout.lines().collect();
+        for line in &[
+            "schemes = [\"file\", \"https\"]",
+            "allow-hosts = [\"example.com\"]",
+            "deny-hosts = [\"a.example.com\", \"b.example.com\"]",
+            "size-limit = 2097152",
+            "timeout = 10",
+            "retries = 0",
+        ] {
+            assert!(lines.contains(line), "Stdout: {}", stdout);
+        }
+        // The local root is relative to the configuration file
+        let local_root = config.with_file_name("images");
+        assert!(
+            stdout.contains(&format!(
+                "local-root = {:?}",
+                local_root.display().to_string()
+            )),
+            "Stdout: {}",
+            stdout
+        );
+    }
+
+    #[test]
+    fn invalid_size_limit() {
+        let output = run_cargo_mdcat(["--size-limit", "2X", "sample/common-mark.md"]);
+        let stderr = std::str::from_utf8(&output.stderr).unwrap();
+        assert!(!output.status.success());
+        assert!(stderr.contains("Invalid size: 2X"), "Stderr: {}", stderr);
+    }
+
+    #[test]
+    fn images_outside_of_local_root() {
+        let logo = std::env::current_dir()
+            .unwrap()
+            .join("sample")
+            .join("rust-logo-128x128.png");
+        let document = write_config("local-root", "").with_file_name("images.md");
+        std::fs::write(&document, format!("![logo]({})\n", logo.display())).unwrap();
+        let render = |args: &[&str]| {
+            let output = cargo_mdcat()
+                .args([
+                    "--ansi-only",
+                    "--image-protocol",
+                    "half-blocks",
+                    "--no-cache",
+                ])
+                .args(args)
+                .arg(&document)
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            String::from_utf8(output.stdout).unwrap()
+        };
+        // Local images must be in the directory of the document by default
+        let denied = render(&[]);
+        let permitted = render(&["--local-root", "sample"]);
+        std::fs::remove_dir_all(document.parent().unwrap()).unwrap();
+        assert!(!denied.contains('▀'), "Stdout: {}", denied);
+        assert!(permitted.contains('▀'), "Stdout: {}", permitted);
+    }
+
+    #[test]
+    fn verbose_warnings() {
+        let document = write_config("verbose", "").with_file_name("missing.md");
+        std::fs::write(&document, "![missing](missing.png)\n").unwrap();
+        let render = |args: &[&str]| {
+            let output = cargo_mdcat()
+                .args(["--ansi-only", "--image-protocol", "half-blocks"])
+                .args(args)
+                .arg(&document)
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            String::from_utf8(output.stderr).unwrap()
+        };
+        let quiet = render(&[]);
+        let verbose = render(&["--verbose"]);
+        std::fs::remove_dir_all(document.parent().unwrap()).unwrap();
+        assert!(quiet.is_empty(), "Stderr: {}", quiet);
+        assert!(
+            verbose.starts_with("Warning: Failed to open file at file://"),
+            "Stderr: {}",
+            verbose
+        );
+        assert!(verbose.contains("missing.png"), "Stderr: {}", verbose);
+    }
+
+    #[test]
+    fn write_html() {
+        let document = write_config("html", "").with_file_name("html.md");
+        std::fs::write(
+            &document,
+            "# <Title>\n\n[Link](https://example.com)\n\n![Logo](logo.png)\n",
+        )
+        .unwrap();
+        std::fs::copy(
+            "sample/rust-logo-128x128.png",
+            document.with_file_name("logo.png"),
+        )
+        .unwrap();
+        let output = cargo_mdcat()
+            .args(["--paginate", "--html"])
+            .arg(&document)
+            .output()
+            .unwrap();
+        std::fs::remove_dir_all(document.parent().unwrap()).unwrap();
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success());
+        assert!(stdout.starts_with("<!DOCTYPE html>"), "Stdout: {}", stdout);
+        assert!(
+            stdout.contains("&lt;Title&gt;</span>"),
+            "Stdout: {}",
+            stdout
+        );
+        assert!(
+            stdout.contains(r#"<a href="https://example.com/">"#),
+            "Stdout: {}",
+            stdout
+        );
+        assert!(
+            stdout.contains(r#"<img src="data:image/png;base64,"#),
+            "Stdout: {}",
+            stdout
+        );
+        assert!(stdout.ends_with("</pre>\n</body>\n</html>\n"));
+        assert!(!stdout.contains('\x1b'), "Stdout: {}", stdout);
+    }
+
+    /// Serve `files` by path over HTTP on a local port, and return the base URL.
+    fn serve(files: Vec<(&'static str, Vec<u8>)>) -> String {
+        use std::io::{BufRead, BufReader, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+                let (status, body) = match files.iter().find(|(name, _)| *name == path) {
+                    Some((_, body)) => ("200 OK", body.as_slice()),
+                    None => ("404 Not Found", &b""[..]),
+                };
+                let head = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(body);
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    #[test]
+    fn render_remote_document() {
+        let server = serve(vec![
+            (
+                "/docs/README.md",
+                b"# Remote\n\nA ![logo](logo.png).\n".to_vec(),
+            ),
+            (
+                "/docs/logo.png",
+                std::fs::read("sample/rust-logo-128x128.png").unwrap(),
+            ),
+        ]);
+        let url = format!("{}/docs/README.md", server);
+        let output = cargo_mdcat()
+            .args([
+                "--ansi-only",
+                "--image-protocol",
+                "half-blocks",
+                "--no-cache",
+            ])
+            .arg(&url)
+            .output()
+            .unwrap();
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success());
+        assert!(stdout.contains("Remote"), "Stdout: {}", stdout);
+        // The image resolves against the URL of the document
+        assert!(stdout.contains('▀'), "Stdout: {}", stdout);
+    }
+
+    #[test]
+    fn remote_document_errors() {
+        let server = serve(vec![("/README.md", b"# Remote\n".to_vec())]);
+        let url = format!("{}/README.md", server);
+        let missing = format!("{}/missing.md", server);
+        let output = cargo_mdcat()
+            .args(["--no-colour", "--no-cache", "--retries", "0"])
+            .args([&missing, &url])
+            .output()
+            .unwrap();
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        let stderr = std::str::from_utf8(&output.stderr).unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        assert!(stdout.contains("Remote"), "Stdout: {}", stdout);
+        assert!(
+            stderr.contains(&format!("Error: {}: Failed to GET", missing))
+                && stderr.contains("status code 404"),
+            "Stderr: {}",
+            stderr
+        );
+
+        let output = cargo_mdcat().args(["--local", &url]).output().unwrap();
+        let stderr = std::str::from_utf8(&output.stderr).unwrap();
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+        assert!(
+            stderr.contains("scheme http not permitted"),
+            "Stderr: {}",
+            stderr
+        );
+    }
+
+    #[test]
+    fn follow_links_to_local_documents() {
+        let index = write_config("follow", "").with_file_name("README.md");
+        let directory = index.parent().unwrap();
+        std::fs::create_dir_all(directory.join("docs")).unwrap();
+        std::fs::write(
+            &index,
+            "Read [install](docs/install.md), [usage](docs/usage.md#intro) and [web](https://example.com).\n",
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("docs").join("install.md"),
+            "Then [usage](usage.md), or [index](../README.md), or [missing](missing.md).\n",
+        )
+        .unwrap();
+        std::fs::write(directory.join("docs").join("usage.md"), "Just run it.\n").unwrap();
+        let output = cargo_mdcat()
+            .args(["--no-colour", "--follow"])
+            .arg(&index)
+            .output()
+            .unwrap();
+        std::fs::remove_dir_all(directory).unwrap();
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            stdout,
+            "┄§1 README.md
+
+Read install[§2], usage[§3] and web[1].
+
+[1]: https://example.com
+
+┄§2 docs/install.md
+
+Then usage[§3], or index[§1], or missing[1].
+
+[1]: missing.md
+
+┄§3 docs/usage.md
+
+Just run it.
+"
+        );
+    }
+
+    #[test]
+    fn table_of_contents() {
+        let document = write_config("toc", "").with_file_name("toc.md");
+        std::fs::write(
+            &document,
+            "# Title\n\nIntro\n\n## *Install*\n\n### From source\n\n# Usage\n\nText\n",
+        )
+        .unwrap();
+        let run = |flag: &str| {
+            cargo_mdcat()
+                .args(["--no-colour", flag])
+                .arg(&document)
+                .output()
+                .unwrap()
+        };
+        let toc = run("--toc");
+        let toc_only = run("--toc-only");
+        std::fs::remove_dir_all(document.parent().unwrap()).unwrap();
+        assert!(toc.status.success());
+        assert_eq!(
+            std::str::from_utf8(&toc.stdout).unwrap(),
+            "• Title
+  • Install
+    • From source
+
+• Usage
+
+┄Title
+
+Intro
+
+┄┄Install
+
+┄┄┄From source
+
+┄Usage
+
+Text
+"
+        );
+        assert!(toc_only.status.success());
+        assert_eq!(
+            std::str::from_utf8(&toc_only.stdout).unwrap(),
+            "• Title
+  • Install
+    • From source
+
+• Usage
+"
+        );
+    }
+
+    #[test]
+    fn render_only_section() {
+        let document = write_config("section", "").with_file_name("CHANGELOG.md");
+        std::fs::write(
+            &document,
+            "# Changelog\n\n## [Unreleased]\n\nfoo\n\n### Added\n\n- bar\n\n## [0.22.0] - 2020-10-25\n\nold\n",
+        )
+        .unwrap();
+        let run = |section: &str| {
+            cargo_mdcat()
+                .args(["--no-colour", "--section", section])
+                .arg(&document)
+                .output()
+                .unwrap()
+        };
+        let unreleased = run("unreleased");
+        let fuzzy = run("0.22");
+        let missing = run("Removed");
+        std::fs::remove_dir_all(document.parent().unwrap()).unwrap();
+        assert!(unreleased.status.success());
+        assert_eq!(
+            std::str::from_utf8(&unreleased.stdout).unwrap(),
+            "┄┄[Unreleased]\n\nfoo\n\n┄┄┄Added\n\n• bar\n"
+        );
+        assert!(fuzzy.status.success());
+        assert_eq!(
+            std::str::from_utf8(&fuzzy.stdout).unwrap(),
+            "┄┄[0.22.0] - 2020-10-25\n\nold\n"
+        );
+        assert!(!missing.status.success());
+        assert!(missing.stdout.is_empty());
+        let stderr = std::str::from_utf8(&missing.stderr).unwrap();
+        assert!(
+            stderr.contains(
+                "No section matches \"Removed\"; available sections:
+  Changelog (changelog)
+    [Unreleased] (unreleased)
+      Added (added)
+    [0.22.0] - 2020-10-25 (0220---2020-10-25)"
+            ),
+            "Stderr: {}",
+            stderr
+        );
+    }
+
+    #[test]
+    fn clear_cache_in_cache_home() {
+        let cache_home =
+            std::env::temp_dir().join(format!("mdcat-cache-home-{}", std::process::id()));
+        let entry = cache_home.join("mdcat").join("rendered").join("foo.png");
+        std::fs::create_dir_all(entry.parent().unwrap()).unwrap();
+        std::fs::write(&entry, b"foo").unwrap();
+        let output = cargo_mdcat()
+            .env("XDG_CACHE_HOME", &cache_home)
+            .arg("--clear-cache")
+            .output()
+            .unwrap();
+        let cleared = !cache_home.join("mdcat").exists();
+        std::fs::remove_dir_all(&cache_home).unwrap();
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+        assert!(cleared);
+    }
+
+    #[test]
+    fn missing_config_file() {
+        let output = run_cargo_mdcat(["--config", "does-not-exist.toml", "sample/common-mark.md"]);
+        let stderr = std::str::from_utf8(&output.stderr).unwrap();
+        assert!(!output.status.success());
+        assert!(
+            stderr.contains("Failed to read configuration from does-not-exist.toml"),
+            "Stderr: {}",
+            stderr
+        );
+        assert!(output.stdout.is_empty());
+    }
+
     #[test]
     fn file_list_fail_late() {
-        let output = run_cargo_mdcat(&["does-not-exist", "sample/common-mark.md"]);
+        let output = run_cargo_mdcat(["does-not-exist", "sample/common-mark.md"]);
         let stderr = std::str::from_utf8(&output.stderr).unwrap();
         let stdout = std::str::from_utf8(&output.stdout).unwrap();
         assert!(!output.status.success());
@@ -55,7 +751,7 @@ mod cli {
 
     #[test]
     fn file_list_fail_fast() {
-        let output = run_cargo_mdcat(&["--fail", "does-not-exist", "sample/common-mark.md"]);
+        let output = run_cargo_mdcat(["--fail", "does-not-exist", "sample/common-mark.md"]);
         let stderr = std::str::from_utf8(&output.stderr).unwrap();
         assert!(!output.status.success());
         // We failed to read the first file and exited early, so nothing was printed at all
@@ -85,6 +781,7 @@ mod cli {
             .unwrap()
             .read_to_end(&mut stderr)
             .unwrap();
+        child.wait().unwrap();
 
         use pretty_assertions::assert_eq;
         assert_eq!(String::from_utf8_lossy(&stderr), "")
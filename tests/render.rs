@@ -15,33 +15,50 @@ use std::path::Path;
 
 use pretty_assertions::assert_eq;
 use pulldown_cmark::{Options, Parser};
+use syntect::highlighting::{Theme, ThemeSet};
 use syntect::parsing::SyntaxSet;
 use test_generator::test_resources;
 
 use anyhow::{Context, Result};
 use lazy_static::lazy_static;
-use mdcat::Environment;
+use mdcat::{Environment, Stylesheet};
 use url::Url;
 
 lazy_static! {
     static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static ref THEME: Theme = ThemeSet::load_defaults().themes["Solarized (dark)"].clone();
     static ref SETTINGS_ANSI_ONLY: mdcat::Settings = mdcat::Settings {
         terminal_capabilities: mdcat::TerminalCapabilities::ansi(),
         terminal_size: mdcat::TerminalSize::default(),
-        resource_access: mdcat::ResourceAccess::LocalOnly,
+        resource_policy: mdcat::ResourcePolicy::local_only(),
+        cache: None,
         syntax_set: (*SYNTAX_SET).clone(),
+        theme: (*THEME).clone(),
+        stylesheet: Stylesheet::default(),
+        error_recovery: mdcat::ErrorRecovery::Fail,
+        diagnostics: None,
     };
     static ref SETTINGS_VTE50: mdcat::Settings = mdcat::Settings {
         terminal_capabilities: mdcat::TerminalCapabilities::vte50(),
         terminal_size: mdcat::TerminalSize::default(),
-        resource_access: mdcat::ResourceAccess::LocalOnly,
+        resource_policy: mdcat::ResourcePolicy::local_only(),
+        cache: None,
         syntax_set: (*SYNTAX_SET).clone(),
+        theme: (*THEME).clone(),
+        stylesheet: Stylesheet::default(),
+        error_recovery: mdcat::ErrorRecovery::Fail,
+        diagnostics: None,
     };
     static ref SETTINGS_ITERM2: mdcat::Settings = mdcat::Settings {
         terminal_capabilities: mdcat::TerminalCapabilities::iterm2(),
         terminal_size: mdcat::TerminalSize::default(),
-        resource_access: mdcat::ResourceAccess::LocalOnly,
+        resource_policy: mdcat::ResourcePolicy::local_only(),
+        cache: None,
         syntax_set: (*SYNTAX_SET).clone(),
+        theme: (*THEME).clone(),
+        stylesheet: Stylesheet::default(),
+        error_recovery: mdcat::ErrorRecovery::Fail,
+        diagnostics: None,
     };
 }
 
@@ -57,7 +74,10 @@ fn render_to_string<P: AsRef<Path>>(
     })?;
     let parser = Parser::new_ext(
         &markdown,
-        Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH,
+        Options::ENABLE_TASKLISTS
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TABLES
+            | Options::ENABLE_FOOTNOTES,
     );
     let abs_path = std::fs::canonicalize(&markdown_file).with_context(|| {
         format!(
@@ -153,14 +173,14 @@ fn ansi_only(markdown_file: &str) {
     test_with_golden_file(
         markdown_file,
         "tests/render/golden/ansi-only",
-        &*SETTINGS_ANSI_ONLY,
+        &SETTINGS_ANSI_ONLY,
     )
 }
 
 /// Test basic rendering plus inline links.
 #[test_resources("tests/render/md/*/*.md")]
 fn vte50(markdown_file: &str) {
-    test_with_golden_file(markdown_file, "tests/render/golden/vte50", &*SETTINGS_VTE50)
+    test_with_golden_file(markdown_file, "tests/render/golden/vte50", &SETTINGS_VTE50)
 }
 
 /// Test the full shebang, but not on Windows, since the iTerm2 backend has some unimplemented stuff on Windows.
@@ -170,6 +190,6 @@ fn iterm2(markdown_file: &str) {
     test_with_golden_file(
         markdown_file,
         "tests/render/golden/iterm2",
-        &*SETTINGS_ITERM2,
+        &SETTINGS_ITERM2,
     )
 }